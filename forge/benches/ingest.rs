@@ -1,8 +1,12 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
 use forge::chunking::cdc::{chunk_data, ChunkConfig};
+use forge::cli::add::stage_paths;
 use forge::store::cas::ChunkStore;
 use forge::store::compression;
-use tempfile::tempdir;
+use forge::Repository;
+use tempfile::{tempdir, TempDir};
 
 fn patterned_data(size: usize) -> Vec<u8> {
     (0..size)
@@ -10,6 +14,19 @@ fn patterned_data(size: usize) -> Vec<u8> {
         .collect()
 }
 
+/// Non-repeating but moderately compressible bytes, distinct per `seed`.
+fn unique_data(size: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 64) as u8
+        })
+        .collect()
+}
+
 fn bench_chunking(c: &mut Criterion) {
     let cfg = ChunkConfig::default();
     let mut group = c.benchmark_group("chunking");
@@ -66,5 +83,47 @@ fn bench_full_pipeline(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_chunking, bench_hash, bench_compress, bench_full_pipeline);
+/// Fresh repository holding `files` files of `size` bytes each, none of them staged.
+fn repo_with_files(files: usize, size: usize) -> (TempDir, Repository) {
+    let dir = tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let assets = repo.root.join("assets");
+    fs::create_dir_all(&assets).unwrap();
+    for i in 0..files {
+        fs::write(assets.join(format!("asset_{i:04}.bin")), unique_data(size, i as u64 + 1)).unwrap();
+    }
+    (dir, repo)
+}
+
+fn bench_add(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add");
+    group.sample_size(10);
+
+    // Many mid-sized files, and a few large ones that exercise in-file parallelism.
+    for (files, size) in [(256, 256 * 1024), (4, 32 * 1024 * 1024)] {
+        group.throughput(Throughput::Bytes((files * size) as u64));
+        group.bench_with_input(
+            BenchmarkId::new("end_to_end", format!("{files}x{}KiB", size / 1024)),
+            &(files, size),
+            |b, &(files, size)| {
+                // By reference, so the temp dir is deleted outside the timing.
+                b.iter_batched_ref(
+                    || repo_with_files(files, size),
+                    |(_, repo)| stage_paths(repo, &["assets".to_string()], false).unwrap(),
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_chunking,
+    bench_hash,
+    bench_compress,
+    bench_full_pipeline,
+    bench_add
+);
 criterion_main!(benches);
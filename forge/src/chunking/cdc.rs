@@ -22,24 +22,43 @@ pub struct ChunkResult {
     pub hash: blake3::Hash,
 }
 
+/// Lazily produced chunks; each hash is computed only when the chunk is pulled,
+/// so consumers can store chunks as they go instead of holding the whole list.
+pub type ChunkIter<'a> = Box<dyn Iterator<Item = ChunkResult> + Send + 'a>;
+
 pub fn chunk_data(data: &[u8], config: &ChunkConfig) -> Vec<ChunkResult> {
+    iter_chunks(data, 0, config).collect()
+}
+
+/// FastCDC over `data`, reporting offsets relative to `base`.
+pub fn iter_chunks<'a>(data: &'a [u8], base: usize, config: &ChunkConfig) -> ChunkIter<'a> {
     if data.is_empty() {
-        return Vec::new();
+        return Box::new(std::iter::empty());
     }
 
-    let mut out = Vec::new();
-    for chunk in fastcdc::v2020::FastCDC::new(
-        data,
-        config.min_size,
-        config.avg_size,
-        config.max_size,
-    ) {
-        let payload = &data[chunk.offset..chunk.offset + chunk.length];
-        out.push(ChunkResult {
-            offset: chunk.offset,
-            length: chunk.length,
-            hash: blake3::hash(payload),
-        });
+    Box::new(
+        fastcdc::v2020::FastCDC::new(data, config.min_size, config.avg_size, config.max_size).map(
+            move |chunk| ChunkResult {
+                offset: base + chunk.offset,
+                length: chunk.length,
+                hash: blake3::hash(&data[chunk.offset..chunk.offset + chunk.length]),
+            },
+        ),
+    )
+}
+
+/// A structural region (header, box, page) at `base`: one chunk when it fits
+/// within `max_size`, otherwise split by FastCDC so no chunk exceeds the limit.
+pub fn segment<'a>(data: &'a [u8], base: usize, config: &ChunkConfig) -> ChunkIter<'a> {
+    if data.is_empty() {
+        return Box::new(std::iter::empty());
+    }
+    if data.len() > config.max_size as usize {
+        return iter_chunks(data, base, config);
     }
-    out
+    Box::new(std::iter::once_with(move || ChunkResult {
+        offset: base,
+        length: data.len(),
+        hash: blake3::hash(data),
+    }))
 }
@@ -1,15 +1,26 @@
 pub mod cdc;
 pub mod structure_aware;
 
-use crate::chunking::cdc::{chunk_data, ChunkConfig, ChunkResult};
+use crate::chunking::cdc::{ChunkConfig, ChunkIter, ChunkResult};
 use crate::core::manifest::FileType;
 
 pub fn chunk_file(data: &[u8], file_type: FileType, config: &ChunkConfig) -> Vec<ChunkResult> {
+    chunk_stream(data, file_type, config).collect()
+}
+
+/// Chunk boundaries for `data` in file order, produced lazily so a caller
+/// working over an mmap never holds more than the chunk in hand.
+pub fn chunk_stream<'a>(data: &'a [u8], file_type: FileType, config: &ChunkConfig) -> ChunkIter<'a> {
     match file_type {
         FileType::UAsset => structure_aware::uasset::chunk_uasset(data, config),
         FileType::Mp4 => structure_aware::mp4::chunk_mp4(data, config),
         FileType::Exr => structure_aware::exr::chunk_exr(data, config),
         FileType::Csp => structure_aware::csp::chunk_csp(data, config),
-        _ => chunk_data(data, config),
+        FileType::Png => structure_aware::png::chunk_png(data, config),
+        FileType::Psd => structure_aware::psd::chunk_psd(data, config),
+        FileType::Blend => structure_aware::blend::chunk_blend(data, config),
+        FileType::Gltf => structure_aware::gltf::chunk_glb(data, config),
+        FileType::Usd => structure_aware::usd::chunk_usdc(data, config),
+        _ => cdc::iter_chunks(data, 0, config),
     }
 }
@@ -0,0 +1,140 @@
+//! Blender `.blend` files: a header, then file blocks (`BHead` plus data)
+//! up to `ENDB`. Each ID datablock (`OB`, `ME`, `MA`, ...) is followed by the
+//! `DATA` blocks it owns, so boundaries go before every block that is not
+//! `DATA`: one object, mesh or material per chunk, and an edit to one of
+//! them leaves the others' chunks alone.
+//!
+//! Compressed files (zstd since Blender 3.0, gzip before) do not start with
+//! the `BLENDER` magic and get plain CDC; their bytes must come back exactly
+//! as written, so they are not decompressed first.
+
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
+
+const MAGIC: &[u8; 7] = b"BLENDER";
+
+/// Header and block layout, read from the file header.
+struct Format {
+    header_len: usize,
+    head_len: usize,
+    /// Offset and width of the block's data length within its `BHead`.
+    len_at: usize,
+    len_width: usize,
+    little_endian: bool,
+}
+
+impl Format {
+    /// `BLENDER_v300` (pointer size `_` = 4 or `-` = 8, endianness `v`/`V`),
+    /// or since Blender 5.0 `BLENDER17-01v0500`: header length, `-`, file
+    /// format version, endianness and version, with 64-bit lengths. `None`
+    /// when the header does not fit its stated length or the file.
+    fn read(data: &[u8]) -> Option<Self> {
+        let format = Self::parse(data)?;
+        (format.header_len <= data.len()).then_some(format)
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        let rest = data.get(MAGIC.len()..)?;
+        if rest.first()?.is_ascii_digit() {
+            let header_len: usize = std::str::from_utf8(rest.get(..2)?).ok()?.parse().ok()?;
+            let format: u32 = std::str::from_utf8(rest.get(3..5)?).ok()?.parse().ok()?;
+            // The fields below, through the four-digit version.
+            if format != 1 || rest.get(2) != Some(&b'-') || header_len < MAGIC.len() + 10 {
+                return None;
+            }
+            // code, SDNA index, old pointer, length and count.
+            return Some(Self {
+                header_len,
+                head_len: 32,
+                len_at: 16,
+                len_width: 8,
+                little_endian: endianness(*rest.get(5)?)?,
+            });
+        }
+
+        let pointer = match *rest.first()? {
+            b'_' => 4,
+            b'-' => 8,
+            _ => return None,
+        };
+        // code, length, old pointer, SDNA index and count.
+        Some(Self {
+            header_len: 12,
+            head_len: 16 + pointer,
+            len_at: 4,
+            len_width: 4,
+            little_endian: endianness(*rest.get(1)?)?,
+        })
+    }
+
+    fn block_len(&self, head: &[u8]) -> Option<usize> {
+        let field = head.get(self.len_at..self.len_at + self.len_width)?;
+        let len = match (self.len_width, self.little_endian) {
+            (4, true) => i32::from_le_bytes(field.try_into().ok()?) as i64,
+            (4, false) => i32::from_be_bytes(field.try_into().ok()?) as i64,
+            (_, true) => i64::from_le_bytes(field.try_into().ok()?),
+            (_, false) => i64::from_be_bytes(field.try_into().ok()?),
+        };
+        usize::try_from(len).ok()
+    }
+}
+
+fn endianness(byte: u8) -> Option<bool> {
+    match byte {
+        b'v' => Some(true),
+        b'V' => Some(false),
+        _ => None,
+    }
+}
+
+/// Offsets where a block other than `DATA` starts, and where parsing stopped:
+/// after `ENDB`, or at the first block that does not fit.
+fn block_starts(data: &[u8], format: &Format) -> (Vec<usize>, usize) {
+    let mut starts = Vec::new();
+    let mut pos = format.header_len;
+    while let Some(head) = data.get(pos..pos + format.head_len) {
+        let code = &head[0..4];
+        if code == b"ENDB" {
+            return (starts, data.len());
+        }
+        let Some(end) = format
+            .block_len(head)
+            .and_then(|len| (pos + format.head_len).checked_add(len))
+            .filter(|&end| end <= data.len())
+        else {
+            break;
+        };
+        if code != b"DATA" {
+            starts.push(pos);
+        }
+        pos = end;
+    }
+    (starts, pos)
+}
+
+pub fn chunk_blend<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
+    let format = match data.get(..MAGIC.len()) {
+        Some(magic) if magic == MAGIC => Format::read(data),
+        _ => None,
+    };
+    let Some(format) = format else {
+        return cdc::iter_chunks(data, 0, config);
+    };
+
+    // The header rides with the first block; `ENDB` with the last.
+    let (mut starts, parsed) = block_starts(data, &format);
+    if starts.first() == Some(&format.header_len) {
+        starts[0] = 0;
+    } else {
+        starts.insert(0, 0);
+    }
+    starts.push(parsed);
+
+    let regions: Vec<(usize, usize)> = starts.windows(2).map(|w| (w[0], w[1])).collect();
+    let segment_config = config.clone();
+    Box::new(
+        regions
+            .into_iter()
+            .flat_map(move |(start, end)| cdc::segment(&data[start..end], start, &segment_config))
+            .chain(cdc::iter_chunks(&data[parsed..], parsed, config)),
+    )
+}
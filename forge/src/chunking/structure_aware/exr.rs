@@ -1,13 +1,13 @@
-use crate::chunking::cdc::{self, ChunkConfig, ChunkResult};
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
 
-pub fn chunk_exr(data: &[u8], config: &ChunkConfig) -> Vec<ChunkResult> {
+pub fn chunk_exr<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
     if data.len() < 16 {
-        return cdc::chunk_data(data, config);
+        return cdc::iter_chunks(data, 0, config);
     }
 
     let magic = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
     if magic != 0x762F3101 {
-        return cdc::chunk_data(data, config);
+        return cdc::iter_chunks(data, 0, config);
     }
 
     let mut pos = 8usize;
@@ -20,26 +20,10 @@ pub fn chunk_exr(data: &[u8], config: &ChunkConfig) -> Vec<ChunkResult> {
     }
 
     if pos >= data.len() {
-        return cdc::chunk_data(data, config);
+        return cdc::iter_chunks(data, 0, config);
     }
 
     let header_end = pos.min(data.len());
-    let mut out = Vec::new();
-    let header = &data[..header_end];
-    out.push(ChunkResult {
-        offset: 0,
-        length: header.len(),
-        hash: blake3::hash(header),
-    });
-
-    if header_end < data.len() {
-        let pixel = &data[header_end..];
-        let mut pixel_chunks = cdc::chunk_data(pixel, config);
-        for chunk in &mut pixel_chunks {
-            chunk.offset += header_end;
-        }
-        out.extend(pixel_chunks);
-    }
-
-    out
+    let (header, pixel) = data.split_at(header_end);
+    Box::new(cdc::segment(header, 0, config).chain(cdc::iter_chunks(pixel, header_end, config)))
 }
@@ -0,0 +1,102 @@
+//! Binary glTF (`.glb`): a 12-byte header, a JSON chunk describing the scene
+//! and usually a BIN chunk holding the vertex, index and image data.
+//!
+//! The header and JSON end one chunk, and the BIN chunk is cut at the start
+//! of every bufferView that points into it, so editing a material or a node
+//! only rewrites the JSON chunk and leaves the buffers' chunks alone, even
+//! when a resized view shifts the ones after it. Text `.gltf` files have no
+//! binary layout to follow and get plain CDC.
+
+use serde::Deserialize;
+
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
+
+const MAGIC: &[u8; 4] = b"glTF";
+const HEADER_LEN: usize = 12;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    #[serde(default)]
+    buffers: Vec<Buffer>,
+    #[serde(default)]
+    buffer_views: Vec<BufferView>,
+}
+
+#[derive(Deserialize)]
+struct Buffer {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos.checked_add(4)?)?.try_into().ok()?))
+}
+
+/// Region boundaries after the header: the end of the JSON chunk and BIN
+/// chunk header, each bufferView start in the BIN data, and the end of the
+/// BIN chunk. Returns them with the end of the chunks that parsed.
+fn boundaries(data: &[u8]) -> Option<(Vec<usize>, usize)> {
+    let json_len = u32_at(data, HEADER_LEN)? as usize;
+    if u32_at(data, HEADER_LEN + 4)? != CHUNK_JSON {
+        return None;
+    }
+    let json_start = HEADER_LEN + 8;
+    let json_end = json_start.checked_add(json_len).filter(|&end| end <= data.len())?;
+
+    let bin_len = match u32_at(data, json_end + 4) {
+        Some(CHUNK_BIN) => u32_at(data, json_end)? as usize,
+        _ => return Some((vec![json_end], json_end)),
+    };
+    let bin_start = json_end + 8;
+    let bin_end = bin_start.checked_add(bin_len).filter(|&end| end <= data.len())?;
+
+    // Views of buffer 0 with no uri are in the BIN chunk.
+    let mut cuts = vec![bin_start];
+    if let Ok(document) = serde_json::from_slice::<Document>(&data[json_start..json_end]) {
+        if document.buffers.first().is_some_and(|buffer| buffer.uri.is_none()) {
+            cuts.extend(
+                document
+                    .buffer_views
+                    .iter()
+                    .filter(|view| view.buffer == 0 && view.byte_offset < bin_len)
+                    .map(|view| bin_start + view.byte_offset),
+            );
+        }
+    }
+    cuts.push(bin_end);
+    cuts.sort_unstable();
+    cuts.dedup();
+    Some((cuts, bin_end))
+}
+
+pub fn chunk_glb<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
+    if data.len() < HEADER_LEN + 8 || &data[0..4] != MAGIC {
+        return cdc::iter_chunks(data, 0, config);
+    }
+    let Some((cuts, parsed)) = boundaries(data) else {
+        return cdc::iter_chunks(data, 0, config);
+    };
+
+    let regions: Vec<(usize, usize)> = std::iter::once(0)
+        .chain(cuts.iter().copied())
+        .zip(cuts.iter().copied())
+        .collect();
+    // Whatever follows the BIN chunk (extension chunks, trailing bytes).
+    let segment_config = config.clone();
+    Box::new(
+        regions
+            .into_iter()
+            .flat_map(move |(start, end)| cdc::segment(&data[start..end], start, &segment_config))
+            .chain(cdc::iter_chunks(&data[parsed..], parsed, config)),
+    )
+}
@@ -1,4 +1,9 @@
+pub mod blend;
 pub mod csp;
 pub mod exr;
+pub mod gltf;
 pub mod mp4;
+pub mod png;
+pub mod psd;
 pub mod uasset;
+pub mod usd;
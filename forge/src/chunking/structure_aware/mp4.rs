@@ -1,111 +1,344 @@
-use crate::chunking::cdc::{self, ChunkConfig, ChunkResult};
+//! MP4/MOV (ISO base media) files: a sequence of top-level boxes.
+//!
+//! Every `moov` and `moof` ends a chunk, together with any small boxes
+//! (`ftyp`, `styp`, `sidx`, `free`) in front of it, and each `mdat` payload is
+//! chunked on its own, so a fragmented recording or a file with several
+//! `mdat`s never mixes metadata into the media chunks. Inside an `mdat`, cuts
+//! fall between samples, located through the `stsz`/`stsc`/`stco` tables in
+//! `moov` or the `trun` boxes of each fragment, and are chosen from the
+//! samples' own bytes, so a re-muxed file that moves samples around still
+//! produces the same chunks for them.
 
-pub fn find_box(data: &[u8], box_type: &[u8; 4]) -> Option<(usize, usize)> {
-    let mut offset = 0usize;
-    while offset + 8 <= data.len() {
-        let size32 = u32::from_be_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]) as usize;
-        let typ = &data[offset + 4..offset + 8];
-
-        let box_size = if size32 == 0 {
-            data.len().saturating_sub(offset)
-        } else if size32 == 1 {
-            if offset + 16 > data.len() {
-                return None;
-            }
-            let size64 = u64::from_be_bytes([
-                data[offset + 8],
-                data[offset + 9],
-                data[offset + 10],
-                data[offset + 11],
-                data[offset + 12],
-                data[offset + 13],
-                data[offset + 14],
-                data[offset + 15],
-            ]) as usize;
-            size64
-        } else {
-            size32
-        };
+use std::collections::HashMap;
+
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
+
+/// Bytes at the end of a sample that decide whether a chunk ends after it.
+const CUT_WINDOW: usize = 32;
+
+struct Mp4Box {
+    kind: [u8; 4],
+    start: usize,
+    /// Start of the box's payload, after the size and type fields.
+    body: usize,
+    end: usize,
+}
 
-        if box_size < 8 || offset + box_size > data.len() {
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(pos..pos.checked_add(8)?)?.try_into().ok()?))
+}
+
+/// Boxes laid end to end in `data[start..end]`, up to the first that does not fit.
+fn boxes(data: &[u8], start: usize, end: usize) -> impl Iterator<Item = Mp4Box> + '_ {
+    let mut pos = start;
+    std::iter::from_fn(move || {
+        let size32 = u32_at(data, pos)? as usize;
+        let kind: [u8; 4] = data.get(pos + 4..pos + 8)?.try_into().ok()?;
+        if !kind.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
             return None;
         }
+        let (size, header) = match size32 {
+            0 => (end - pos, 8),
+            1 => (usize::try_from(u64_at(data, pos + 8)?).ok()?, 16),
+            size => (size, 8),
+        };
+        let box_end = pos.checked_add(size).filter(|&e| size >= header && e <= end)?;
+        let found = Mp4Box {
+            kind,
+            start: pos,
+            body: pos + header,
+            end: box_end,
+        };
+        pos = box_end;
+        Some(found)
+    })
+}
 
-        if typ == box_type {
-            return Some((offset, box_size));
-        }
+fn children<'a>(data: &'a [u8], parent: &Mp4Box, kind: &'a [u8; 4]) -> impl Iterator<Item = Mp4Box> + 'a {
+    boxes(data, parent.body, parent.end).filter(move |b| &b.kind == kind)
+}
 
-        offset += box_size;
-    }
+fn child(data: &[u8], parent: &Mp4Box, kind: &[u8; 4]) -> Option<Mp4Box> {
+    children(data, parent, kind).next()
+}
 
-    None
+/// Offset and size of the first top-level box of `box_type`.
+pub fn find_box(data: &[u8], box_type: &[u8; 4]) -> Option<(usize, usize)> {
+    boxes(data, 0, data.len())
+        .find(|b| &b.kind == box_type)
+        .map(|b| (b.start, b.end - b.start))
 }
 
-pub fn chunk_mp4(data: &[u8], config: &ChunkConfig) -> Vec<ChunkResult> {
-    if data.len() < 16 {
-        return cdc::chunk_data(data, config);
+/// Sample byte ranges of every track in `moov`, and the `trex` default sample
+/// sizes fragments fall back on. A track whose tables do not parse is skipped.
+fn moov_samples(data: &[u8], moov: &Mp4Box, samples: &mut Vec<(usize, usize)>) -> HashMap<u32, u32> {
+    for trak in children(data, moov, b"trak") {
+        let stbl = child(data, &trak, b"mdia")
+            .and_then(|mdia| child(data, &mdia, b"minf"))
+            .and_then(|minf| child(data, &minf, b"stbl"));
+        if let Some(stbl) = stbl {
+            let _ = track_samples(data, &stbl, samples);
+        }
     }
 
-    let Some((mdat_offset, mdat_size)) = find_box(data, b"mdat") else {
-        return cdc::chunk_data(data, config);
-    };
+    let mut trex = HashMap::new();
+    if let Some(mvex) = child(data, moov, b"mvex") {
+        for entry in children(data, &mvex, b"trex") {
+            if let (Some(track), Some(size)) = (u32_at(data, entry.body + 4), u32_at(data, entry.body + 16)) {
+                trex.insert(track, size);
+            }
+        }
+    }
+    trex
+}
 
-    if mdat_size < 8 {
-        return cdc::chunk_data(data, config);
+fn track_samples(data: &[u8], stbl: &Mp4Box, samples: &mut Vec<(usize, usize)>) -> Option<()> {
+    let stsz = child(data, stbl, b"stsz")?;
+    let fixed_size = u32_at(data, stsz.body + 4)? as usize;
+    let count = u32_at(data, stsz.body + 8)? as usize;
+    if count > data.len() {
+        return None;
     }
 
-    let header_size = if u32::from_be_bytes([
-        data[mdat_offset],
-        data[mdat_offset + 1],
-        data[mdat_offset + 2],
-        data[mdat_offset + 3],
-    ]) == 1
-    {
-        16
+    let offsets: Vec<usize> = if let Some(stco) = child(data, stbl, b"stco") {
+        let n = u32_at(data, stco.body + 4)? as usize;
+        (0..n)
+            .map(|i| u32_at(data, stco.body + 8 + 4 * i).map(|o| o as usize))
+            .collect::<Option<_>>()?
     } else {
-        8
+        let co64 = child(data, stbl, b"co64")?;
+        let n = u32_at(data, co64.body + 4)? as usize;
+        (0..n)
+            .map(|i| u64_at(data, co64.body + 8 + 8 * i).and_then(|o| usize::try_from(o).ok()))
+            .collect::<Option<_>>()?
     };
 
-    if mdat_offset + header_size > data.len() {
-        return cdc::chunk_data(data, config);
+    // (first chunk, samples per chunk), chunks numbered from 1.
+    let stsc = child(data, stbl, b"stsc")?;
+    let runs = u32_at(data, stsc.body + 4)? as usize;
+    let runs: Vec<(usize, usize)> = (0..runs)
+        .map(|i| {
+            let entry = stsc.body + 8 + 12 * i;
+            Some((u32_at(data, entry)? as usize, u32_at(data, entry + 4)? as usize))
+        })
+        .collect::<Option<_>>()?;
+
+    let mut sample = 0;
+    let mut run = 0;
+    for (index, &offset) in offsets.iter().enumerate() {
+        while run + 1 < runs.len() && runs[run + 1].0 <= index + 1 {
+            run += 1;
+        }
+        let per_chunk = runs.get(run)?.1.min(count - sample);
+        if fixed_size != 0 {
+            // Constant-size samples (PCM audio) are kept as one run per chunk.
+            let len = per_chunk.checked_mul(fixed_size)?;
+            samples.push((offset, offset.checked_add(len)?));
+        } else {
+            let mut pos = offset;
+            for i in sample..sample + per_chunk {
+                let size = u32_at(data, stsz.body + 12 + 4 * i)? as usize;
+                samples.push((pos, pos.checked_add(size)?));
+                pos += size;
+            }
+        }
+        sample += per_chunk;
+        if sample == count {
+            break;
+        }
     }
+    Some(())
+}
 
-    let mdat_payload_offset = mdat_offset + header_size;
-    let mdat_payload_end = (mdat_offset + mdat_size).min(data.len());
-    if mdat_payload_offset >= mdat_payload_end {
-        return cdc::chunk_data(data, config);
+/// Sample byte ranges of every `trun` in a movie fragment.
+fn moof_samples(data: &[u8], moof: &Mp4Box, trex: &HashMap<u32, u32>, samples: &mut Vec<(usize, usize)>) -> Option<()> {
+    let mut previous_end = moof.start;
+    for (index, traf) in children(data, moof, b"traf").enumerate() {
+        let tfhd = child(data, &traf, b"tfhd")?;
+        let flags = u32_at(data, tfhd.body)? & 0x00FF_FFFF;
+        let track = u32_at(data, tfhd.body + 4)?;
+        let mut pos = tfhd.body + 8;
+        let base = if flags & 0x01 != 0 {
+            pos += 8;
+            usize::try_from(u64_at(data, pos - 8)?).ok()?
+        } else if flags & 0x02_0000 != 0 || index == 0 {
+            moof.start
+        } else {
+            previous_end
+        };
+        // Sample description index and default duration precede the size.
+        pos += 4 * ((flags & 0x02 != 0) as usize + (flags & 0x08 != 0) as usize);
+        let default_size = if flags & 0x10 != 0 {
+            u32_at(data, pos)
+        } else {
+            trex.get(&track).copied()
+        };
+
+        let mut data_pos = base;
+        for trun in children(data, &traf, b"trun") {
+            let flags = u32_at(data, trun.body)? & 0x00FF_FFFF;
+            let count = u32_at(data, trun.body + 4)? as usize;
+            if count > data.len() {
+                return None;
+            }
+            let mut pos = trun.body + 8;
+            if flags & 0x01 != 0 {
+                let offset = u32_at(data, pos)? as i32 as i64;
+                data_pos = usize::try_from(base as i64 + offset).ok()?;
+                pos += 4;
+            }
+            if flags & 0x04 != 0 {
+                pos += 4;
+            }
+            for _ in 0..count {
+                if flags & 0x100 != 0 {
+                    pos += 4;
+                }
+                let size = if flags & 0x200 != 0 {
+                    pos += 4;
+                    u32_at(data, pos - 4)?
+                } else {
+                    default_size?
+                } as usize;
+                pos += 4 * ((flags & 0x400 != 0) as usize + (flags & 0x800 != 0) as usize);
+                samples.push((data_pos, data_pos.checked_add(size)?));
+                data_pos += size;
+            }
+        }
+        previous_end = data_pos;
     }
+    Some(())
+}
 
-    let mut out = Vec::new();
-    if mdat_offset > 0 {
-        let metadata = &data[..mdat_offset];
-        out.push(ChunkResult {
-            offset: 0,
-            length: metadata.len(),
-            hash: blake3::hash(metadata),
-        });
+enum Kind {
+    /// Boxes other than media data, kept whole.
+    Boxes,
+    /// Whole samples grouped into one chunk.
+    Samples,
+    /// Media data with no usable sample boundaries, left to FastCDC.
+    Stream,
+}
+
+/// Whether a chunk may end after `sample`: about one cut per `avg_size`
+/// bytes, decided by the sample's last bytes.
+fn is_cut(sample: &[u8], avg_size: u32) -> bool {
+    let tail = &sample[sample.len().saturating_sub(CUT_WINDOW)..];
+    let hash = blake3::hash(tail);
+    let value = u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("slice of 8 bytes"));
+    value % (avg_size.max(1) as u64) < sample.len() as u64
+}
+
+/// Split the `mdat` payload `[start, end)` at the sample boundaries `cuts`
+/// (sorted, strictly inside), keeping chunks between `min_size` and `max_size`
+/// where the samples allow; a sample larger than `max_size` is left to FastCDC.
+fn group_samples(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    cuts: &[usize],
+    config: &ChunkConfig,
+    regions: &mut Vec<(usize, usize, Kind)>,
+) {
+    let (min_size, max_size) = (config.min_size as usize, config.max_size as usize);
+    let mut group = start;
+    let mut previous = start;
+    for &boundary in cuts.iter().chain(std::iter::once(&end)) {
+        if boundary - previous > max_size {
+            if previous > group {
+                regions.push((group, previous, Kind::Samples));
+            }
+            regions.push((previous, boundary, Kind::Stream));
+            group = boundary;
+        } else {
+            if boundary - group > max_size {
+                regions.push((group, previous, Kind::Samples));
+                group = previous;
+            }
+            if boundary - group >= min_size && is_cut(&data[previous..boundary], config.avg_size) {
+                regions.push((group, boundary, Kind::Samples));
+                group = boundary;
+            }
+        }
+        previous = boundary;
+    }
+    if end > group {
+        regions.push((group, end, Kind::Samples));
     }
+}
 
-    let payload = &data[mdat_payload_offset..mdat_payload_end];
-    let mut payload_chunks = cdc::chunk_data(payload, config);
-    for chunk in &mut payload_chunks {
-        chunk.offset += mdat_payload_offset;
+/// Add `[start, end)` of non-media boxes, joining the region before it when
+/// that one is boxes too.
+fn push_boxes(regions: &mut Vec<(usize, usize, Kind)>, start: usize, end: usize) {
+    if start == end {
+        return;
+    }
+    match regions.last_mut() {
+        Some((_, last_end, Kind::Boxes)) if *last_end == start => *last_end = end,
+        _ => regions.push((start, end, Kind::Boxes)),
+    }
+}
+
+pub fn chunk_mp4<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
+    if data.len() < 16 {
+        return cdc::iter_chunks(data, 0, config);
     }
-    out.extend(payload_chunks);
-
-    if mdat_payload_end < data.len() {
-        let tail = &data[mdat_payload_end..];
-        out.push(ChunkResult {
-            offset: mdat_payload_end,
-            length: tail.len(),
-            hash: blake3::hash(tail),
-        });
+    let top: Vec<Mp4Box> = boxes(data, 0, data.len()).collect();
+    if !top.iter().any(|b| &b.kind == b"mdat") {
+        return cdc::iter_chunks(data, 0, config);
+    }
+
+    let mut samples = Vec::new();
+    let mut trex = HashMap::new();
+    for moov in top.iter().filter(|b| &b.kind == b"moov") {
+        trex.extend(moov_samples(data, moov, &mut samples));
+    }
+    for moof in top.iter().filter(|b| &b.kind == b"moof") {
+        let _ = moof_samples(data, moof, &trex, &mut samples);
+    }
+    let mut boundaries: Vec<usize> = samples.iter().flat_map(|&(start, end)| [start, end]).collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    // Small boxes ride with the next `moov`/`moof`, and an `mdat` header
+    // with the boxes before it.
+    let mut regions = Vec::new();
+    let mut cut = 0;
+    for b in &top {
+        match &b.kind {
+            b"moov" | b"moof" => {
+                push_boxes(&mut regions, cut, b.end);
+                cut = b.end;
+            }
+            b"mdat" => {
+                push_boxes(&mut regions, cut, b.body);
+                let lo = boundaries.partition_point(|&at| at <= b.body);
+                let hi = boundaries.partition_point(|&at| at < b.end);
+                if hi > lo {
+                    group_samples(data, b.body, b.end, &boundaries[lo..hi], config, &mut regions);
+                } else if b.end > b.body {
+                    regions.push((b.body, b.end, Kind::Stream));
+                }
+                cut = b.end;
+            }
+            _ => {}
+        }
     }
+    let parsed = top.last().map_or(0, |b| b.end);
+    push_boxes(&mut regions, cut, parsed);
 
-    out
+    // Whatever follows the last box that parsed.
+    let segment_config = config.clone();
+    Box::new(
+        regions
+            .into_iter()
+            .flat_map(move |(start, end, kind)| match kind {
+                Kind::Stream => cdc::iter_chunks(&data[start..end], start, &segment_config),
+                Kind::Boxes | Kind::Samples => cdc::segment(&data[start..end], start, &segment_config),
+            })
+            .chain(cdc::iter_chunks(&data[parsed..], parsed, config)),
+    )
 }
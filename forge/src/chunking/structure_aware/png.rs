@@ -0,0 +1,214 @@
+//! PNG images: the signature, then chunks (length, type, data, CRC) up to
+//! `IEND`. Every chunk other than image data gets its own boundaries, so a
+//! tool rewriting a `tEXt` timestamp or an `iCCP` profile leaves the image
+//! data's chunks alone. Each run of `IDAT` chunks (or an APNG frame's `fdAT`
+//! chunks) is one zlib stream and gets its own FastCDC pass.
+
+use std::io::Read;
+use std::ops::Range;
+
+use flate2::read::ZlibDecoder;
+
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Most bytes deflate can expand one compressed byte into.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// Adam7 passes: starting column and row, then column and row steps.
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Chunk {
+    kind: [u8; 4],
+    body: Range<usize>,
+    end: usize,
+}
+
+impl Chunk {
+    fn is_image_data(&self) -> bool {
+        matches!(&self.kind, b"IDAT" | b"fdAT")
+    }
+}
+
+/// The chunks of a PNG in file order, through `IEND` or up to the first one
+/// that does not fit.
+fn chunks(data: &[u8]) -> impl Iterator<Item = Chunk> + '_ {
+    let mut pos = SIGNATURE.len();
+    let mut done = !data.starts_with(SIGNATURE);
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let head = data.get(pos..pos + 8)?;
+        let len = u32::from_be_bytes(head[0..4].try_into().ok()?) as usize;
+        let kind: [u8; 4] = head[4..8].try_into().ok()?;
+        let body = pos + 8..(pos + 8).checked_add(len)?;
+        let end = body.end.checked_add(4).filter(|&end| end <= data.len())?;
+        let chunk = Chunk {
+            kind,
+            body,
+            end,
+        };
+        done = &kind == b"IEND";
+        pos = end;
+        Some(chunk)
+    })
+}
+
+pub fn chunk_png<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
+    if !data.starts_with(SIGNATURE) {
+        return cdc::iter_chunks(data, 0, config);
+    }
+
+    // (start, end, image data); the signature rides with `IHDR`.
+    let mut regions: Vec<(usize, usize, bool)> = Vec::new();
+    let mut parsed = 0;
+    let mut previous = None;
+    for chunk in chunks(data) {
+        let image_data = chunk.is_image_data();
+        match regions.last_mut() {
+            Some((_, end, true)) if image_data && previous == Some(chunk.kind) => *end = chunk.end,
+            _ => regions.push((parsed, chunk.end, image_data)),
+        }
+        previous = Some(chunk.kind);
+        parsed = chunk.end;
+    }
+
+    // Whatever follows `IEND`, or the chunk that did not fit.
+    let segment_config = config.clone();
+    Box::new(
+        regions
+            .into_iter()
+            .flat_map(move |(start, end, image_data)| {
+                if image_data {
+                    cdc::iter_chunks(&data[start..end], start, &segment_config)
+                } else {
+                    cdc::segment(&data[start..end], start, &segment_config)
+                }
+            })
+            .chain(cdc::iter_chunks(&data[parsed..], parsed, config)),
+    )
+}
+
+/// Hash of the decoded image: header fields, palette, transparency and the
+/// unfiltered scanlines. Two files that differ only in their zlib or filter
+/// choices, or in ancillary chunks, hash the same. `None` when `data` is not
+/// a PNG this can decode.
+pub fn pixel_hash(data: &[u8]) -> Option<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut header = None;
+    let mut compressed = Vec::new();
+    for chunk in chunks(data) {
+        let body = &data[chunk.body.clone()];
+        match &chunk.kind {
+            b"IHDR" => header = Some(body.get(..13)?),
+            b"PLTE" | b"tRNS" => {
+                hasher.update(&chunk.kind);
+                hasher.update(&(body.len() as u32).to_be_bytes());
+                hasher.update(body);
+            }
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    // Width, height, bit depth, color type; compression and filter method
+    // are always 0 and the interlace method shapes the rows below.
+    let header = header?;
+    hasher.update(&header[..10]);
+    hasher.update(&header[12..13]);
+    let width = u32::from_be_bytes(header[0..4].try_into().ok()?);
+    let height = u32::from_be_bytes(header[4..8].try_into().ok()?);
+    let depth = header[8] as usize;
+    let channels = match header[9] {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => return None,
+    };
+    let bits = channels * depth;
+    let bpp = bits.div_ceil(8);
+    let passes: &[_] = match header[12] {
+        0 => &[(0, 0, 1, 1)],
+        1 => &ADAM7,
+        _ => return None,
+    };
+
+    // (columns, rows, row length) per pass, refusing a header that asks for
+    // more scanline bytes than the image data could inflate to.
+    let mut layout = Vec::new();
+    let mut total = 0usize;
+    for &(x0, y0, dx, dy) in passes {
+        let columns = width.saturating_sub(x0).div_ceil(dx) as usize;
+        let rows = height.saturating_sub(y0).div_ceil(dy) as usize;
+        if columns == 0 {
+            continue;
+        }
+        let row_len = columns.checked_mul(bits)?.div_ceil(8);
+        total = row_len.checked_add(1)?.checked_mul(rows)?.checked_add(total)?;
+        layout.push((columns, rows, row_len));
+    }
+    if total > compressed.len().saturating_mul(MAX_DEFLATE_RATIO) {
+        return None;
+    }
+
+    let mut decoder = ZlibDecoder::new(compressed.as_slice());
+    for (columns, rows, row_len) in layout {
+        let mut previous = vec![0u8; row_len];
+        let mut row = vec![0u8; 1 + row_len];
+        for _ in 0..rows {
+            decoder.read_exact(&mut row).ok()?;
+            let (filter, current) = row.split_first_mut()?;
+            unfilter(*filter, current, &previous, bpp)?;
+            // Bits past the last pixel are unspecified.
+            let used = (columns * bits) % 8;
+            if used != 0 {
+                current[row_len - 1] &= 0xFF << (8 - used);
+            }
+            hasher.update(current);
+            previous.copy_from_slice(current);
+        }
+    }
+    Some(hasher.finalize())
+}
+
+fn unfilter(filter: u8, current: &mut [u8], previous: &[u8], bpp: usize) -> Option<()> {
+    for i in 0..current.len() {
+        let left = if i >= bpp { current[i - bpp] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return None,
+        };
+        current[i] = current[i].wrapping_add(predicted);
+    }
+    Some(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
@@ -0,0 +1,196 @@
+//! Photoshop documents (PSD, and PSB with its 64-bit lengths).
+//!
+//! The header and color mode data, the image resources, every layer record
+//! and every channel's pixel data each get their own boundaries, so editing
+//! one layer only produces new chunks for that layer, the section lengths in
+//! front of it, and the merged image at the end, which is left to FastCDC.
+
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
+
+const SIGNATURE: &[u8; 4] = b"8BPS";
+const HEADER_LEN: usize = 26;
+
+/// Tagged blocks whose length is 64-bit in a PSB.
+const LONG_BLOCKS: [&[u8; 4]; 13] = [
+    b"LMsk", b"Lr16", b"Lr32", b"Layr", b"Mt16", b"Mt32", b"Mtrn", b"Alph", b"FMsk", b"lnk2", b"FEid",
+    b"FXid", b"PxSD",
+];
+
+/// Structural regions found so far, in file order and back to back; each is
+/// kept as one chunk when it fits.
+struct Layout<'a> {
+    data: &'a [u8],
+    psb: bool,
+    regions: Vec<(usize, usize)>,
+    /// End of the last region.
+    cut: usize,
+}
+
+impl<'a> Layout<'a> {
+    fn bytes(&self, pos: usize, len: usize) -> Option<&'a [u8]> {
+        self.data.get(pos..pos.checked_add(len)?)
+    }
+
+    fn u16(&self, pos: usize) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(pos, 2)?.try_into().ok()?))
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(pos, 4)?.try_into().ok()?))
+    }
+
+    fn u64(&self, pos: usize) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(pos, 8)?.try_into().ok()?))
+    }
+
+    /// A section or channel length: 32-bit in a PSD, 64-bit in a PSB when `wide`.
+    /// Returns the value and the width of the field.
+    fn length(&self, pos: usize, wide: bool) -> Option<(usize, usize)> {
+        if self.psb && wide {
+            Some((usize::try_from(self.u64(pos)?).ok()?, 8))
+        } else {
+            Some((self.u32(pos)? as usize, 4))
+        }
+    }
+
+    /// End the current region at `end`.
+    fn cut_at(&mut self, end: usize) -> Option<()> {
+        if end < self.cut || end > self.data.len() {
+            return None;
+        }
+        if end > self.cut {
+            self.regions.push((self.cut, end));
+            self.cut = end;
+        }
+        Some(())
+    }
+
+    /// `[start, end)` must hold a whole layer info block: the layer count,
+    /// the records, then each layer's channels in record order. Section
+    /// length fields and the count ride along with the first record.
+    fn layer_info(&mut self, start: usize, end: usize) -> Option<()> {
+        let count = (self.u16(start)? as i16).unsigned_abs() as usize;
+        let mut pos = start + 2;
+        let mut channels: Vec<Vec<usize>> = Vec::with_capacity(count);
+        for _ in 0..count {
+            // Bounding box (four i32s), then the channel count.
+            let channel_count = self.u16(pos + 16)? as usize;
+            pos += 18;
+            let mut lengths = Vec::with_capacity(channel_count);
+            for _ in 0..channel_count {
+                let (len, width) = self.length(pos + 2, true)?;
+                lengths.push(len);
+                pos += 2 + width;
+            }
+            if self.bytes(pos, 4)? != b"8BIM" {
+                return None;
+            }
+            // Blend mode signature and key, opacity, clipping, flags, filler.
+            pos += 12;
+            let extra = self.u32(pos)? as usize;
+            pos = pos.checked_add(4 + extra)?;
+            if pos > end {
+                return None;
+            }
+            self.cut_at(pos)?;
+            channels.push(lengths);
+        }
+
+        for len in channels.into_iter().flatten() {
+            pos = pos.checked_add(len)?;
+            if pos > end {
+                return None;
+            }
+            self.cut_at(pos)?;
+        }
+        Some(())
+    }
+
+    /// Additional layer information in `[pos, end)`. 16- and 32-bit documents
+    /// keep their layers in `Lr16`/`Lr32` blocks here instead of the layer
+    /// info block.
+    fn tagged_blocks(&mut self, mut pos: usize, end: usize) -> Option<()> {
+        while pos + 12 <= end {
+            // Writers differ on whether block lengths include padding to
+            // two or four bytes, so look a few bytes ahead for the next one.
+            pos = (pos..pos + 4).find(|&at| matches!(self.bytes(at, 4), Some(b"8BIM" | b"8B64")))?;
+            let key: &[u8; 4] = self.bytes(pos + 4, 4)?.try_into().ok()?;
+            let (len, width) = self.length(pos + 8, LONG_BLOCKS.contains(&key))?;
+            let body = pos + 8 + width;
+            let body_end = body.checked_add(len)?;
+            if body_end > end {
+                return None;
+            }
+            if matches!(key, b"Lr16" | b"Lr32" | b"Layr") && len > 0 {
+                self.cut_at(body)?;
+                self.layer_info(body, body_end)?;
+            }
+            pos = body_end;
+        }
+        Some(())
+    }
+
+    /// Walk the sections, recording regions; `None` where parsing gives up.
+    fn parse(&mut self) -> Option<()> {
+        let color_mode = self.u32(HEADER_LEN)? as usize;
+        let resources = HEADER_LEN.checked_add(4 + color_mode)?;
+        self.cut_at(resources)?;
+        let resources_len = self.u32(resources)? as usize;
+        let layers = resources.checked_add(4 + resources_len)?;
+        self.cut_at(layers)?;
+
+        let (section_len, width) = self.length(layers, true)?;
+        let section = layers + width;
+        let section_end = section.checked_add(section_len)?;
+        if section_end > self.data.len() {
+            return None;
+        }
+        if section_len > 0 {
+            let (info_len, width) = self.length(section, true)?;
+            let info = section + width;
+            let info_end = info.checked_add(info_len)?;
+            if info_end > section_end {
+                return None;
+            }
+            if info_len > 0 {
+                self.layer_info(info, info_end)?;
+            }
+            // Padding, the global layer mask and the tagged blocks.
+            let mask_len = self.u32(info_end)? as usize;
+            let blocks = info_end.checked_add(4 + mask_len)?;
+            // Blocks that fail to parse stay part of the section's last chunk.
+            let _ = self.tagged_blocks(blocks, section_end);
+        }
+        self.cut_at(section_end)
+    }
+}
+
+pub fn chunk_psd<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
+    if data.len() < HEADER_LEN || &data[0..4] != SIGNATURE {
+        return cdc::iter_chunks(data, 0, config);
+    }
+    let psb = match u16::from_be_bytes([data[4], data[5]]) {
+        1 => false,
+        2 => true,
+        _ => return cdc::iter_chunks(data, 0, config),
+    };
+
+    let mut layout = Layout {
+        data,
+        psb,
+        regions: Vec::new(),
+        cut: 0,
+    };
+    let _ = layout.parse();
+    let cut = layout.cut;
+
+    // Whatever follows the last region parsed, normally the merged image.
+    let segment_config = config.clone();
+    Box::new(
+        layout
+            .regions
+            .into_iter()
+            .flat_map(move |(start, end)| cdc::segment(&data[start..end], start, &segment_config))
+            .chain(cdc::iter_chunks(&data[cut..], cut, config)),
+    )
+}
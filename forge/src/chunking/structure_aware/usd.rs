@@ -0,0 +1,69 @@
+//! USD crate files (`.usdc`): an 88-byte bootstrap (`PXR-USDC`, version,
+//! table of contents offset), the out-of-line values such as point and
+//! normal arrays, then the structural sections (`TOKENS`, `STRINGS`,
+//! `FIELDS`, `FIELDSETS`, `PATHS`, `SPECS`) and finally the table of contents
+//! listing them.
+//!
+//! The bootstrap, the value data, each section and the table of contents are
+//! chunked separately, so a material edit that rewrites tokens and fields
+//! leaves the vertex data's chunks alone. Text `.usda` files get plain CDC.
+
+use crate::chunking::cdc::{self, ChunkConfig, ChunkIter};
+
+const MAGIC: &[u8; 8] = b"PXR-USDC";
+const BOOTSTRAP_LEN: usize = 88;
+/// Section name (15 characters and a NUL), start and size.
+const SECTION_LEN: usize = 32;
+
+fn u64_at(data: &[u8], pos: usize) -> Option<usize> {
+    let value = u64::from_le_bytes(data.get(pos..pos.checked_add(8)?)?.try_into().ok()?);
+    usize::try_from(value).ok()
+}
+
+/// Offsets where regions start after the bootstrap: the value data, each
+/// section, the table of contents, and the end of whichever comes last.
+fn boundaries(data: &[u8]) -> Option<Vec<usize>> {
+    let toc = u64_at(data, 16)?;
+    let count = u64_at(data, toc)?;
+    let toc_end = count
+        .checked_mul(SECTION_LEN)
+        .and_then(|len| (toc + 8).checked_add(len))
+        .filter(|&end| toc >= BOOTSTRAP_LEN && end <= data.len())?;
+
+    let mut cuts = vec![BOOTSTRAP_LEN, toc, toc_end];
+    for i in 0..count {
+        let entry = toc + 8 + i * SECTION_LEN;
+        let start = u64_at(data, entry + 16)?;
+        let end = start.checked_add(u64_at(data, entry + 24)?)?;
+        if start < BOOTSTRAP_LEN || end > data.len() {
+            return None;
+        }
+        cuts.extend([start, end]);
+    }
+    cuts.sort_unstable();
+    cuts.dedup();
+    Some(cuts)
+}
+
+pub fn chunk_usdc<'a>(data: &'a [u8], config: &ChunkConfig) -> ChunkIter<'a> {
+    if data.len() < BOOTSTRAP_LEN || &data[0..8] != MAGIC {
+        return cdc::iter_chunks(data, 0, config);
+    }
+    let Some(cuts) = boundaries(data) else {
+        return cdc::iter_chunks(data, 0, config);
+    };
+    let parsed = cuts.last().copied().unwrap_or(BOOTSTRAP_LEN);
+
+    let regions: Vec<(usize, usize)> = std::iter::once(0)
+        .chain(cuts.iter().copied())
+        .zip(cuts.iter().copied())
+        .collect();
+    // Whatever follows the last section or the table of contents.
+    let segment_config = config.clone();
+    Box::new(
+        regions
+            .into_iter()
+            .flat_map(move |(start, end)| cdc::segment(&data[start..end], start, &segment_config))
+            .chain(cdc::iter_chunks(&data[parsed..], parsed, config)),
+    )
+}
@@ -1,32 +1,76 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-use anyhow::{Context, Result};
-use memmap2::Mmap;
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{bounded, unbounded, Sender};
+use memmap2::{Advice, Mmap};
 use walkdir::WalkDir;
 
-use crate::chunking::cdc::ChunkConfig;
-use crate::chunking::chunk_file;
-use crate::core::hash::hash_bytes;
+use crate::chunking::cdc::{ChunkConfig, ChunkResult};
+use crate::chunking::chunk_stream;
 use crate::core::manifest::{serialize_file_entry, ChunkRef, FileEntry, FileType};
 use crate::core::repository::Repository;
+use crate::core::worktree::record_stats;
 use crate::db::metadata::MetadataDb;
 use crate::store::cas::ChunkStore;
-use crate::store::compression;
 use crate::util::human::human_bytes;
 use crate::util::ignore::ForgeIgnore;
 use crate::util::progress::create_progress_bar;
 
-fn read_file_bytes(path: &Path) -> Result<Vec<u8>> {
-    let metadata = fs::metadata(path).with_context(|| format!("stat {}", path.display()))?;
-    if metadata.len() > 4 * 1024 * 1024 {
+/// Size above which files are mapped rather than read into memory.
+const MMAP_THRESHOLD: u64 = 4 * 1024 * 1024;
+/// How much already-ingested mapping to accumulate before handing pages back.
+const RELEASE_WINDOW: usize = 64 * 1024 * 1024;
+
+enum FileBytes {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl FileBytes {
+    fn open(path: &Path, len: u64) -> Result<Self> {
+        if len <= MMAP_THRESHOLD {
+            return Ok(Self::Owned(
+                fs::read(path).with_context(|| format!("read {}", path.display()))?,
+            ));
+        }
         let file = fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
         let mapped = unsafe { Mmap::map(&file) }.with_context(|| format!("mmap {}", path.display()))?;
-        Ok(mapped.to_vec())
-    } else {
-        fs::read(path).with_context(|| format!("read {}", path.display()))
+        #[cfg(unix)]
+        let _ = mapped.advise(Advice::Sequential);
+        Ok(Self::Mapped(mapped))
     }
+
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes,
+            Self::Mapped(mapped) => mapped,
+        }
+    }
+
+    /// Drop resident pages of `[from, to)` so a huge file's ingest does not
+    /// grow the page-cache footprint of this process without bound.
+    #[cfg(unix)]
+    fn release(&self, from: usize, to: usize) {
+        if let Self::Mapped(mapped) = self {
+            let page = 4096;
+            let start = from / page * page;
+            let end = to / page * page;
+            if end > start {
+                // SAFETY: the mapping is read-only and file-backed; released pages
+                // are faulted back in from the file if touched again.
+                let _ = unsafe {
+                    mapped.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, start, end - start)
+                };
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn release(&self, _from: usize, _to: usize) {}
 }
 
 fn gather_files(paths: &[String], root: &Path, ignore: &ForgeIgnore, force: bool) -> Vec<PathBuf> {
@@ -87,11 +131,171 @@ fn mode(_meta: &fs::Metadata) -> u32 {
     0
 }
 
-pub fn run(paths: &[String], force: bool) -> Result<()> {
-    let cwd = std::env::current_dir().context("get current dir")?;
-    let repo = Repository::discover(&cwd)?;
+/// Chunks queued between the readers and the compressors, per worker.
+const QUEUE_DEPTH: usize = 4;
+/// Files staged per metadata transaction.
+const BATCH_FILES: usize = 256;
+
+/// One chunk cut by a reader, waiting to be hashed-checked, compressed and stored.
+struct ChunkJob {
+    file: usize,
+    seq: usize,
+    contents: Arc<FileBytes>,
+    chunk: ChunkResult,
+    dict_id: Option<u32>,
+}
+
+enum Event {
+    Chunk {
+        file: usize,
+        seq: usize,
+        chunk: ChunkRef,
+        new: bool,
+    },
+    /// Sent once a reader has cut every chunk of a file; `entry.chunks` is empty
+    /// and `meta` is the stat taken before the file was read.
+    FileDone {
+        file: usize,
+        chunks: usize,
+        entry: FileEntry,
+        meta: Box<fs::Metadata>,
+    },
+    Failed(anyhow::Error),
+}
+
+#[derive(Default)]
+struct PendingFile {
+    chunks: Vec<Option<ChunkRef>>,
+    received: usize,
+    done: Option<(usize, FileEntry, fs::Metadata)>,
+}
+
+impl PendingFile {
+    /// The finished entry once the reader is done and every chunk has come back.
+    fn take_if_complete(&mut self) -> Option<(FileEntry, fs::Metadata)> {
+        let (expected, _, _) = self.done.as_ref()?;
+        if self.received != *expected {
+            return None;
+        }
+        let (_, mut entry, meta) = self.done.take()?;
+        entry.chunks = self.chunks.drain(..).map(|c| c.expect("all chunks received")).collect();
+        Some((entry, meta))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AddStats {
+    pub staged_files: usize,
+    pub new_chunks: usize,
+    pub deduped_chunks: usize,
+    pub new_chunk_bytes: u64,
+    pub dedup_saved_bytes: u64,
+}
+
+/// Reader stage: map a file and cut it into chunks, feeding the compressors.
+fn read_file(
+    repo: &Repository,
+    store: &ChunkStore,
+    chunk_cfg: &ChunkConfig,
+    file: usize,
+    path: &Path,
+    jobs: &Sender<ChunkJob>,
+) -> Result<Event> {
+    let metadata = fs::metadata(path).with_context(|| format!("stat {}", path.display()))?;
+    let contents = Arc::new(FileBytes::open(path, metadata.len())?);
+    let bytes = contents.as_slice();
+    let header_len = bytes.len().min(128);
+    let file_type = FileType::detect(path, &bytes[..header_len]);
+
+    let rel_path = pathdiff::diff_paths(path, &repo.root).unwrap_or_else(|| path.to_path_buf());
+    let rel = rel_path.to_string_lossy().replace('\\', "/");
+    let dict_id = store.dictionaries().for_file(&rel, file_type);
+
+    // Chunks arrive in file order, so the whole-file hash is built alongside.
+    let mut file_hasher = blake3::Hasher::new();
+    let mut released = 0usize;
+    let mut count = 0usize;
+    for chunk in chunk_stream(bytes, file_type, chunk_cfg) {
+        file_hasher.update(&bytes[chunk.offset..chunk.offset + chunk.length]);
+        let done = chunk.offset + chunk.length;
+        let job = ChunkJob {
+            file,
+            seq: count,
+            contents: Arc::clone(&contents),
+            chunk,
+            dict_id,
+        };
+        if jobs.send(job).is_err() {
+            bail!("ingest pipeline stopped");
+        }
+        count += 1;
+
+        // The bounded queue keeps compressors close behind, so pages well
+        // behind the cursor are no longer needed.
+        if done - released >= 2 * RELEASE_WINDOW {
+            let upto = done - RELEASE_WINDOW;
+            contents.release(released, upto);
+            released = upto;
+        }
+    }
+
+    Ok(Event::FileDone {
+        file,
+        chunks: count,
+        entry: FileEntry {
+            path: rel,
+            size: metadata.len(),
+            file_hash: *file_hasher.finalize().as_bytes(),
+            chunks: Vec::new(),
+            mode: mode(&metadata),
+            mtime_ns: mtime_ns(&metadata),
+            file_type,
+        },
+        meta: Box::new(metadata),
+    })
+}
+
+/// Compressor stage: store chunks not seen before, claiming each hash so that
+/// two workers never compress the same chunk.
+fn store_chunk(
+    db: &MetadataDb,
+    store: &ChunkStore,
+    level: i32,
+    claimed: &Mutex<HashSet<[u8; 32]>>,
+    job: ChunkJob,
+) -> Result<Event> {
+    let ChunkResult { offset, length, hash } = job.chunk;
+    let hash_arr = *hash.as_bytes();
+    let mut compressed_length = length as u32;
+
+    let new = !db.is_chunk_known(&hash_arr)?
+        && claimed.lock().expect("claimed set poisoned").insert(hash_arr);
+    if new {
+        let slice = &job.contents.as_slice()[offset..offset + length];
+        let compressed = store.dictionaries().compress(slice, level, job.dict_id)?;
+        compressed_length = compressed.len() as u32;
+        store.store(&hash, &compressed)?;
+    }
+
+    Ok(Event::Chunk {
+        file: job.file,
+        seq: job.seq,
+        chunk: ChunkRef {
+            hash: hash_arr,
+            offset: offset as u64,
+            length: length as u32,
+            compressed_length,
+        },
+        new,
+    })
+}
+
+/// Stage `paths` (files or directories) through the parallel ingest pipeline:
+/// reader threads map and chunk files, compressor threads store new chunks,
+/// and this thread assembles entries and commits them in batched transactions.
+pub fn stage_paths(repo: &Repository, paths: &[String], force: bool) -> Result<AddStats> {
     let db = MetadataDb::open(&repo.metadata_db_path())?;
-    let store = ChunkStore::new(repo.forge_dir.join("objects/chunks"));
+    let store = repo.chunk_store()?;
     let config = repo.read_config()?;
 
     let chunk_cfg = ChunkConfig {
@@ -108,72 +312,123 @@ pub fn run(paths: &[String], force: bool) -> Result<()> {
         .sum();
 
     let bar = create_progress_bar(total_bytes);
+    let workers = num_cpus::get().max(1);
+    let readers = (workers / 4).clamp(1, files.len().max(1));
 
-    let mut staged_files = 0usize;
-    let mut new_chunks = 0usize;
-    let mut deduped_chunks = 0usize;
-    let mut new_chunk_bytes = 0u64;
-    let mut dedup_saved_bytes = 0u64;
-
-    for file in files {
-        let metadata = fs::metadata(&file).with_context(|| format!("stat {}", file.display()))?;
-        let bytes = read_file_bytes(&file)?;
-        let header_len = bytes.len().min(128);
-        let file_type = FileType::detect(&file, &bytes[..header_len]);
-        let chunks = chunk_file(&bytes, file_type, &chunk_cfg);
-
-        let mut refs = Vec::with_capacity(chunks.len());
-        for ch in chunks {
-            let slice = &bytes[ch.offset..ch.offset + ch.length];
-            let hash_arr = *ch.hash.as_bytes();
-            let mut compressed_length = ch.length as u32;
-
-            if !db.is_chunk_known(&hash_arr)? {
-                let compressed = compression::compress(slice, config.compression_level)?;
-                compressed_length = compressed.len() as u32;
-                let _ = store.store(&ch.hash, &compressed)?;
-                db.insert_chunk(&hash_arr)?;
-                new_chunks += 1;
-                new_chunk_bytes += ch.length as u64;
-            } else {
-                deduped_chunks += 1;
-                dedup_saved_bytes += ch.length as u64;
-            }
+    let (file_tx, file_rx) = unbounded::<(usize, PathBuf)>();
+    let (job_tx, job_rx) = bounded::<ChunkJob>(workers * QUEUE_DEPTH);
+    let (event_tx, event_rx) = unbounded::<Event>();
+    for (i, path) in files.into_iter().enumerate() {
+        file_tx.send((i, path)).expect("file queue open");
+    }
+    drop(file_tx);
+
+    let claimed = Mutex::new(HashSet::new());
+    let mut stats = AddStats::default();
 
-            refs.push(ChunkRef {
-                hash: hash_arr,
-                offset: ch.offset as u64,
-                length: ch.length as u32,
-                compressed_length,
+    thread::scope(|scope| -> Result<()> {
+        for _ in 0..readers {
+            let (file_rx, job_tx, event_tx) = (file_rx.clone(), job_tx.clone(), event_tx.clone());
+            let (store, chunk_cfg) = (&store, &chunk_cfg);
+            scope.spawn(move || {
+                for (file, path) in file_rx {
+                    let event = read_file(repo, store, chunk_cfg, file, &path, &job_tx)
+                        .unwrap_or_else(Event::Failed);
+                    if event_tx.send(event).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        for _ in 0..workers {
+            let (job_rx, event_tx) = (job_rx.clone(), event_tx.clone());
+            let (db, store, claimed) = (&db, &store, &claimed);
+            scope.spawn(move || {
+                for job in job_rx {
+                    let event = store_chunk(db, store, config.compression_level, claimed, job)
+                        .unwrap_or_else(Event::Failed);
+                    if event_tx.send(event).is_err() {
+                        break;
+                    }
+                }
             });
         }
+        drop((job_tx, job_rx, event_tx));
 
-        let rel_path = pathdiff::diff_paths(&file, &repo.root).unwrap_or_else(|| file.clone());
-        let rel = rel_path.to_string_lossy().replace('\\', "/");
-        let entry = FileEntry {
-            path: rel.clone(),
-            size: metadata.len(),
-            file_hash: *hash_bytes(&bytes).as_bytes(),
-            chunks: refs,
-            mode: mode(&metadata),
-            mtime_ns: mtime_ns(&metadata),
-            file_type,
+        let mut pending: HashMap<usize, PendingFile> = HashMap::new();
+        let mut batch_refs: Vec<[u8; 32]> = Vec::new();
+        let mut batch_entries: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut batch_stats: Vec<(String, fs::Metadata, [u8; 32])> = Vec::new();
+        let flush = |refs: &[[u8; 32]], entries: &[(String, Vec<u8>)], stats: &[(String, fs::Metadata, [u8; 32])]| {
+            db.stage_batch(refs, entries)?;
+            record_stats(&db, stats.iter().map(|(path, meta, hash)| (path.as_str(), meta, *hash)))
         };
 
-        let entry_bytes = serialize_file_entry(&entry)?;
-        db.stage_file(&rel, &entry_bytes)?;
-        staged_files += 1;
-        bar.inc(metadata.len());
-    }
+        // Returning early drops `event_rx`, which makes every stage wind down.
+        for event in event_rx {
+            let file = match event {
+                Event::Chunk { file, seq, chunk, new } => {
+                    bar.inc(chunk.length as u64);
+                    if new {
+                        stats.new_chunks += 1;
+                        stats.new_chunk_bytes += chunk.length as u64;
+                    } else {
+                        stats.deduped_chunks += 1;
+                        stats.dedup_saved_bytes += chunk.length as u64;
+                    }
+                    let slot = pending.entry(file).or_default();
+                    if slot.chunks.len() <= seq {
+                        slot.chunks.resize(seq + 1, None);
+                    }
+                    slot.chunks[seq] = Some(chunk);
+                    slot.received += 1;
+                    file
+                }
+                Event::FileDone { file, chunks, entry, meta } => {
+                    pending.entry(file).or_default().done = Some((chunks, entry, *meta));
+                    file
+                }
+                Event::Failed(err) => return Err(err),
+            };
+
+            let Some((entry, meta)) = pending.get_mut(&file).and_then(PendingFile::take_if_complete) else {
+                continue;
+            };
+            pending.remove(&file);
+            batch_refs.extend(entry.chunks.iter().map(|c| c.hash));
+            batch_entries.push((entry.path.clone(), serialize_file_entry(&entry)?));
+            batch_stats.push((entry.path, meta, entry.file_hash));
+            stats.staged_files += 1;
+
+            if batch_entries.len() >= BATCH_FILES {
+                flush(&batch_refs, &batch_entries, &batch_stats)?;
+                batch_refs.clear();
+                batch_entries.clear();
+                batch_stats.clear();
+            }
+        }
+
+        if !batch_entries.is_empty() {
+            flush(&batch_refs, &batch_entries, &batch_stats)?;
+        }
+        Ok(())
+    })?;
 
     bar.finish_and_clear();
+    Ok(stats)
+}
+
+pub fn run(paths: &[String], force: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let stats = stage_paths(&repo, paths, force)?;
     println!(
         "Staged {} files, {} new chunks ({}), {} deduped chunks ({} saved)",
-        staged_files,
-        new_chunks,
-        human_bytes(new_chunk_bytes),
-        deduped_chunks,
-        human_bytes(dedup_saved_bytes)
+        stats.staged_files,
+        stats.new_chunks,
+        human_bytes(stats.new_chunk_bytes),
+        stats.deduped_chunks,
+        human_bytes(stats.dedup_saved_bytes)
     );
     Ok(())
 }
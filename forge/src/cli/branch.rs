@@ -0,0 +1,67 @@
+use anyhow::{bail, Context, Result};
+
+use crate::core::repository::{validate_ref_name, Repository};
+use crate::core::revision::resolve_revision;
+use crate::util::human::short_hex;
+
+fn list(repo: &Repository) -> Result<()> {
+    let current = repo.current_branch()?;
+    let branches = repo.list_refs("refs/heads")?;
+
+    if let Some(name) = &current {
+        if !branches.iter().any(|(b, _)| b == name) {
+            println!("* {name} (no commits yet)");
+        }
+    }
+
+    for (name, id) in branches {
+        let marker = if current.as_deref() == Some(name.as_str()) { '*' } else { ' ' };
+        let subject = repo
+            .read_commit(&id)
+            .map(|c| c.message.lines().next().unwrap_or_default().to_string())
+            .unwrap_or_default();
+        println!("{marker} {name:<24} {} {subject}", short_hex(&id));
+    }
+    Ok(())
+}
+
+pub fn run(name: Option<&str>, start_point: Option<&str>, delete: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+
+    let Some(name) = name else {
+        if delete {
+            bail!("branch name required for --delete");
+        }
+        return list(&repo);
+    };
+    validate_ref_name(name)?;
+    let reference = format!("refs/heads/{name}");
+
+    if delete {
+        if repo.current_branch()?.as_deref() == Some(name) {
+            bail!("cannot delete branch '{name}' while it is checked out");
+        }
+        let Some(id) = repo.read_ref(&reference)? else {
+            bail!("branch '{name}' not found");
+        };
+        repo.delete_ref(&reference)?;
+        println!("Deleted branch {name} (was {})", short_hex(&id));
+        return Ok(());
+    }
+
+    if repo.read_ref(&reference)?.is_some() {
+        bail!("a branch named '{name}' already exists");
+    }
+
+    let target = match start_point {
+        Some(start) => resolve_revision(&repo, start)?,
+        None => repo
+            .read_head()?
+            .ok_or_else(|| anyhow::anyhow!("cannot create branch '{name}': no commits yet"))?,
+    };
+
+    repo.write_ref(&reference, &target)?;
+    println!("Created branch {name} at {}", short_hex(&target));
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::cli::checkout::prefetch;
+use crate::core::repository::Repository;
+use crate::core::revision::resolve_revision;
+
+/// Stream `path` as of `rev` to stdout, fetching its chunks first in a
+/// partial clone.
+pub fn run(path: &str, rev: &str) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let commit_id = resolve_revision(&repo, rev)?;
+    let rel = repo.relative_path(path);
+    let files = repo.read_commit_files(&commit_id)?;
+    let entry = files
+        .get(&rel)
+        .ok_or_else(|| anyhow!("'{rel}' does not exist in {rev}"))?;
+
+    let store = repo.chunk_store()?;
+    prefetch(&store, std::iter::once(entry))?;
+    let mut out = std::io::stdout().lock();
+    for chunk in &entry.chunks {
+        let raw = store.read_decompressed(&blake3::Hash::from(chunk.hash))?;
+        out.write_all(&raw).context("write to stdout")?;
+    }
+    out.flush().context("write to stdout")
+}
@@ -1,54 +1,281 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Write;
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path};
+use std::time::{Duration, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
-use crate::core::manifest::deserialize_commit;
+use crate::core::hash::hash_file;
+use crate::core::manifest::{deserialize_file_entry, serialize_file_entry, FileEntry};
 use crate::core::repository::Repository;
+use crate::core::revision::resolve_revision;
+use crate::core::worktree::record_stats;
+use crate::db::metadata::MetadataDb;
 use crate::store::cas::ChunkStore;
-use crate::store::compression;
+use crate::util::human::short_hex;
 
-pub fn run(commit_id_hex: &str) -> Result<()> {
-    let cwd = std::env::current_dir().context("get current dir")?;
-    let repo = Repository::discover(&cwd)?;
-    let store = ChunkStore::new(repo.forge_dir.join("objects/chunks"));
+#[derive(Debug, Default)]
+pub(crate) struct CheckoutStats {
+    pub written: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+fn mtime_ns(meta: &fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+/// Whether the file on disk still matches `entry` (stat fast path, hash fallback).
+fn matches_entry(abs: &Path, meta: &fs::Metadata, entry: &FileEntry) -> Result<bool> {
+    if meta.len() != entry.size {
+        return Ok(false);
+    }
+    if mtime_ns(meta) == entry.mtime_ns {
+        return Ok(true);
+    }
+    Ok(hash_file(abs)?.as_bytes() == &entry.file_hash)
+}
+
+/// Reassemble one manifest entry into the working tree, restoring mode and mtime.
+pub(crate) fn write_entry(repo: &Repository, store: &ChunkStore, entry: &FileEntry) -> Result<()> {
+    let relative = Path::new(&entry.path);
+    let normal = relative
+        .components()
+        .all(|part| matches!(part, Component::Normal(name) if name != ".forge"));
+    if entry.path.is_empty() || !normal {
+        bail!("refusing to write '{}' outside the working tree", entry.path);
+    }
+    let out_path = repo.root.join(relative);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("create parent dirs {}", parent.display()))?;
+    }
+
+    let mut file = fs::File::create(&out_path)
+        .with_context(|| format!("create output file {}", out_path.display()))?;
+
+    for chunk in &entry.chunks {
+        let hash = blake3::Hash::from(chunk.hash);
+        let raw = store.read_decompressed(&hash)?;
+        file.write_all(&raw)
+            .with_context(|| format!("write data to {}", out_path.display()))?;
+    }
+
+    if entry.mtime_ns > 0 {
+        let mtime = UNIX_EPOCH + Duration::from_nanos(entry.mtime_ns as u64);
+        file.set_modified(mtime)
+            .with_context(|| format!("set mtime on {}", out_path.display()))?;
+    }
+    drop(file);
+
+    #[cfg(unix)]
+    if entry.mode != 0 {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&out_path, fs::Permissions::from_mode(entry.mode))
+            .with_context(|| format!("set mode on {}", out_path.display()))?;
+    }
+
+    Ok(())
+}
 
-    let manifest_path = repo.forge_dir.join("manifests").join(commit_id_hex);
-    let bytes = fs::read(&manifest_path)
-        .with_context(|| format!("read manifest {}", manifest_path.display()))?;
-    let commit = deserialize_commit(&bytes)?;
+/// Download the chunks of `entries` a partial clone lacks in one batch
+/// rather than one request per chunk.
+pub(crate) fn prefetch<'a>(store: &ChunkStore, entries: impl Iterator<Item = &'a FileEntry>) -> Result<()> {
+    let hashes: Vec<[u8; 32]> = entries.flat_map(|e| e.chunks.iter().map(|c| c.hash)).collect();
+    store.fetch_missing(&hashes)?;
+    Ok(())
+}
 
-    for entry in &commit.files {
-        let out_path = repo.root.join(&entry.path);
-        if let Some(parent) = out_path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("create parent dirs {}", parent.display()))?;
+/// Remove the directories above `path` that are left empty, up to `root`.
+pub(crate) fn remove_empty_parents(root: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || fs::remove_dir(current).is_err() {
+            break;
         }
+        dir = current.parent();
+    }
+}
+
+/// Make the working tree match the snapshot `target`, keyed by path.
+///
+/// Refuses, unless `force` is set, when staged changes exist or when a locally
+/// modified or untracked file would be overwritten or deleted. Files whose
+/// content already matches are left alone, paths absent from `target` are
+/// removed, and the tracked-file table is reset so `forge status` is clean.
+pub(crate) fn materialize(
+    repo: &Repository,
+    db: &MetadataDb,
+    target: &BTreeMap<String, FileEntry>,
+    force: bool,
+) -> Result<CheckoutStats> {
+    let store = repo.chunk_store()?;
 
-        let mut file = fs::File::create(&out_path)
-            .with_context(|| format!("create output file {}", out_path.display()))?;
+    let mut tracked = BTreeMap::new();
+    for (path, bytes) in db.get_all_tracked_files()? {
+        tracked.insert(path, deserialize_file_entry(&bytes)?);
+    }
+    let wanted: BTreeMap<&str, &FileEntry> = target.iter().map(|(p, f)| (p.as_str(), f)).collect();
 
-        for chunk in &entry.chunks {
-            let hash = blake3::Hash::from(chunk.hash);
-            let compressed = store.read(&hash)?;
-            let raw = compression::decompress(&compressed)?;
-            file.write_all(&raw)
-                .with_context(|| format!("write data to {}", out_path.display()))?;
+    if !force {
+        if !db.get_staged_files()?.is_empty() || !db.get_staged_removals()?.is_empty() {
+            bail!("you have staged changes; commit them or use --force to discard them");
         }
 
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            fs::set_permissions(&out_path, fs::Permissions::from_mode(entry.mode))
-                .with_context(|| format!("set mode on {}", out_path.display()))?;
+        let mut clobbered = Vec::new();
+        for (path, entry) in &tracked {
+            let abs = repo.root.join(path);
+            let Ok(meta) = fs::metadata(&abs) else {
+                continue;
+            };
+            let unchanged_by_target = wanted
+                .get(path.as_str())
+                .is_some_and(|next| next.file_hash == entry.file_hash);
+            if !unchanged_by_target && !matches_entry(&abs, &meta, entry)? {
+                clobbered.push(path.clone());
+            }
         }
+        for (path, entry) in &wanted {
+            if tracked.contains_key(*path) {
+                continue;
+            }
+            let abs = repo.root.join(path);
+            if let Ok(meta) = fs::metadata(&abs) {
+                if meta.len() != entry.size || hash_file(&abs)?.as_bytes() != &entry.file_hash {
+                    clobbered.push(path.to_string());
+                }
+            }
+        }
+        if !clobbered.is_empty() {
+            clobbered.sort();
+            bail!(
+                "checkout would overwrite local changes in:\n  {}\nCommit them or use --force.",
+                clobbered.join("\n  ")
+            );
+        }
+    }
+
+    let mut stats = CheckoutStats::default();
+    // Paths whose stat records no longer describe the file; rewritten files
+    // are usually too fresh to get a new record right away.
+    let mut stale = Vec::new();
+    for path in tracked.keys() {
+        if wanted.contains_key(path.as_str()) {
+            continue;
+        }
+        let abs = repo.root.join(path);
+        stale.push(path.clone());
+        match fs::remove_file(&abs) {
+            Ok(_) => {
+                stats.removed += 1;
+                remove_empty_parents(&repo.root, &abs);
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("remove {}", abs.display())),
+        }
+    }
+
+    let mut current = Vec::with_capacity(target.len());
+    for entry in target.values() {
+        let abs = repo.root.join(&entry.path);
+        current.push(match fs::metadata(&abs) {
+            Ok(meta) => match tracked.get(&entry.path) {
+                Some(known) if known.file_hash == entry.file_hash => matches_entry(&abs, &meta, known)?,
+                _ => meta.len() == entry.size && hash_file(&abs)?.as_bytes() == &entry.file_hash,
+            },
+            Err(_) => false,
+        });
+    }
+    prefetch(&store, target.values().zip(&current).filter(|(_, &ok)| !ok).map(|(e, _)| e))?;
+
+    let mut refreshed = Vec::with_capacity(target.len());
+    let mut stats_seen = Vec::with_capacity(target.len());
+    for (entry, up_to_date) in target.values().zip(current) {
+        let abs = repo.root.join(&entry.path);
+        if up_to_date {
+            stats.unchanged += 1;
+        } else {
+            write_entry(repo, &store, entry)?;
+            stale.push(entry.path.clone());
+            stats.written += 1;
+        }
+
+        let meta = fs::metadata(&abs).with_context(|| format!("stat {}", abs.display()))?;
+        let mut fresh = entry.clone();
+        fresh.mtime_ns = mtime_ns(&meta);
+        refreshed.push((fresh.path.clone(), serialize_file_entry(&fresh)?));
+        stats_seen.push((meta, entry.file_hash));
+    }
+
+    db.replace_tracked_files(&refreshed)?;
+    db.update_stat_cache(&[], &stale)?;
+    record_stats(
+        db,
+        target
+            .values()
+            .zip(&stats_seen)
+            .map(|(entry, (meta, hash))| (entry.path.as_str(), meta, *hash)),
+    )?;
+    if force {
+        db.clear_staging()?;
     }
+    Ok(stats)
+}
+
+/// Write `paths` (files or directories) as of `commit_id` into the working
+/// tree, leaving HEAD and the rest of the tree alone. The restored files show
+/// as modified until they are added.
+fn restore_paths(repo: &Repository, commit_id: &[u8; 32], paths: &[String]) -> Result<()> {
+    let files = repo.read_commit_files(commit_id)?;
+    let mut selected = BTreeMap::new();
+    for raw in paths {
+        let rel = repo.relative_path(raw);
+        let prefix = format!("{rel}/");
+        let before = selected.len();
+        selected.extend(
+            files
+                .iter()
+                .filter(|(p, _)| rel.is_empty() || **p == rel || p.starts_with(&prefix)),
+        );
+        if selected.len() == before {
+            bail!("pathspec '{raw}' did not match any file in {}", short_hex(commit_id));
+        }
+    }
+
+    let store = repo.chunk_store()?;
+    prefetch(&store, selected.values().copied())?;
+    for entry in selected.values() {
+        write_entry(repo, &store, entry)?;
+    }
+    println!("Restored {} files from {}", selected.len(), short_hex(commit_id));
+    Ok(())
+}
+
+pub fn run(commit: &str, paths: &[String], force: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let commit_id = resolve_revision(&repo, commit)?;
+    if !paths.is_empty() {
+        return restore_paths(&repo, &commit_id, paths);
+    }
+
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+    let target = repo.read_commit_files(&commit_id)?;
+    let stats = materialize(&repo, &db, &target, force)?;
 
-    fs::write(repo.head_path(), format!("{}\n", commit_id_hex)).context("write detached HEAD")?;
+    repo.detach_head(&commit_id)?;
     println!(
-        "Checked out {} ({} files)",
-        &commit_id_hex[..commit_id_hex.len().min(12)],
-        commit.files.len()
+        "Checked out {} ({} files: {} written, {} unchanged, {} removed)",
+        short_hex(&commit_id),
+        target.len(),
+        stats.written,
+        stats.unchanged,
+        stats.removed
     );
     Ok(())
 }
@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use crate::cli::checkout::materialize;
+use crate::cli::fetch::fetch_remote;
+use crate::core::partial::PartialConfig;
+use crate::core::remote::{Remote, RemoteConfig};
+use crate::core::repository::Repository;
+use crate::db::metadata::MetadataDb;
+use crate::transport::quic;
+use crate::util::human::{parse_bytes, short_hex};
+
+/// Directory a clone of `url` lands in when none is given: the host name.
+fn default_dir(url: &str) -> Result<PathBuf> {
+    let (host, _) = quic::parse_url(url)?;
+    Ok(PathBuf::from(host.replace(':', "_")))
+}
+
+/// Create a repository in `dir`, fetch everything from `url` as `origin`,
+/// and check out the remote's default branch. A `partial` clone only fetches
+/// the chunks of the files it checks out.
+pub fn run(url: &str, dir: Option<&str>, partial: bool, cache_size: Option<&str>) -> Result<()> {
+    if !quic::is_quic_url(url) {
+        bail!("'{url}' is not a quic:// URL");
+    }
+    let cache_size = cache_size.map(parse_bytes).transpose()?;
+    let partial = partial.then(|| PartialConfig {
+        remote: "origin".to_string(),
+        cache_size,
+    });
+    let dir = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => default_dir(url)?,
+    };
+    let created = !dir.exists();
+    if !created && fs::read_dir(&dir)?.next().is_some() {
+        bail!("destination '{}' already exists and is not empty", dir.display());
+    }
+
+    let result = clone_into(url, &dir, partial);
+    if result.is_err() {
+        // Leave nothing half-cloned behind.
+        let _ = if created {
+            fs::remove_dir_all(&dir)
+        } else {
+            fs::remove_dir_all(dir.join(".forge"))
+        };
+    }
+    result
+}
+
+fn clone_into(url: &str, dir: &Path, partial: Option<PartialConfig>) -> Result<()> {
+    let repo = Repository::init(dir)?;
+    let origin = RemoteConfig::new(url);
+    let mut config = repo.read_config()?;
+    config.remotes.insert("origin".to_string(), origin.clone());
+    config.partial = partial;
+    repo.write_config(&config)?;
+    println!("Cloning {url} into {}", repo.root.display());
+
+    let remote = Remote {
+        name: Some("origin".to_string()),
+        config: origin,
+    };
+    let refs = fetch_remote(&repo, &remote)?;
+    let Some((branch, tip)) = refs.default_branch() else {
+        println!("The remote has no branches yet; nothing to check out.");
+        return Ok(());
+    };
+
+    let reference = format!("refs/heads/{branch}");
+    repo.write_ref(&reference, &tip)?;
+    repo.set_head_ref(&reference)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+    let files = repo.read_commit_files(&tip)?;
+    let stats = materialize(&repo, &db, &files, false)?;
+    println!(
+        "Checked out '{branch}' at {} ({} files written)",
+        short_hex(&tip),
+        stats.written
+    );
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 
 use anyhow::{bail, Context, Result};
@@ -5,71 +6,108 @@ use chrono::Utc;
 
 use crate::core::manifest::{deserialize_file_entry, serialize_commit, Commit};
 use crate::core::repository::Repository;
+use crate::core::tree::update_tree;
 use crate::db::metadata::MetadataDb;
 use crate::util::human::short_hex;
 
+pub(crate) fn author_name() -> String {
+    std::env::var("GIT_AUTHOR_NAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub(crate) fn now_ns() -> i64 {
+    Utc::now().timestamp_nanos_opt().unwrap_or_else(|| Utc::now().timestamp() * 1_000_000_000)
+}
+
+/// Assign the content id to `draft`, persist its manifest and advance HEAD.
+pub(crate) fn write_commit(repo: &Repository, db: &MetadataDb, draft: Commit) -> Result<Commit> {
+    let draft_bytes = serialize_commit(&draft)?;
+    let commit_id = *blake3::hash(&draft_bytes).as_bytes();
+
+    let commit = Commit {
+        id: commit_id,
+        ..draft
+    };
+    let commit_bytes = serialize_commit(&commit)?;
+    let id_hex = hex::encode(commit_id);
+
+    let manifest_path = repo.manifest_path(&commit_id);
+    fs::write(&manifest_path, &commit_bytes)
+        .with_context(|| format!("write manifest {}", manifest_path.display()))?;
+
+    db.store_commit(&id_hex, &commit_bytes)?;
+    repo.update_head(&commit_id)?;
+    Ok(commit)
+}
+
 pub fn run(message: &str) -> Result<()> {
     let cwd = std::env::current_dir().context("get current dir")?;
     let repo = Repository::discover(&cwd)?;
     let db = MetadataDb::open(&repo.metadata_db_path())?;
 
+    let merge_head = repo.read_merge_head()?;
+    if merge_head.is_some() {
+        let conflicts = db.get_conflicts()?;
+        if !conflicts.is_empty() {
+            let paths: Vec<_> = conflicts.into_iter().map(|(path, _)| path).collect();
+            bail!(
+                "cannot commit with unresolved conflicts:\n  {}\nUse `forge resolve <path> --ours|--theirs`.",
+                paths.join("\n  ")
+            );
+        }
+    }
+
     let staged = db.get_staged_files()?;
-    if staged.is_empty() {
+    let removals = db.get_staged_removals()?;
+    if staged.is_empty() && removals.is_empty() && merge_head.is_none() {
         bail!("Nothing staged");
     }
 
-    let mut files = Vec::with_capacity(staged.len());
-    for (_path, bytes) in &staged {
-        files.push(deserialize_file_entry(bytes)?);
-    }
+    let head = repo.read_head()?;
 
-    let mut parents = Vec::new();
-    if let Some(parent) = repo.read_head()? {
-        parents.push(parent);
+    // A commit is a full snapshot: start from the parent's tree and overlay
+    // staged changes, rewriting only the directories they touch.
+    let base_tree = match &head {
+        Some(parent) => Some(repo.read_commit(parent)?.tree),
+        None => None,
+    };
+    let mut changes = BTreeMap::new();
+    for (path, _) in &removals {
+        changes.insert(path.clone(), None);
     }
+    for (path, bytes) in &staged {
+        changes.insert(path.clone(), Some(deserialize_file_entry(bytes)?));
+    }
+    let tree = update_tree(&repo, base_tree.as_ref(), &changes)?;
 
-    let author = std::env::var("GIT_AUTHOR_NAME")
-        .or_else(|_| std::env::var("USER"))
-        .unwrap_or_else(|_| "unknown".to_string());
-
-    let timestamp_ns = Utc::now().timestamp_nanos_opt().unwrap_or_else(|| Utc::now().timestamp() * 1_000_000_000);
+    let parents: Vec<_> = head.into_iter().chain(merge_head).collect();
 
     let draft = Commit {
         id: [0u8; 32],
         parents,
-        files,
+        tree,
         message: message.to_string(),
-        author,
-        timestamp_ns,
+        author: author_name(),
+        timestamp_ns: now_ns(),
     };
+    let commit = write_commit(&repo, &db, draft)?;
 
-    let draft_bytes = serialize_commit(&draft)?;
-    let commit_id = *blake3::hash(&draft_bytes).as_bytes();
-
-    let commit = Commit {
-        id: commit_id,
-        ..draft
-    };
-    let commit_bytes = serialize_commit(&commit)?;
-    let id_hex = hex::encode(commit_id);
-
-    let manifest_path = repo.forge_dir.join("manifests").join(&id_hex);
-    fs::write(&manifest_path, &commit_bytes)
-        .with_context(|| format!("write manifest {}", manifest_path.display()))?;
-
-    db.store_commit(&id_hex, &commit_bytes)?;
-
-    for (path, bytes) in staged {
-        db.store_file_entry(&path, &bytes)?;
+    for (path, _) in &removals {
+        db.remove_file_entry(path)?;
+    }
+    for (path, bytes) in &staged {
+        db.store_file_entry(path, bytes)?;
     }
 
-    repo.update_head(&commit_id)?;
     db.clear_staging()?;
+    repo.clear_merge_head()?;
 
     println!(
-        "Committed {} — {} files, message: {}",
-        short_hex(&commit_id),
-        commit.files.len(),
+        "Committed {} — {} files ({} changed), message: {}",
+        short_hex(&commit.id),
+        repo.read_tree(&commit.tree)?.file_count,
+        staged.len() + removals.len(),
         message
     );
 
@@ -3,23 +3,57 @@ use std::fs;
 
 use anyhow::{Context, Result};
 
+use crate::chunking::structure_aware::png::pixel_hash;
+use crate::cli::checkout::prefetch;
 use crate::core::hash::hash_file;
-use crate::core::manifest::{deserialize_commit, deserialize_file_entry, FileEntry};
+use crate::core::manifest::{deserialize_file_entry, FileEntry, FileType};
 use crate::core::repository::Repository;
+use crate::core::revision::resolve_revision;
+use crate::core::tree::diff_trees;
+use crate::core::worktree::{self, WorkingFile};
 use crate::db::metadata::MetadataDb;
+use crate::store::cas::ChunkStore;
 
-fn load_commit_files(repo: &Repository, hex_id: &str) -> Result<BTreeMap<String, FileEntry>> {
-    let path = repo.forge_dir.join("manifests").join(hex_id);
-    let bytes = fs::read(&path).with_context(|| format!("read manifest {}", path.display()))?;
-    let commit = deserialize_commit(&bytes)?;
-    Ok(commit
-        .files
-        .into_iter()
-        .map(|f| (f.path.clone(), f))
-        .collect::<BTreeMap<_, _>>())
+/// Reads both sides of a modified PNG for `--pixels`.
+struct Pixels<'a> {
+    repo: &'a Repository,
+    store: ChunkStore,
+    /// Files edited in the working tree, whose new contents are only on disk.
+    on_disk: BTreeSet<String>,
+}
+
+impl Pixels<'_> {
+    fn read(&self, entry: &FileEntry) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(entry.size as usize);
+        for chunk in &entry.chunks {
+            data.extend_from_slice(&self.store.read_decompressed(&blake3::Hash::from(chunk.hash))?);
+        }
+        Ok(data)
+    }
+
+    /// Whether both versions decode to the same image.
+    fn unchanged(&self, path: &str, old: &FileEntry, new: &FileEntry) -> Result<bool> {
+        if old.file_type != FileType::Png || new.file_type != FileType::Png {
+            return Ok(false);
+        }
+        let on_disk = self.on_disk.contains(path);
+        prefetch(&self.store, std::iter::once(old).chain((!on_disk).then_some(new)))?;
+        let new_data = if on_disk {
+            fs::read(self.repo.root.join(path)).with_context(|| format!("read {path}"))?
+        } else {
+            self.read(new)?
+        };
+        let (old_hash, new_hash) = (pixel_hash(&self.read(old)?), pixel_hash(&new_data));
+        Ok(old_hash.is_some() && old_hash == new_hash)
+    }
 }
 
-fn print_diff(old_map: &BTreeMap<String, FileEntry>, new_map: &BTreeMap<String, FileEntry>, path_filter: Option<&str>) {
+fn print_diff(
+    old_map: &BTreeMap<String, FileEntry>,
+    new_map: &BTreeMap<String, FileEntry>,
+    path_filter: Option<&str>,
+    pixels: Option<&Pixels>,
+) -> Result<()> {
     let mut paths = BTreeSet::new();
     paths.extend(old_map.keys().cloned());
     paths.extend(new_map.keys().cloned());
@@ -57,55 +91,93 @@ fn print_diff(old_map: &BTreeMap<String, FileEntry>, new_map: &BTreeMap<String,
                         changed,
                         reuse_pct
                     );
+                    if let Some(pixels) = pixels {
+                        if pixels.unchanged(&path, old_entry, new_entry)? {
+                            println!("  pixels unchanged (only the PNG encoding or metadata differs)");
+                        }
+                    }
                 }
             }
             (None, None) => {}
         }
     }
+    Ok(())
 }
 
-pub fn run(path: Option<&str>, commit1: Option<&str>, commit2: Option<&str>) -> Result<()> {
+/// With `pixels`, modified PNGs whose decoded images are identical are
+/// called out.
+pub fn run(path: Option<&str>, commit1: Option<&str>, commit2: Option<&str>, pixels: bool) -> Result<()> {
     let cwd = std::env::current_dir().context("get current dir")?;
     let repo = Repository::discover(&cwd)?;
+    let mut checker = if pixels {
+        Some(Pixels {
+            repo: &repo,
+            store: repo.chunk_store()?,
+            on_disk: BTreeSet::new(),
+        })
+    } else {
+        None
+    };
 
     if let (Some(c1), Some(c2)) = (commit1, commit2) {
-        let old_map = load_commit_files(&repo, c1)?;
-        let new_map = load_commit_files(&repo, c2)?;
-        print_diff(&old_map, &new_map, path);
-        return Ok(());
+        let old_tree = repo.read_commit(&resolve_revision(&repo, c1)?)?.tree;
+        let new_tree = repo.read_commit(&resolve_revision(&repo, c2)?)?.tree;
+        let (old_map, new_map) = diff_trees(&repo, Some(&old_tree), Some(&new_tree))?;
+        return print_diff(&old_map, &new_map, path, checker.as_ref());
     }
 
     let db = MetadataDb::open(&repo.metadata_db_path())?;
 
     let head_map = if let Some(head) = repo.read_head()? {
-        load_commit_files(&repo, &hex::encode(head))?
+        repo.read_commit_files(&head)?
     } else {
         BTreeMap::new()
     };
 
     let mut current_map = head_map.clone();
 
+    let removed: BTreeSet<String> = db
+        .get_staged_removals()?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    for path in &removed {
+        current_map.remove(path);
+    }
+
     for (staged_path, bytes) in db.get_staged_files()? {
         let entry = deserialize_file_entry(&bytes)?;
         current_map.insert(staged_path, entry);
     }
 
     for (tracked_path, bytes) in db.get_all_tracked_files()? {
-        if current_map.contains_key(&tracked_path) {
+        if current_map.contains_key(&tracked_path) || removed.contains(&tracked_path) {
             continue;
         }
-        let mut entry = deserialize_file_entry(&bytes)?;
-        let abs = repo.root.join(&tracked_path);
-        if abs.exists() {
-            let meta = fs::metadata(&abs)?;
-            if meta.len() != entry.size {
-                entry.size = meta.len();
-                entry.file_hash = *hash_file(&abs)?.as_bytes();
-            }
-            current_map.insert(tracked_path, entry);
+        if repo.root.join(&tracked_path).exists() {
+            current_map.insert(tracked_path, deserialize_file_entry(&bytes)?);
         }
     }
 
-    print_diff(&head_map, &current_map, path);
-    Ok(())
+    // Files edited since they were committed or staged; their new contents
+    // are only in the working tree.
+    let working: BTreeMap<String, WorkingFile> = current_map
+        .keys()
+        .filter_map(|path| {
+            let abs = repo.root.join(path);
+            let meta = fs::metadata(&abs).ok()?;
+            Some((path.clone(), WorkingFile { abs, meta }))
+        })
+        .collect();
+    for path in worktree::modified_files(&db, &current_map, &working)? {
+        let file = &working[&path];
+        let entry = current_map.get_mut(&path).expect("modified paths are in the map");
+        entry.size = file.meta.len();
+        entry.file_hash = *hash_file(&file.abs)?.as_bytes();
+        if let Some(checker) = &mut checker {
+            checker.on_disk.insert(path);
+        }
+    }
+
+    print_diff(&head_map, &current_map, path, checker.as_ref())
 }
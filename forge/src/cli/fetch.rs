@@ -0,0 +1,82 @@
+use anyhow::{bail, Context, Result};
+
+use crate::core::remote::{self, Remote, TransportKind};
+use crate::core::repository::Repository;
+use crate::db::metadata::MetadataDb;
+use crate::transport::client::{self, RemoteRefs};
+use crate::transport::protocol::PARTIAL_CLONE_VERSION;
+use crate::transport::quic;
+use crate::util::human::{human_bytes, short_hex};
+
+/// Download every branch of a `quic` remote. A named remote's
+/// `refs/remotes/<name>/*` are moved to match, and dropped for branches the
+/// remote no longer has. The working tree is left alone. From the remote of a
+/// partial clone only commits and trees are downloaded.
+pub(crate) fn fetch_remote(repo: &Repository, remote: &Remote) -> Result<RemoteRefs> {
+    if remote.config.kind() != TransportKind::Quic {
+        bail!("'{}' is a mirror remote; only quic remotes can be fetched from", remote.label());
+    }
+    let url = &remote.config.url;
+    let partial = repo
+        .read_config()?
+        .partial
+        .is_some_and(|partial| remote.name.as_deref() == Some(partial.remote.as_str()));
+    let rt = tokio::runtime::Runtime::new().context("create tokio runtime")?;
+    let (refs, stats) = rt.block_on(async {
+        let mut session = quic::open_session(repo, &remote.config).await?;
+        let refs = client::handshake(&mut session.channel).await?;
+        if partial && refs.version < PARTIAL_CLONE_VERSION {
+            bail!("{url} speaks protocol {}; partial clones need {PARTIAL_CLONE_VERSION}", refs.version);
+        }
+        let tips: Vec<[u8; 32]> = refs.branches().map(|(_, id)| id).collect();
+        let db = MetadataDb::open(&repo.metadata_db_path())?;
+        let stats = client::fetch(repo, &db, &mut session.channel, &tips, !partial).await?;
+        session.channel.finish().await?;
+        anyhow::Ok((refs, stats))
+    })?;
+
+    println!(
+        "Fetched {} commits, {} trees, {} chunks ({}) from {url}",
+        stats.commits,
+        stats.trees,
+        stats.chunks,
+        human_bytes(stats.chunk_bytes)
+    );
+    if let Some(name) = &remote.name {
+        update_tracking_refs(repo, name, &refs)?;
+    }
+    Ok(refs)
+}
+
+fn update_tracking_refs(repo: &Repository, name: &str, remote: &RemoteRefs) -> Result<()> {
+    let prefix = format!("refs/remotes/{name}");
+    for (branch, id) in remote.branches() {
+        let reference = format!("{prefix}/{branch}");
+        let old = repo.read_ref(&reference)?;
+        if old == Some(id) {
+            continue;
+        }
+        repo.write_ref(&reference, &id)?;
+        match old {
+            Some(old) => println!("  {branch} -> {name}/{branch} ({}..{})", short_hex(&old), short_hex(&id)),
+            None => println!("  {branch} -> {name}/{branch} (new)"),
+        }
+    }
+
+    for (branch, _) in repo.list_refs(&prefix)? {
+        if remote.get(&format!("refs/heads/{branch}")).is_none() {
+            repo.delete_ref(&format!("{prefix}/{branch}"))?;
+            println!("  {name}/{branch} deleted (gone from the remote)");
+        }
+    }
+    Ok(())
+}
+
+/// `remote` is a remote name or a `quic://` URL; only a named remote gets
+/// remote-tracking refs.
+pub fn run(remote: &str) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    fetch_remote(&repo, &remote::require(&repo, remote)?)?;
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use walkdir::WalkDir;
+
+use crate::core::manifest::{deserialize_tree, verified_manifest_id, FileEntry, TreeNode};
+use crate::core::repository::{parse_object_id, Repository};
+use crate::db::metadata::MetadataDb;
+use crate::store::cas::ChunkStore;
+use crate::store::pack::{list_packs, read_from_pack, read_pack_index, PackFile};
+use crate::util::human::short_hex;
+
+fn verify_chunk(store: &ChunkStore, hash: &[u8; 32], compressed: &[u8]) -> Result<(), String> {
+    let raw = store
+        .dictionaries()
+        .decompress(compressed).map_err(|e| format!("undecodable: {e}"))?;
+    if blake3::hash(&raw).as_bytes() != hash {
+        return Err("content hash mismatch".to_string());
+    }
+    Ok(())
+}
+
+fn quarantine(repo: &Repository, kind: &str, src: &Path, name: &str) -> Result<()> {
+    let dir = repo.forge_dir.join("quarantine").join(kind);
+    fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+    fs::rename(src, dir.join(name)).with_context(|| format!("quarantine {}", src.display()))
+}
+
+pub fn run(quarantine_bad: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+    let store = repo.chunk_store()?;
+
+    let mut problems: Vec<String> = Vec::new();
+
+    // ---- chunks -------------------------------------------------------------
+    let mut present: HashSet<[u8; 32]> = HashSet::new();
+    let mut corrupt: HashSet<[u8; 32]> = HashSet::new();
+    let mut checked_chunks = 0usize;
+
+    for hash in store.list_loose()? {
+        let key = *hash.as_bytes();
+        let path = store.chunk_path(&hash);
+        let bytes = fs::read(&path).with_context(|| format!("read {}", path.display()))?;
+        checked_chunks += 1;
+        if let Err(reason) = verify_chunk(&store, &key, &bytes) {
+            problems.push(format!("corrupt chunk {} ({reason})", hash.to_hex()));
+            corrupt.insert(key);
+            if quarantine_bad {
+                quarantine(&repo, "chunks", &path, &hash.to_hex())?;
+                continue;
+            }
+        }
+        present.insert(key);
+    }
+
+    for pack in list_packs(&store.pack_dir)? {
+        let index = match read_pack_index(&pack) {
+            Ok(index) => index,
+            Err(err) => {
+                problems.push(format!("unreadable pack {}: {err:#}", pack.display()));
+                continue;
+            }
+        };
+        match PackFile::open(&pack) {
+            Ok(lookup) => {
+                let stale = lookup.len() != index.entries.len()
+                    || index.entries.iter().any(|e| lookup.find(&e.hash).as_ref() != Some(e));
+                if stale {
+                    problems.push(format!(
+                        "index for {} does not match the pack; delete the .idx to rebuild it",
+                        pack.display()
+                    ));
+                }
+            }
+            Err(err) => problems.push(format!("unreadable index for {}: {err:#}", pack.display())),
+        }
+        for entry in index.entries {
+            let bytes = read_from_pack(&pack, &entry)?;
+            checked_chunks += 1;
+            if let Err(reason) = verify_chunk(&store, &entry.hash, &bytes) {
+                problems.push(format!(
+                    "corrupt chunk {} in {} ({reason})",
+                    hex::encode(entry.hash),
+                    pack.display()
+                ));
+                corrupt.insert(entry.hash);
+                continue;
+            }
+            present.insert(entry.hash);
+        }
+    }
+
+    if quarantine_bad && !corrupt.is_empty() {
+        let bad: Vec<_> = corrupt.iter().copied().collect();
+        db.forget_chunks(&bad)?;
+    }
+
+    // ---- manifests ----------------------------------------------------------
+    let manifests_dir = repo.forge_dir.join("manifests");
+    let mut manifests: HashSet<String> = HashSet::new();
+    for entry in
+        fs::read_dir(&manifests_dir).with_context(|| format!("read {}", manifests_dir.display()))?
+    {
+        let path = entry.context("read manifests entry")?.path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let valid = fs::read(&path)
+            .ok()
+            .and_then(|bytes| verified_manifest_id(&bytes))
+            .filter(|id| hex::encode(id) == name);
+        if valid.is_none() {
+            problems.push(format!("corrupt manifest {name}"));
+            if quarantine_bad {
+                quarantine(&repo, "manifests", &path, &name)?;
+                continue;
+            }
+        }
+        manifests.insert(name);
+    }
+
+    let in_db: HashSet<String> = db.list_commit_ids()?.into_iter().collect();
+    for id in in_db.difference(&manifests) {
+        problems.push(format!(
+            "commit {id} in metadata.redb but missing from manifests/"
+        ));
+    }
+    for id in manifests.difference(&in_db) {
+        problems.push(format!("manifest {id} missing from metadata.redb"));
+    }
+    for id in in_db.intersection(&manifests) {
+        let on_disk = fs::read(manifests_dir.join(id)).ok();
+        if on_disk != db.get_commit(id)? {
+            problems.push(format!(
+                "commit {id} differs between metadata.redb and manifests/"
+            ));
+        }
+    }
+
+    // ---- trees --------------------------------------------------------------
+    let trees_dir = repo.forge_dir.join("objects/trees");
+    let mut checked_trees = 0usize;
+    if trees_dir.exists() {
+        for entry in fs::read_dir(&trees_dir).with_context(|| format!("read {}", trees_dir.display()))? {
+            let path = entry.context("read trees entry")?.path();
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            checked_trees += 1;
+            let valid = fs::read(&path).ok().filter(|bytes| {
+                deserialize_tree(bytes).is_ok()
+                    && parse_object_id(&name).is_ok_and(|id| blake3::hash(bytes).as_bytes() == &id)
+            });
+            if valid.is_none() {
+                problems.push(format!("corrupt tree {name}"));
+                if quarantine_bad {
+                    quarantine(&repo, "trees", &path, &name)?;
+                }
+            }
+        }
+    }
+
+    // ---- refs ---------------------------------------------------------------
+    let mut tips: Vec<(String, [u8; 32])> = Vec::new();
+    match repo.read_head() {
+        Ok(Some(id)) => tips.push(("HEAD".to_string(), id)),
+        Ok(None) => {}
+        Err(err) => problems.push(format!("unreadable HEAD: {err:#}")),
+    }
+    let refs_dir = repo.forge_dir.join("refs");
+    for entry in WalkDir::new(&refs_dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+    {
+        let rel = entry
+            .path()
+            .strip_prefix(&repo.forge_dir)
+            .context("strip forge dir")?
+            .to_string_lossy()
+            .replace('\\', "/");
+        match repo
+            .read_ref(&rel)
+            .and_then(|id| id.map(|id| repo.peel_to_commit(&id)).transpose())
+        {
+            Ok(Some(id)) => tips.push((rel, id)),
+            Ok(None) => {}
+            Err(err) => problems.push(format!("broken ref {rel}: {err:#}")),
+        }
+    }
+
+    // ---- reachable history --------------------------------------------------
+    let mut seen: HashSet<[u8; 32]> = HashSet::new();
+    let mut seen_trees: HashSet<[u8; 32]> = HashSet::new();
+    let mut verified_files: HashSet<[u8; 32]> = HashSet::new();
+    let mut on_remote: HashSet<[u8; 32]> = HashSet::new();
+    let mut queue: VecDeque<([u8; 32], String)> =
+        tips.into_iter().map(|(name, id)| (id, name)).collect();
+
+    while let Some((id, via)) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let id_hex = hex::encode(id);
+        if !manifests.contains(&id_hex) {
+            problems.push(format!("missing commit {id_hex} (reachable from {via})"));
+            continue;
+        }
+        let commit = match repo.read_commit(&id) {
+            Ok(commit) => commit,
+            Err(_) => continue,
+        };
+        for parent in &commit.parents {
+            queue.push_back((*parent, via.clone()));
+        }
+        let mut trees = vec![commit.tree];
+        while let Some(tree_id) = trees.pop() {
+            if !seen_trees.insert(tree_id) {
+                continue;
+            }
+            let tree = match repo.read_tree(&tree_id) {
+                Ok(tree) => tree,
+                Err(_) => {
+                    problems.push(format!(
+                        "missing or unreadable tree {} (in commit {})",
+                        hex::encode(tree_id),
+                        short_hex(&id)
+                    ));
+                    continue;
+                }
+            };
+            for entry in tree.entries {
+                let file = match entry.node {
+                    TreeNode::Dir(sub) => {
+                        trees.push(sub);
+                        continue;
+                    }
+                    TreeNode::File(file) => file,
+                };
+                if verified_files.contains(&file.file_hash) {
+                    continue;
+                }
+                // A partial clone's missing chunks are the remote's to keep.
+                if store.is_partial()
+                    && file.chunks.iter().any(|c| !present.contains(&c.hash) && !corrupt.contains(&c.hash))
+                {
+                    on_remote.insert(file.file_hash);
+                    continue;
+                }
+                match verify_file(&store, &present, &corrupt, &file) {
+                    Ok(()) => {
+                        verified_files.insert(file.file_hash);
+                    }
+                    Err(reason) => {
+                        problems.push(format!("{} @ {}: {reason}", file.path, short_hex(&id)))
+                    }
+                }
+            }
+        }
+    }
+
+    for problem in &problems {
+        println!("{problem}");
+    }
+    println!(
+        "fsck: checked {} chunks, {} manifests, {} trees, {} reachable commits, {} files",
+        checked_chunks,
+        manifests.len(),
+        checked_trees,
+        seen.len(),
+        verified_files.len()
+    );
+    if !on_remote.is_empty() {
+        println!("{} files left unchecked: their chunks are on the partial clone's remote", on_remote.len());
+    }
+    if !problems.is_empty() {
+        bail!("{} problem(s) found", problems.len());
+    }
+    println!("No problems found.");
+    Ok(())
+}
+
+/// Check every chunk of `entry` exists and that they reassemble to `file_hash`.
+fn verify_file(
+    store: &ChunkStore,
+    present: &HashSet<[u8; 32]>,
+    corrupt: &HashSet<[u8; 32]>,
+    entry: &FileEntry,
+) -> Result<(), String> {
+    let total: u64 = entry.chunks.iter().map(|c| c.length as u64).sum();
+    if total != entry.size {
+        return Err(format!(
+            "chunk lengths sum to {total}, expected {}",
+            entry.size
+        ));
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    for chunk in &entry.chunks {
+        if corrupt.contains(&chunk.hash) {
+            return Err(format!("uses corrupt chunk {}", hex::encode(chunk.hash)));
+        }
+        if !present.contains(&chunk.hash) {
+            return Err(format!("missing chunk {}", hex::encode(chunk.hash)));
+        }
+        let raw = store
+            .read_decompressed(&blake3::Hash::from(chunk.hash))
+            .map_err(|e| format!("{e:#}"))?;
+        hasher.update(&raw);
+    }
+
+    if hasher.finalize().as_bytes() != &entry.file_hash {
+        return Err("reassembled content does not match file hash".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+
+use crate::core::graph::reachable_commits;
+use crate::core::manifest::{deserialize_conflict, deserialize_file_entry, FileEntry, TreeNode};
+use crate::core::partial::evict;
+use crate::core::repository::parse_object_id;
+use crate::core::repository::Repository;
+use crate::core::tree;
+use crate::db::metadata::MetadataDb;
+use crate::store::pack::{list_packs, read_pack_index, rewrite_pack};
+use crate::util::human::human_bytes;
+
+fn count_refs(counts: &mut HashMap<[u8; 32], u32>, entry: &FileEntry) {
+    for chunk in &entry.chunks {
+        *counts.entry(chunk.hash).or_default() += 1;
+    }
+}
+
+/// Mark chunks and trees referenced from any reachable commit, the index or an
+/// in-progress merge, then sweep everything else that is older than the grace period.
+pub fn run(grace_hours: u64, dry_run: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+    let store = repo.chunk_store()?;
+
+    // ---- mark ---------------------------------------------------------------
+    let commits = reachable_commits(&repo)?;
+    let mut live: HashMap<[u8; 32], u32> = HashMap::new();
+    let mut live_trees: HashSet<[u8; 32]> = HashSet::new();
+    for id in &commits {
+        let root = repo.read_commit(id)?.tree;
+        // Shared subtrees are visited once, so each counts as a single reference.
+        tree::walk(&repo, &root, &mut live_trees, &mut |_, tree| {
+            for entry in &tree.entries {
+                if let TreeNode::File(file) = &entry.node {
+                    count_refs(&mut live, file);
+                }
+            }
+        })?;
+    }
+
+    // Working state is live too, but does not contribute to reference counts.
+    let mut pending: HashMap<[u8; 32], u32> = HashMap::new();
+    for (_, bytes) in db.get_staged_files()?.iter().chain(&db.get_all_tracked_files()?) {
+        count_refs(&mut pending, &deserialize_file_entry(bytes)?);
+    }
+    for (_, bytes) in db.get_conflicts()? {
+        let conflict = deserialize_conflict(&bytes)?;
+        for entry in [conflict.base, conflict.ours, conflict.theirs].iter().flatten() {
+            count_refs(&mut pending, entry);
+        }
+    }
+    for hash in pending.into_keys() {
+        live.entry(hash).or_insert(1);
+    }
+
+    // ---- sweep --------------------------------------------------------------
+    let cutoff = SystemTime::now() - Duration::from_secs(grace_hours * 3600);
+    let is_recent = |meta: &fs::Metadata| meta.modified().map(|m| m > cutoff).unwrap_or(true);
+
+    let mut kept = live.clone();
+    let mut removed_chunks = 0usize;
+    let mut reclaimed = 0u64;
+
+    for hash in store.list_loose()? {
+        let key = *hash.as_bytes();
+        if live.contains_key(&key) {
+            continue;
+        }
+        let path = store.chunk_path(&hash);
+        let meta = fs::metadata(&path).with_context(|| format!("stat {}", path.display()))?;
+        if is_recent(&meta) {
+            kept.insert(key, 1);
+            continue;
+        }
+        if !dry_run {
+            store.remove(&hash)?;
+        }
+        removed_chunks += 1;
+        reclaimed += meta.len();
+    }
+
+    let mut removed_trees = 0usize;
+    let trees_dir = repo.forge_dir.join("objects/trees");
+    if trees_dir.exists() {
+        for entry in fs::read_dir(&trees_dir).with_context(|| format!("read {}", trees_dir.display()))? {
+            let entry = entry.context("read trees entry")?;
+            let Ok(id) = parse_object_id(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+            let meta = entry.metadata().context("stat tree object")?;
+            if live_trees.contains(&id) || is_recent(&meta) {
+                continue;
+            }
+            if !dry_run {
+                fs::remove_file(entry.path())
+                    .with_context(|| format!("remove {}", entry.path().display()))?;
+            }
+            removed_trees += 1;
+            reclaimed += meta.len();
+        }
+    }
+
+    let mut rewritten_packs = 0usize;
+    for pack in list_packs(&repo.forge_dir.join("objects/packs"))? {
+        let meta = fs::metadata(&pack).with_context(|| format!("stat {}", pack.display()))?;
+        let index = read_pack_index(&pack)?;
+        if is_recent(&meta) {
+            for entry in &index.entries {
+                kept.entry(entry.hash).or_insert(1);
+            }
+            continue;
+        }
+
+        let dead: Vec<_> = index
+            .entries
+            .iter()
+            .filter(|e| !live.contains_key(&e.hash))
+            .collect();
+        if dead.is_empty() {
+            continue;
+        }
+        removed_chunks += dead.len();
+        rewritten_packs += 1;
+        if dry_run {
+            reclaimed += dead.iter().map(|e| e.length as u64).sum::<u64>();
+            continue;
+        }
+
+        let new_len = match rewrite_pack(&pack, &index, |hash| live.contains_key(hash))? {
+            Some(path) => fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            None => 0,
+        };
+        reclaimed += meta.len().saturating_sub(new_len);
+    }
+
+    if !dry_run {
+        db.rebuild_chunk_counts(&kept)?;
+    }
+
+    println!(
+        "{} {} unreachable chunks and {} trees ({}) across {} commits; {} pack(s) rewritten",
+        if dry_run { "Would remove" } else { "Removed" },
+        removed_chunks,
+        removed_trees,
+        human_bytes(reclaimed),
+        commits.len(),
+        rewritten_packs
+    );
+
+    // A partial clone also trims its chunk cache back under the limit.
+    if let Some(partial) = repo.read_config()?.partial.filter(|_| !dry_run) {
+        if let Some(limit) = partial.cache_size {
+            let evicted = evict(&repo, &store, &partial, limit, &HashSet::new())?;
+            println!(
+                "Evicted {} cached chunks ({}) available from '{}'",
+                evicted.chunks,
+                human_bytes(evicted.bytes),
+                partial.remote
+            );
+        }
+    }
+    Ok(())
+}
@@ -4,7 +4,12 @@ use anyhow::Result;
 
 use crate::core::repository::Repository;
 
-pub fn run(path: &str) -> Result<()> {
+pub fn run(path: &str, bare: bool) -> Result<()> {
+    if bare {
+        let repo = Repository::init_bare(Path::new(path))?;
+        println!("Initialized bare Forge repository at {}", repo.root.display());
+        return Ok(());
+    }
     let repo = Repository::init(Path::new(path))?;
     println!("Initialized Forge repository at {}", repo.root.display());
     Ok(())
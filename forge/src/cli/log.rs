@@ -1,10 +1,8 @@
-use std::fs;
-
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 
-use crate::core::manifest::deserialize_commit;
 use crate::core::repository::Repository;
+use crate::util::human::short_hex;
 
 fn ts_to_datetime(timestamp_ns: i64) -> DateTime<Utc> {
     let secs = timestamp_ns.div_euclid(1_000_000_000);
@@ -23,18 +21,20 @@ pub fn run(count: usize) -> Result<()> {
 
     for _ in 0..count {
         let hex_id = hex::encode(current);
-        let path = repo.forge_dir.join("manifests").join(&hex_id);
-        if !path.exists() {
+        if !repo.manifest_path(&current).exists() {
             break;
         }
-        let bytes = fs::read(&path).with_context(|| format!("read manifest {}", path.display()))?;
-        let commit = deserialize_commit(&bytes)?;
+        let commit = repo.read_commit(&current)?;
         let dt = ts_to_datetime(commit.timestamp_ns);
 
         println!("\x1b[33mcommit {}\x1b[0m", hex_id);
+        if commit.parents.len() > 1 {
+            let parents: Vec<_> = commit.parents.iter().map(short_hex).collect();
+            println!("Merge:  {}", parents.join(" "));
+        }
         println!("Author: {}", commit.author);
         println!("Date:   {}", dt.format("%Y-%m-%d %H:%M:%S UTC"));
-        println!("Files:  {}", commit.files.len());
+        println!("Files:  {}", repo.read_tree(&commit.tree)?.file_count);
         println!("\n    {}\n", commit.message);
 
         let Some(parent) = commit.parents.first().copied() else {
@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::checkout::materialize;
+use crate::cli::commit::{author_name, now_ns, write_commit};
+use crate::core::graph::{is_ancestor, merge_base};
+use crate::core::manifest::{serialize_conflict, serialize_file_entry, Commit, FileEntry, MergeConflict};
+use crate::core::tree::write_tree;
+use crate::core::repository::Repository;
+use crate::core::revision::resolve_revision;
+use crate::db::metadata::MetadataDb;
+use crate::util::human::short_hex;
+
+/// Side to take for every conflicting path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Side::Ours => "ours",
+            Side::Theirs => "theirs",
+        })
+    }
+}
+
+fn same_content(a: Option<&FileEntry>, b: Option<&FileEntry>) -> bool {
+    a.map(|e| e.file_hash) == b.map(|e| e.file_hash)
+}
+
+fn abort(repo: &Repository, db: &MetadataDb) -> Result<()> {
+    if repo.read_merge_head()?.is_none() {
+        bail!("there is no merge in progress");
+    }
+    let head = repo
+        .read_head()?
+        .ok_or_else(|| anyhow::anyhow!("cannot abort: HEAD has no commit"))?;
+    materialize(repo, db, &repo.read_commit_files(&head)?, true)?;
+    db.clear_conflicts()?;
+    repo.clear_merge_head()?;
+    println!("Merge aborted; working tree reset to {}", short_hex(&head));
+    Ok(())
+}
+
+pub fn run(
+    revision: Option<&str>,
+    strategy: Option<Side>,
+    message: Option<&str>,
+    abort_merge: bool,
+) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+
+    if abort_merge {
+        return abort(&repo, &db);
+    }
+    let Some(revision) = revision else {
+        bail!("nothing to merge; pass a branch, tag or commit");
+    };
+    if repo.read_merge_head()?.is_some() {
+        bail!("a merge is already in progress; resolve and commit it, or run `forge merge --abort`");
+    }
+
+    let theirs_id = resolve_revision(&repo, revision)?;
+    let theirs = repo.read_commit_files(&theirs_id)?;
+
+    let Some(ours_id) = repo.read_head()? else {
+        materialize(&repo, &db, &theirs, false)?;
+        repo.update_head(&theirs_id)?;
+        println!("Fast-forward to {}", short_hex(&theirs_id));
+        return Ok(());
+    };
+
+    if ours_id == theirs_id || is_ancestor(&repo, &theirs_id, &ours_id)? {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    if is_ancestor(&repo, &ours_id, &theirs_id)? {
+        let stats = materialize(&repo, &db, &theirs, false)?;
+        repo.update_head(&theirs_id)?;
+        println!(
+            "Fast-forward {} -> {} ({} written, {} removed)",
+            short_hex(&ours_id),
+            short_hex(&theirs_id),
+            stats.written,
+            stats.removed
+        );
+        return Ok(());
+    }
+
+    let base = match merge_base(&repo, &ours_id, &theirs_id)? {
+        Some(id) => repo.read_commit_files(&id)?,
+        None => BTreeMap::new(),
+    };
+    let ours = repo.read_commit_files(&ours_id)?;
+
+    let mut paths = BTreeSet::new();
+    paths.extend(base.keys().cloned());
+    paths.extend(ours.keys().cloned());
+    paths.extend(theirs.keys().cloned());
+
+    let mut merged = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for path in &paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let pick = if same_content(o, t) || same_content(b, t) {
+            o
+        } else if same_content(b, o) {
+            t
+        } else {
+            match strategy {
+                Some(Side::Ours) => o,
+                Some(Side::Theirs) => t,
+                None => {
+                    conflicts.push((
+                        path.clone(),
+                        MergeConflict {
+                            base: b.cloned(),
+                            ours: o.cloned(),
+                            theirs: t.cloned(),
+                        },
+                    ));
+                    o
+                }
+            }
+        };
+        if let Some(entry) = pick {
+            merged.insert(path.clone(), entry.clone());
+        }
+    }
+
+    let branch = repo.current_branch()?.unwrap_or_else(|| "HEAD".to_string());
+    let message = message
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Merge {revision} into {branch}"));
+    let draft = Commit {
+        id: [0u8; 32],
+        parents: vec![ours_id, theirs_id],
+        tree: write_tree(&repo, &merged)?,
+        message,
+        author: author_name(),
+        timestamp_ns: now_ns(),
+    };
+
+    let stats = materialize(&repo, &db, &merged, false)?;
+
+    if conflicts.is_empty() {
+        let commit = write_commit(&repo, &db, draft)?;
+        println!(
+            "Merge made {} ({} written, {} removed)",
+            short_hex(&commit.id),
+            stats.written,
+            stats.removed
+        );
+        return Ok(());
+    }
+
+    // Leave the merge in progress: stage every auto-merged change so `forge commit`
+    // picks it up, and park the conflicting paths (currently at our version).
+    for path in &paths {
+        match (ours.get(path), merged.get(path)) {
+            (Some(o), Some(m)) if o.file_hash == m.file_hash => {}
+            (_, Some(m)) => db.stage_file(path, &serialize_file_entry(m)?)?,
+            (Some(_), None) => db.stage_removal(path, None)?,
+            (None, None) => {}
+        }
+    }
+    for (path, conflict) in &conflicts {
+        db.record_conflict(path, &serialize_conflict(conflict)?)?;
+    }
+    repo.write_merge_head(&theirs_id)?;
+
+    println!("Conflicts (binary assets cannot be line-merged; pick a side):");
+    for (path, conflict) in &conflicts {
+        let kind = match (&conflict.ours, &conflict.theirs) {
+            (Some(_), Some(_)) => "both modified",
+            (Some(_), None) => "deleted by them",
+            (None, Some(_)) => "deleted by us",
+            (None, None) => "both deleted",
+        };
+        println!("U {path} ({kind})");
+    }
+    bail!(
+        "automatic merge failed; run `forge resolve <path> --ours|--theirs` for each conflict, then `forge commit`"
+    )
+}
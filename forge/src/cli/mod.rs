@@ -1,12 +1,27 @@
 pub mod add;
 pub mod auth;
+pub mod branch;
+pub mod cat;
 pub mod checkout;
+pub mod clone;
 pub mod commit;
 pub mod diff;
+pub mod fetch;
+pub mod fsck;
+pub mod gc;
 pub mod init;
 pub mod log;
+pub mod merge;
+pub mod mv;
 pub mod pull;
 pub mod push;
+pub mod remote;
+pub mod repack;
+pub mod resolve;
+pub mod rm;
+pub mod serve;
 pub mod status;
+pub mod switch;
+pub mod tag;
 pub mod train_dict;
 pub mod vibe_demo;
@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+
+use crate::core::manifest::{deserialize_file_entry, serialize_file_entry};
+use crate::core::repository::Repository;
+use crate::db::metadata::MetadataDb;
+
+/// Rename a tracked file or directory. Chunks are untouched, so moving a
+/// multi-gigabyte asset only rewrites its metadata.
+pub fn run(source: &str, destination: &str, force: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+
+    let src = repo.relative_path(source);
+    let mut dst = repo.relative_path(destination);
+    if src.is_empty() || dst.is_empty() {
+        bail!("cannot move the repository root");
+    }
+    if repo.root.join(&dst).is_dir() {
+        let name = src.rsplit('/').next().unwrap_or(&src);
+        dst = format!("{dst}/{name}");
+    }
+    if dst == src || dst.starts_with(&format!("{src}/")) {
+        bail!("cannot move '{src}' into itself");
+    }
+
+    let removed: BTreeSet<String> = db
+        .get_staged_removals()?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    let mut view: BTreeMap<String, Vec<u8>> = db
+        .get_all_tracked_files()?
+        .into_iter()
+        .filter(|(path, _)| !removed.contains(path))
+        .collect();
+    let tracked: BTreeSet<String> = view.keys().cloned().collect();
+    view.extend(db.get_staged_files()?);
+
+    let prefix = format!("{src}/");
+    let moves: Vec<(String, String)> = view
+        .keys()
+        .filter_map(|path| {
+            if *path == src {
+                Some((path.clone(), dst.clone()))
+            } else {
+                path.strip_prefix(&prefix)
+                    .map(|rest| (path.clone(), format!("{dst}/{rest}")))
+            }
+        })
+        .collect();
+    if moves.is_empty() {
+        bail!("'{source}' is not under version control");
+    }
+
+    let src_abs = repo.root.join(&src);
+    let dst_abs = repo.root.join(&dst);
+    if !force {
+        if dst_abs.exists() {
+            bail!("destination '{dst}' already exists (use --force to overwrite)");
+        }
+        if let Some((_, to)) = moves.iter().find(|(_, to)| view.contains_key(to)) {
+            bail!("destination '{to}' is already tracked (use --force to overwrite)");
+        }
+    }
+
+    if src_abs.exists() {
+        if let Some(parent) = dst_abs.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        fs::rename(&src_abs, &dst_abs)
+            .with_context(|| format!("rename {} -> {}", src_abs.display(), dst_abs.display()))?;
+    }
+
+    for (from, to) in &moves {
+        let mut entry = deserialize_file_entry(&view[from])?;
+        entry.path = to.clone();
+        db.stage_file(to, &serialize_file_entry(&entry)?)?;
+        if tracked.contains(from) {
+            db.stage_removal(from, Some(to))?;
+        } else {
+            db.unstage_file(from)?;
+        }
+        println!("Renamed {from} -> {to}");
+    }
+
+    Ok(())
+}
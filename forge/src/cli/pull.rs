@@ -1,7 +1,9 @@
 use std::path::Path;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
+use crate::cli::fetch::fetch_remote;
+use crate::core::remote::{self, Remote, TransportKind};
 use crate::core::repository::Repository;
 use crate::db::metadata::MetadataDb;
 
@@ -12,9 +14,29 @@ struct MirrorRecord {
     url: String,
 }
 
+/// Fetch from a `quic` remote and merge its copy of the current branch.
+fn pull_quic(repo: &Repository, remote: &Remote) -> Result<()> {
+    let branch = repo
+        .current_branch()?
+        .ok_or_else(|| anyhow!("HEAD is detached; switch to a branch to pull"))?;
+    let refs = fetch_remote(repo, remote)?;
+    let tip = refs
+        .get(&format!("refs/heads/{branch}"))
+        .ok_or_else(|| anyhow!("{} has no branch '{branch}'", remote.label()))?;
+    crate::cli::merge::run(Some(&hex::encode(tip)), None, None, false)
+}
+
 pub fn run(remote: &str) -> Result<()> {
     let cwd = std::env::current_dir().context("get cwd")?;
     let repo = Repository::discover(&cwd)?;
+    // Mirror remotes, and the unconfigured `origin` of older repositories,
+    // restore files from the mirror records of earlier pushes.
+    match remote::resolve(&repo, remote)? {
+        Some(remote) if remote.config.kind() == TransportKind::Quic => return pull_quic(&repo, &remote),
+        Some(_) => {}
+        None if repo.read_config()?.remotes.is_empty() => {}
+        None => bail!("no remote named '{remote}'"),
+    }
 
     let db = MetadataDb::open(&repo.metadata_db_path())?;
     let all_targets = db.get_all_mirror_targets()?;
@@ -1,9 +1,9 @@
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
-use crate::core::manifest::deserialize_commit;
+use crate::core::remote::{self, Remote, RemoteConfig, TransportKind};
 use crate::core::repository::Repository;
 use crate::db::metadata::MetadataDb;
 use crate::mirror::auth::AuthStore;
@@ -19,8 +19,8 @@ use crate::mirror::backends::{
     youtube::YouTubeBackend,
 };
 use crate::mirror::{MirrorBackend, MirrorDispatcher, MirrorTarget};
-use crate::store::cas::ChunkStore;
-use crate::store::compression;
+use crate::transport::{client, quic};
+use crate::util::human::{human_bytes, short_hex};
 
 /// Serialisable record stored per-file in MIRRORS_TABLE.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -38,36 +38,98 @@ impl From<(&str, &MirrorTarget)> for MirrorRecord {
     }
 }
 
+/// Push the current branch to a `quic` remote.
+fn push_quic(repo: &Repository, remote: &Remote) -> Result<()> {
+    let branch = repo
+        .current_branch()?
+        .ok_or_else(|| anyhow!("HEAD is detached; switch to a branch to push"))?;
+    let local = repo
+        .read_head()?
+        .ok_or_else(|| anyhow!("nothing to push — no commits yet"))?;
+    let refname = format!("refs/heads/{branch}");
+
+    let rt = tokio::runtime::Runtime::new().context("create tokio runtime")?;
+    rt.block_on(async {
+        let mut session = quic::open_session(repo, &remote.config).await?;
+        let refs = client::handshake(&mut session.channel).await?;
+        let old = refs.get(&refname);
+        if old == Some(local) {
+            println!("Everything up-to-date");
+            return session.channel.finish().await;
+        }
+
+        let stats = client::push(repo, &mut session.channel, &refname, &local, old.as_ref(), &refs.refs).await?;
+        session.channel.finish().await?;
+        println!(
+            "Pushed {branch} to {}: {} -> {} ({} commits, {} trees, {} chunks, {})",
+            remote.label(),
+            old.as_ref().map(short_hex).unwrap_or_else(|| "(new)".to_string()),
+            short_hex(&local),
+            stats.commits,
+            stats.trees,
+            stats.chunks,
+            human_bytes(stats.chunk_bytes)
+        );
+        Ok(())
+    })
+}
+
 pub fn run(remote: &str, mirror: Option<&str>, pro: bool) -> Result<()> {
     let cwd = std::env::current_dir().context("get cwd")?;
     let repo = Repository::discover(&cwd)?;
 
+    // Resolve the remote. Before named remotes existed `origin` was implicit,
+    // so with none configured it still means the free mirrors.
+    let remote = match remote::resolve(&repo, remote)? {
+        Some(remote) => remote,
+        None if repo.read_config()?.remotes.is_empty() => Remote {
+            name: Some(remote.to_string()),
+            config: RemoteConfig::default(),
+        },
+        None => bail!("no remote named '{remote}' (add one with `forge remote add {remote} <url>`)"),
+    };
+
+    // Mirror flags select the mirror backends even for a QUIC remote.
+    let explicit = mirror.map(str::to_string).or_else(|| pro.then(|| "pro".to_string()));
+    if explicit.is_none() && remote.config.kind() == TransportKind::Quic {
+        return push_quic(&repo, &remote);
+    }
+    let mirror_modes = match explicit {
+        Some(mode) => vec![mode],
+        None if remote.config.mirrors.is_empty() => vec!["all-free".to_string()],
+        None => remote.config.mirrors.clone(),
+    };
+    let mirror_mode = mirror_modes.join(", ");
+
     // Build the tokio runtime (all mirror backends are async).
     let rt = tokio::runtime::Runtime::new().context("create tokio runtime")?;
 
-    // Determine mode ----------------------------------------------------------
-    let mirror_mode = mirror.unwrap_or(if pro { "pro" } else { "all-free" });
-
     // Read latest commit to know what files to push --------------------------
     let head_id = repo
         .read_head()?
         .ok_or_else(|| anyhow::anyhow!("nothing to push — no commits yet"))?;
     let head_hex = hex::encode(head_id);
 
-    let manifest_path = repo.forge_dir.join("manifests").join(&head_hex);
-    let manifest_bytes = std::fs::read(&manifest_path)
-        .with_context(|| format!("read manifest {}", manifest_path.display()))?;
-    let commit = deserialize_commit(&manifest_bytes)?;
+    let files = repo.read_commit_files(&head_id)?;
 
     println!(
         "Pushing commit {} ({} files) → mirror: {mirror_mode}",
         &head_hex[..12],
-        commit.files.len(),
+        files.len(),
     );
 
     // Build backends ----------------------------------------------------------
     let auth = Arc::new(AuthStore::open(&repo.forge_dir)?);
-    let backends = build_backends(&auth, mirror_mode, &repo)?;
+    let github_repo = remote.config.github_repo().unwrap_or_else(default_github_repo);
+    let mut backends: Vec<Arc<dyn MirrorBackend>> = Vec::new();
+    for mode in &mirror_modes {
+        for backend in build_backends(&auth, mode, &github_repo)? {
+            // Modes overlap (`pro` includes the free backends); upload once.
+            if backends.iter().all(|b| b.name() != backend.name()) {
+                backends.push(backend);
+            }
+        }
+    }
 
     if backends.is_empty() {
         bail!(
@@ -77,20 +139,19 @@ pub fn run(remote: &str, mirror: Option<&str>, pro: bool) -> Result<()> {
     }
 
     let dispatcher = MirrorDispatcher::new(backends);
-    let store = ChunkStore::new(repo.forge_dir.join("objects/chunks"));
+    let store = repo.chunk_store()?;
     let db = MetadataDb::open(&repo.metadata_db_path())?;
 
     let mut total_ok: usize = 0;
     let mut total_err: usize = 0;
 
     // Mirror each file --------------------------------------------------------
-    for entry in &commit.files {
+    for entry in files.values() {
         // Reassemble the whole file from chunks
         let mut data = Vec::with_capacity(entry.size as usize);
         for chunk_ref in &entry.chunks {
             let hash = blake3::Hash::from(chunk_ref.hash);
-            let compressed = store.read(&hash)?;
-            let raw = compression::decompress(&compressed)?;
+            let raw = store.read_decompressed(&hash)?;
             data.extend_from_slice(&raw);
         }
 
@@ -124,7 +185,7 @@ pub fn run(remote: &str, mirror: Option<&str>, pro: bool) -> Result<()> {
     if total_err == 0 {
         println!(
             "Push complete ✓  {total_ok} mirror(s) across {} files. No errors.",
-            commit.files.len()
+            files.len()
         );
     } else {
         println!(
@@ -132,11 +193,6 @@ pub fn run(remote: &str, mirror: Option<&str>, pro: bool) -> Result<()> {
         );
     }
 
-    // Also note the remote for future QUIC transport (informational)
-    if remote != "origin" {
-        println!("(remote '{remote}' noted — QUIC transport not yet available)");
-    }
-
     Ok(())
 }
 
@@ -144,7 +200,7 @@ pub fn run(remote: &str, mirror: Option<&str>, pro: bool) -> Result<()> {
 fn build_backends(
     auth: &Arc<AuthStore>,
     mode: &str,
-    repo: &Repository,
+    github_repo: &str,
 ) -> Result<Vec<Arc<dyn MirrorBackend>>> {
     let mut out: Vec<Arc<dyn MirrorBackend>> = Vec::new();
 
@@ -158,8 +214,7 @@ fn build_backends(
             if has_auth("soundcloud") { out.push(Arc::new(SoundCloudBackend::new(Arc::clone(auth)))); }
             if has_auth("sketchfab")  { out.push(Arc::new(SketchfabBackend::new(Arc::clone(auth)))); }
             if has_auth("github") {
-                let github_repo = read_github_repo(repo)?;
-                out.push(Arc::new(GitHubBackend::new(Arc::clone(auth), github_repo)));
+                out.push(Arc::new(GitHubBackend::new(Arc::clone(auth), github_repo.to_string())));
             }
         }
         "pro" => {
@@ -179,8 +234,7 @@ fn build_backends(
             if has_auth("soundcloud") { out.push(Arc::new(SoundCloudBackend::new(Arc::clone(auth)))); }
             if has_auth("sketchfab")  { out.push(Arc::new(SketchfabBackend::new(Arc::clone(auth)))); }
             if has_auth("github") {
-                let github_repo = read_github_repo(repo)?;
-                out.push(Arc::new(GitHubBackend::new(Arc::clone(auth), github_repo)));
+                out.push(Arc::new(GitHubBackend::new(Arc::clone(auth), github_repo.to_string())));
             }
         }
         // Single backend by name
@@ -191,8 +245,7 @@ fn build_backends(
                 "soundcloud" if has_auth("soundcloud") => out.push(Arc::new(SoundCloudBackend::new(Arc::clone(auth)))),
                 "sketchfab"  if has_auth("sketchfab")  => out.push(Arc::new(SketchfabBackend::new(Arc::clone(auth)))),
                 "github"     if has_auth("github")     => {
-                    let github_repo = read_github_repo(repo)?;
-                    out.push(Arc::new(GitHubBackend::new(Arc::clone(auth), github_repo)));
+                    out.push(Arc::new(GitHubBackend::new(Arc::clone(auth), github_repo.to_string())));
                 }
                 "gdrive"     if has_auth("gdrive")     => out.push(Arc::new(GoogleDriveBackend::new(Arc::clone(auth)))),
                 "dropbox"    if has_auth("dropbox")     => out.push(Arc::new(DropboxBackend::new(Arc::clone(auth)))),
@@ -216,20 +269,10 @@ fn build_backends(
     Ok(out)
 }
 
-/// Read the github mirror repo from config.toml or default from `forge auth` extra field.
-fn read_github_repo(repo: &Repository) -> Result<String> {
-    // Try config.toml first
-    let cfg = repo.read_config()?;
-    if let Some(url) = &cfg.remote_url {
-        // Extract owner/repo from a github URL
-        if let Some(path) = url.strip_prefix("https://github.com/") {
-            let repo_path = path.trim_end_matches('/').trim_end_matches(".git");
-            return Ok(repo_path.to_string());
-        }
-    }
-    // Fallback: use author/forge-mirror
+/// GitHub mirror repo for remotes that name none: `<author>/forge-mirror`.
+fn default_github_repo() -> String {
     let author = std::env::var("GIT_AUTHOR_NAME")
         .or_else(|_| std::env::var("USER"))
         .unwrap_or_else(|_| "forge-user".to_string());
-    Ok(format!("{author}/forge-mirror"))
+    format!("{author}/forge-mirror")
 }
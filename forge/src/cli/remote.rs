@@ -0,0 +1,91 @@
+use anyhow::{bail, Context, Result};
+
+use crate::core::remote::{validate_remote_name, RemoteConfig, TransportKind};
+use crate::core::repository::Repository;
+
+fn open_repo() -> Result<Repository> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    Repository::discover(&cwd)
+}
+
+/// Register `name`. Mirror settings only apply to mirror remotes, and `ca`
+/// only to `quic://` ones.
+pub fn add(
+    name: &str,
+    url: &str,
+    mirrors: &[String],
+    github_repo: Option<&str>,
+    ca: Option<&str>,
+) -> Result<()> {
+    validate_remote_name(name)?;
+    let repo = open_repo()?;
+    let mut config = repo.read_config()?;
+    if config.remotes.contains_key(name) {
+        bail!("remote '{name}' already exists");
+    }
+
+    let mut remote = RemoteConfig::new(url);
+    match remote.kind() {
+        TransportKind::Quic if !mirrors.is_empty() || github_repo.is_some() => {
+            bail!("--mirror and --github-repo only apply to mirror remotes, not '{url}'")
+        }
+        TransportKind::Mirror if ca.is_some() => bail!("--ca only applies to quic:// remotes"),
+        _ => {}
+    }
+    remote.mirrors = mirrors.to_vec();
+    remote.github_repo = github_repo.map(str::to_string);
+    remote.ca = ca.map(str::to_string);
+
+    config.remotes.insert(name.to_string(), remote);
+    repo.write_config(&config)?;
+    println!("Added remote {name} -> {url}");
+    Ok(())
+}
+
+pub fn list(verbose: bool) -> Result<()> {
+    let repo = open_repo()?;
+    for (name, remote) in repo.read_config()?.remotes {
+        if !verbose {
+            println!("{name}");
+            continue;
+        }
+        let mut details = vec![remote.kind().as_str().to_string()];
+        if remote.kind() == TransportKind::Mirror {
+            let mirrors = if remote.mirrors.is_empty() {
+                "all-free".to_string()
+            } else {
+                remote.mirrors.join(",")
+            };
+            details.push(format!("mirrors: {mirrors}"));
+        }
+        if let Some(github_repo) = &remote.github_repo {
+            details.push(format!("github: {github_repo}"));
+        }
+        if let Some(ca) = &remote.ca {
+            details.push(format!("ca: {ca}"));
+        }
+        println!("{name:<16} {} ({})", remote.url, details.join("; "));
+    }
+    Ok(())
+}
+
+/// Forget `name` along with its remote-tracking refs.
+pub fn remove(name: &str) -> Result<()> {
+    let repo = open_repo()?;
+    let mut config = repo.read_config()?;
+    if config.partial.as_ref().is_some_and(|partial| partial.remote == name) {
+        bail!("'{name}' supplies this partial clone's chunks and cannot be removed");
+    }
+    if config.remotes.remove(name).is_none() {
+        bail!("no remote named '{name}'");
+    }
+    repo.write_config(&config)?;
+
+    let prefix = format!("refs/remotes/{name}");
+    let tracking = repo.list_refs(&prefix)?;
+    for (branch, _) in &tracking {
+        repo.delete_ref(&format!("{prefix}/{branch}"))?;
+    }
+    println!("Removed remote {name} ({} remote-tracking refs deleted)", tracking.len());
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use std::fs;
+
+use anyhow::{Context, Result};
+
+use crate::core::repository::Repository;
+use crate::store::cas::ChunkStore;
+use crate::store::pack::{create_pack, list_packs, pack_name, read_pack_index, remove_pack};
+use crate::util::human::human_bytes;
+
+/// Move loose chunks into packs of at most `max_pack_mb` MiB. With `all`, the
+/// existing packs are folded in as well so the repository ends up with as few
+/// packs as the size limit allows.
+pub fn run(all: bool, max_pack_mb: u64) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let store = repo.chunk_store()?;
+    fs::create_dir_all(&store.pack_dir).with_context(|| format!("create {}", store.pack_dir.display()))?;
+
+    let loose = store.list_loose()?;
+    let old_packs = if all { list_packs(&store.pack_dir)? } else { Vec::new() };
+    if loose.is_empty() && old_packs.len() <= 1 {
+        println!("Nothing to repack.");
+        return Ok(());
+    }
+
+    // (hash, compressed length) for everything going into the new packs.
+    let mut seen: HashSet<[u8; 32]> = HashSet::new();
+    let mut pending: Vec<([u8; 32], u64)> = Vec::new();
+    for hash in &loose {
+        let len = fs::metadata(store.chunk_path(hash))
+            .with_context(|| format!("stat chunk {}", hash.to_hex()))?
+            .len();
+        if seen.insert(*hash.as_bytes()) {
+            pending.push((*hash.as_bytes(), len));
+        }
+    }
+    for pack in &old_packs {
+        for entry in read_pack_index(pack)?.entries {
+            if seen.insert(entry.hash) {
+                pending.push((entry.hash, entry.length as u64));
+            }
+        }
+    }
+
+    let limit = max_pack_mb.max(1) * 1024 * 1024;
+    let mut groups: Vec<Vec<[u8; 32]>> = Vec::new();
+    let mut current: Vec<[u8; 32]> = Vec::new();
+    let mut current_size = 0u64;
+    for (hash, len) in pending {
+        if !current.is_empty() && current_size + len > limit {
+            groups.push(std::mem::take(&mut current));
+            current_size = 0;
+        }
+        current.push(hash);
+        current_size += len;
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+
+    let mut written = Vec::new();
+    let mut packed_chunks = 0usize;
+    let mut packed_bytes = 0u64;
+    for group in &groups {
+        let target = store.pack_dir.join(pack_name(group));
+        if !target.exists() {
+            create_pack(&store, group, &target)?;
+        }
+        packed_chunks += group.len();
+        packed_bytes += fs::metadata(&target).map(|m| m.len()).unwrap_or(0);
+        written.push(target);
+    }
+
+    // Everything is safely packed; drop the old copies.
+    for pack in &old_packs {
+        if !written.contains(pack) {
+            remove_pack(pack)?;
+        }
+    }
+    for hash in &loose {
+        store.remove(hash)?;
+    }
+    prune_empty_shards(&store)?;
+    store.reload_packs();
+
+    println!(
+        "Packed {} chunks ({}) into {} pack(s); removed {} loose chunks{}",
+        packed_chunks,
+        human_bytes(packed_bytes),
+        written.len(),
+        loose.len(),
+        if all {
+            format!(", replaced {} pack(s)", old_packs.len())
+        } else {
+            String::new()
+        }
+    );
+    Ok(())
+}
+
+fn prune_empty_shards(store: &ChunkStore) -> Result<()> {
+    if !store.base_dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(&store.base_dir).with_context(|| format!("read {}", store.base_dir.display()))? {
+        let path = entry.context("read shard entry")?.path();
+        if path.is_dir() {
+            // Fails harmlessly when the shard still holds temp files.
+            let _ = fs::remove_dir(&path);
+        }
+    }
+    Ok(())
+}
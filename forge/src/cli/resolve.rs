@@ -0,0 +1,61 @@
+use std::fs;
+use std::io::ErrorKind;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::checkout::write_entry;
+use crate::cli::merge::Side;
+use crate::core::manifest::{deserialize_conflict, serialize_file_entry};
+use crate::core::repository::Repository;
+use crate::db::metadata::MetadataDb;
+
+/// Resolve merge conflicts by taking one side wholesale for each path.
+pub fn run(paths: &[String], side: Side) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+    let store = repo.chunk_store()?;
+
+    let conflicts = db.get_conflicts()?;
+    if conflicts.is_empty() {
+        bail!("there are no unresolved conflicts");
+    }
+
+    for raw in paths {
+        let rel = repo.relative_path(raw);
+        let Some((path, bytes)) = conflicts.iter().find(|(path, _)| *path == rel) else {
+            bail!("'{rel}' has no unresolved conflict");
+        };
+        let conflict = deserialize_conflict(bytes)?;
+        let chosen = match side {
+            Side::Ours => conflict.ours,
+            Side::Theirs => conflict.theirs,
+        };
+
+        match chosen {
+            Some(entry) => {
+                write_entry(&repo, &store, &entry)?;
+                db.stage_file(path, &serialize_file_entry(&entry)?)?;
+                println!("Resolved {path} using {side}");
+            }
+            None => {
+                let abs = repo.root.join(path);
+                match fs::remove_file(&abs) {
+                    Ok(_) => {}
+                    Err(err) if err.kind() == ErrorKind::NotFound => {}
+                    Err(err) => return Err(err).with_context(|| format!("remove {}", abs.display())),
+                }
+                db.stage_removal(path, None)?;
+                println!("Resolved {path} using {side} (deleted)");
+            }
+        }
+    }
+
+    let remaining = db.get_conflicts()?.len();
+    if remaining == 0 {
+        println!("All conflicts resolved; run `forge commit` to conclude the merge.");
+    } else {
+        println!("{remaining} conflict(s) remaining.");
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::checkout::remove_empty_parents;
+use crate::core::hash::hash_file;
+use crate::core::manifest::{deserialize_file_entry, FileEntry};
+use crate::core::repository::Repository;
+use crate::db::metadata::MetadataDb;
+
+pub fn run(paths: &[String], cached: bool, force: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+
+    let mut tracked = BTreeMap::new();
+    for (path, bytes) in db.get_all_tracked_files()? {
+        tracked.insert(path, deserialize_file_entry(&bytes)?);
+    }
+    let mut staged = BTreeMap::new();
+    for (path, bytes) in db.get_staged_files()? {
+        staged.insert(path, deserialize_file_entry(&bytes)?);
+    }
+    let removed: BTreeSet<String> = db
+        .get_staged_removals()?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+
+    let mut targets = BTreeSet::new();
+    for raw in paths {
+        let rel = repo.relative_path(raw);
+        let prefix = format!("{rel}/");
+        let before = targets.len();
+        targets.extend(
+            tracked
+                .keys()
+                .chain(staged.keys())
+                .filter(|p| rel.is_empty() || **p == rel || p.starts_with(&prefix))
+                .filter(|p| !removed.contains(*p))
+                .cloned(),
+        );
+        if targets.len() == before {
+            bail!("pathspec '{raw}' did not match any tracked files");
+        }
+    }
+
+    if !cached && !force {
+        for path in &targets {
+            let abs = repo.root.join(path);
+            let Some(entry): Option<&FileEntry> = staged.get(path).or_else(|| tracked.get(path)) else {
+                continue;
+            };
+            if abs.is_file() && hash_file(&abs)?.as_bytes() != &entry.file_hash {
+                bail!("'{path}' has local modifications (use --cached to keep the file, or --force)");
+            }
+        }
+    }
+
+    for path in &targets {
+        if tracked.contains_key(path) {
+            db.stage_removal(path, None)?;
+        } else {
+            db.unstage_file(path)?;
+        }
+
+        if !cached {
+            let abs = repo.root.join(path);
+            match fs::remove_file(&abs) {
+                Ok(_) => remove_empty_parents(&repo.root, &abs),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("remove {}", abs.display())),
+            }
+        }
+        println!("rm '{path}'");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::core::repository::Repository;
+use crate::transport::quic;
+
+/// Serve a bare repository over QUIC until interrupted. Without `--cert` and
+/// `--key`, a self-signed certificate is generated inside the repository.
+pub fn run(path: &str, listen: &str, cert: Option<&str>, key: Option<&str>) -> Result<()> {
+    let repo = Repository::open_bare(Path::new(path))?;
+    let (cert, key) = match (cert, key) {
+        (Some(cert), Some(key)) => (PathBuf::from(cert), PathBuf::from(key)),
+        (None, None) => quic::ensure_certificate(&repo)?,
+        _ => bail!("--cert and --key must be given together"),
+    };
+
+    let rt = tokio::runtime::Runtime::new().context("create tokio runtime")?;
+    rt.block_on(quic::start_server(repo, listen, &cert, &key))
+}
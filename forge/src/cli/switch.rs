@@ -0,0 +1,50 @@
+use anyhow::{bail, Context, Result};
+
+use crate::cli::checkout::materialize;
+use crate::core::repository::{validate_ref_name, Repository};
+use crate::db::metadata::MetadataDb;
+use crate::util::human::short_hex;
+
+pub fn run(branch: &str, create: bool, force: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+    let db = MetadataDb::open(&repo.metadata_db_path())?;
+
+    validate_ref_name(branch)?;
+    let reference = format!("refs/heads/{branch}");
+    let current = repo.read_head()?;
+
+    if create {
+        if repo.read_ref(&reference)?.is_some() {
+            bail!("a branch named '{branch}' already exists");
+        }
+        if let Some(id) = current {
+            repo.write_ref(&reference, &id)?;
+        }
+        repo.set_head_ref(&reference)?;
+        println!("Switched to a new branch '{branch}'");
+        return Ok(());
+    }
+
+    let Some(target) = repo.read_ref(&reference)? else {
+        bail!("branch '{branch}' not found (use `forge switch -c {branch}` to create it)");
+    };
+
+    if repo.current_branch()?.as_deref() == Some(branch) {
+        println!("Already on '{branch}'");
+        return Ok(());
+    }
+
+    let files = repo.read_commit_files(&target)?;
+    let stats = materialize(&repo, &db, &files, force)?;
+    repo.set_head_ref(&reference)?;
+
+    println!(
+        "Switched to branch '{branch}' at {} ({} files: {} written, {} removed)",
+        short_hex(&target),
+        files.len(),
+        stats.written,
+        stats.removed
+    );
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use anyhow::{bail, Context, Result};
+
+use crate::cli::commit::{author_name, now_ns};
+use crate::core::manifest::{serialize_tag, Tag};
+use crate::core::repository::{validate_ref_name, Repository};
+use crate::core::revision::resolve_revision;
+use crate::util::human::short_hex;
+
+fn list(repo: &Repository) -> Result<()> {
+    for (name, id) in repo.list_refs("refs/tags")? {
+        match repo.read_tag(&id)? {
+            Some(tag) => println!(
+                "{name:<24} {} {}",
+                short_hex(&tag.target),
+                tag.message.lines().next().unwrap_or_default()
+            ),
+            None => println!("{name:<24} {}", short_hex(&id)),
+        }
+    }
+    Ok(())
+}
+
+pub fn run(
+    name: Option<&str>,
+    target: Option<&str>,
+    message: Option<&str>,
+    annotate: bool,
+    delete: bool,
+    force: bool,
+) -> Result<()> {
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd)?;
+
+    let Some(name) = name else {
+        if delete {
+            bail!("tag name required for --delete");
+        }
+        return list(&repo);
+    };
+    validate_ref_name(name)?;
+    let reference = format!("refs/tags/{name}");
+
+    if delete {
+        if !repo.delete_ref(&reference)? {
+            bail!("tag '{name}' not found");
+        }
+        println!("Deleted tag {name}");
+        return Ok(());
+    }
+
+    if !force && repo.read_ref(&reference)?.is_some() {
+        bail!("tag '{name}' already exists (use --force to move it)");
+    }
+
+    let commit_id = resolve_revision(&repo, target.unwrap_or("HEAD"))?;
+
+    if !annotate && message.is_none() {
+        repo.write_ref(&reference, &commit_id)?;
+        println!("Tagged {} as {name}", short_hex(&commit_id));
+        return Ok(());
+    }
+
+    let Some(message) = message else {
+        bail!("annotated tags need a message (-m)");
+    };
+
+    let draft = Tag {
+        id: [0u8; 32],
+        target: commit_id,
+        name: name.to_string(),
+        message: message.to_string(),
+        tagger: author_name(),
+        timestamp_ns: now_ns(),
+    };
+    let tag_id = *blake3::hash(&serialize_tag(&draft)?).as_bytes();
+    let tag = Tag { id: tag_id, ..draft };
+
+    repo.write_tag(&tag)?;
+    repo.write_ref(&reference, &tag_id)?;
+    println!("Tagged {} as {name} (annotated)", short_hex(&commit_id));
+    Ok(())
+}
@@ -1,13 +1,21 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use walkdir::WalkDir;
 
+use crate::core::repository::Repository;
 use crate::store::compression;
 
-pub fn run(file_type: &str, samples_dir: &str, output: &str) -> Result<()> {
-    let ext = file_type.trim_start_matches('.').to_ascii_lowercase();
+/// Train a dictionary from sample files with the given extension. Inside a
+/// repository it is archived as `.forge/dictionaries/<id>.dict`, where it
+/// stays to decode the chunks compressed with it, and registered in
+/// `config.toml` so `forge add` uses it for matching files from now on.
+/// `output` writes an extra copy, and is required outside a repository.
+pub fn run(file_type: &str, samples_dir: &str, output: Option<&str>) -> Result<()> {
+    let ext = compression::dictionary_key(file_type);
+    let cwd = std::env::current_dir().context("get current dir")?;
+    let repo = Repository::discover(&cwd).ok();
     let mut samples = Vec::new();
 
     for entry in WalkDir::new(samples_dir)
@@ -32,16 +40,46 @@ pub fn run(file_type: &str, samples_dir: &str, output: &str) -> Result<()> {
         samples.push(bytes.into_iter().take(128 * 1024).collect::<Vec<u8>>());
     }
 
-    let dict = compression::train_dictionary(&samples, 112_640)?;
-    let out_path = Path::new(output);
-    if let Some(parent) = out_path.parent() {
-        fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+    if samples.is_empty() {
+        bail!("no .{ext} samples found under {samples_dir}");
     }
-    fs::write(out_path, &dict).with_context(|| format!("write dict {}", out_path.display()))?;
+
+    let dict_size = match &repo {
+        Some(repo) => repo.read_config()?.dict_size,
+        None => 112_640,
+    };
+    let dict = compression::train_dictionary(&samples, dict_size)?;
+    let Some(id) = compression::dictionary_id(&dict) else {
+        bail!("zstd did not produce a dictionary with an id");
+    };
     println!(
-        "Trained dictionary from {} samples -> {} bytes",
+        "Trained dictionary {id:08x} from {} samples -> {} bytes",
         samples.len(),
         dict.len()
     );
+
+    if let Some(output) = output {
+        write_dict(&PathBuf::from(output), &dict)?;
+    }
+    let Some(repo) = repo else {
+        if output.is_none() {
+            bail!("--output is required outside a Forge repository");
+        }
+        return Ok(());
+    };
+
+    let stored = format!("dictionaries/{}", compression::dictionary_file_name(id));
+    write_dict(&repo.forge_dir.join(&stored), &dict)?;
+    let mut cfg = repo.read_config()?;
+    cfg.dictionaries.insert(ext.clone(), stored.clone());
+    repo.write_config(&cfg)?;
+    println!("Registered {stored} for .{ext} files in config.toml");
     Ok(())
 }
+
+fn write_dict(path: &Path, dict: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+    }
+    fs::write(path, dict).with_context(|| format!("write dict {}", path.display()))
+}
@@ -40,7 +40,7 @@ pub fn run() -> Result<()> {
 
     // Step 1: init
     println!("  ─── forge init . ───");
-    cli::init::run(".")?;
+    cli::init::run(".", false)?;
     println!();
 
     // Step 2: add all files
@@ -0,0 +1,66 @@
+use std::collections::{HashSet, VecDeque};
+
+use anyhow::Result;
+
+use crate::core::repository::Repository;
+
+/// All commits reachable from `start`, including `start` itself.
+pub fn ancestors(repo: &Repository, start: &[u8; 32]) -> Result<HashSet<[u8; 32]>> {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([*start]);
+    while let Some(id) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        queue.extend(repo.read_commit(&id)?.parents);
+    }
+    Ok(seen)
+}
+
+pub fn is_ancestor(repo: &Repository, ancestor: &[u8; 32], descendant: &[u8; 32]) -> Result<bool> {
+    Ok(ancestors(repo, descendant)?.contains(ancestor))
+}
+
+/// Best common ancestor of `a` and `b`: a shared ancestor that no other shared
+/// ancestor descends from. Criss-cross histories pick the most recent candidate.
+pub fn merge_base(repo: &Repository, a: &[u8; 32], b: &[u8; 32]) -> Result<Option<[u8; 32]>> {
+    let from_a = ancestors(repo, a)?;
+    let common: HashSet<[u8; 32]> = ancestors(repo, b)?
+        .into_iter()
+        .filter(|id| from_a.contains(id))
+        .collect();
+
+    // The shared set is closed under ancestry, so a shared commit descends
+    // from another exactly when it is some shared commit's parent.
+    let mut timestamps = Vec::with_capacity(common.len());
+    let mut dominated = HashSet::new();
+    for id in &common {
+        let commit = repo.read_commit(id)?;
+        dominated.extend(commit.parents);
+        timestamps.push((*id, commit.timestamp_ns));
+    }
+    Ok(timestamps
+        .into_iter()
+        .filter(|(id, _)| !dominated.contains(id))
+        .max_by_key(|&(id, timestamp)| (timestamp, id))
+        .map(|(id, _)| id))
+}
+
+/// Every commit reachable from HEAD, MERGE_HEAD and all refs (tags peeled).
+pub fn reachable_commits(repo: &Repository) -> Result<HashSet<[u8; 32]>> {
+    let mut tips = Vec::new();
+    tips.extend(repo.read_head()?);
+    tips.extend(repo.read_merge_head()?);
+    for (_, id) in repo.list_refs("refs")? {
+        tips.push(repo.peel_to_commit(&id)?);
+    }
+
+    let mut seen = HashSet::new();
+    for tip in tips {
+        if seen.contains(&tip) {
+            continue;
+        }
+        seen.extend(ancestors(repo, &tip)?);
+    }
+    Ok(seen)
+}
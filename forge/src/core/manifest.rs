@@ -22,6 +22,8 @@ pub enum FileType {
     Psd,
     Blend,
     Graphite,
+    Gltf,
+    Usd,
 }
 
 impl FileType {
@@ -33,9 +35,11 @@ impl FileType {
                 "mp4" | "mov" | "m4v" => return Self::Mp4,
                 "clip" | "csp" => return Self::Csp,
                 "png" => return Self::Png,
-                "psd" => return Self::Psd,
+                "psd" | "psb" => return Self::Psd,
                 "blend" => return Self::Blend,
                 "graphite" => return Self::Graphite,
+                "glb" | "gltf" => return Self::Gltf,
+                "usd" | "usdc" | "usda" => return Self::Usd,
                 _ => {}
             }
         }
@@ -50,6 +54,9 @@ impl FileType {
             if header[0..4] == [0x38, 0x42, 0x50, 0x53] {
                 return Self::Psd;
             }
+            if &header[0..4] == b"glTF" {
+                return Self::Gltf;
+            }
         }
         if header.len() >= 8 && &header[4..8] == b"ftyp" {
             return Self::Mp4;
@@ -57,9 +64,29 @@ impl FileType {
         if header.len() >= 7 && &header[0..7] == b"BLENDER" {
             return Self::Blend;
         }
+        if header.len() >= 8 && &header[0..8] == b"PXR-USDC" {
+            return Self::Usd;
+        }
 
         Self::Unknown
     }
+
+    /// Lowercase name used as a key in `config.toml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::UAsset => "uasset",
+            Self::Exr => "exr",
+            Self::Mp4 => "mp4",
+            Self::Csp => "csp",
+            Self::Png => "png",
+            Self::Psd => "psd",
+            Self::Blend => "blend",
+            Self::Graphite => "graphite",
+            Self::Gltf => "gltf",
+            Self::Usd => "usd",
+        }
+    }
 }
 
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
@@ -77,7 +104,8 @@ pub struct FileEntry {
 pub struct Commit {
     pub id: [u8; 32],
     pub parents: Vec<[u8; 32]>,
-    pub files: Vec<FileEntry>,
+    /// Root `Tree` object id.
+    pub tree: [u8; 32],
     pub message: String,
     pub author: String,
     pub timestamp_ns: i64,
@@ -85,6 +113,61 @@ pub struct Commit {
 
 pub type Manifest = Commit;
 
+/// Manifest layout from before tree objects, when every commit embedded its
+/// full file list. Still readable so older repositories keep working.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct LegacyCommit {
+    pub id: [u8; 32],
+    pub parents: Vec<[u8; 32]>,
+    pub files: Vec<FileEntry>,
+    pub message: String,
+    pub author: String,
+    pub timestamp_ns: i64,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub enum TreeNode {
+    File(FileEntry),
+    /// Id of the subtree for this directory.
+    Dir([u8; 32]),
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct TreeEntry {
+    /// Single path component.
+    pub name: String,
+    pub node: TreeNode,
+}
+
+/// One directory level, stored under `.forge/objects/trees/<id>` where the id is
+/// the hash of the serialized tree. Identical directories share one object.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Tree {
+    /// Sorted by name.
+    pub entries: Vec<TreeEntry>,
+    /// Files in this tree and every subtree.
+    pub file_count: u64,
+}
+
+/// Per-path state of an unresolved merge; `None` means the side deleted the file.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct MergeConflict {
+    pub base: Option<FileEntry>,
+    pub ours: Option<FileEntry>,
+    pub theirs: Option<FileEntry>,
+}
+
+/// Annotated tag object stored under `.forge/objects/tags/<id>`.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct Tag {
+    pub id: [u8; 32],
+    pub target: [u8; 32],
+    pub name: String,
+    pub message: String,
+    pub tagger: String,
+    pub timestamp_ns: i64,
+}
+
 pub fn serialize_commit(commit: &Commit) -> Result<Vec<u8>> {
     let bytes =
         rkyv::to_bytes::<rkyv::rancor::Error>(commit).context("failed to serialize commit")?;
@@ -96,6 +179,50 @@ pub fn deserialize_commit(bytes: &[u8]) -> Result<Commit> {
         .context("failed to deserialize archived commit")
 }
 
+/// Content id of a commit: the hash of the commit serialized with a zero id.
+pub fn compute_commit_id(commit: &Commit) -> Result<[u8; 32]> {
+    let draft = Commit {
+        id: [0u8; 32],
+        ..commit.clone()
+    };
+    Ok(*blake3::hash(&serialize_commit(&draft)?).as_bytes())
+}
+
+/// The id stored in manifest `bytes`, in either layout, if it matches the content.
+pub fn verified_manifest_id(bytes: &[u8]) -> Option<[u8; 32]> {
+    if let Ok(commit) = deserialize_commit(bytes) {
+        return (compute_commit_id(&commit).ok()? == commit.id).then_some(commit.id);
+    }
+    let legacy = deserialize_legacy_commit(bytes).ok()?;
+    let draft = LegacyCommit {
+        id: [0u8; 32],
+        ..legacy.clone()
+    };
+    let expected = *blake3::hash(&serialize_legacy_commit(&draft).ok()?).as_bytes();
+    (expected == legacy.id).then_some(legacy.id)
+}
+
+pub fn deserialize_legacy_commit(bytes: &[u8]) -> Result<LegacyCommit> {
+    rkyv::from_bytes::<LegacyCommit, rkyv::rancor::Error>(bytes)
+        .context("failed to deserialize archived legacy commit")
+}
+
+pub fn serialize_legacy_commit(commit: &LegacyCommit) -> Result<Vec<u8>> {
+    let bytes =
+        rkyv::to_bytes::<rkyv::rancor::Error>(commit).context("failed to serialize legacy commit")?;
+    Ok(bytes.to_vec())
+}
+
+pub fn serialize_tree(tree: &Tree) -> Result<Vec<u8>> {
+    let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(tree).context("failed to serialize tree")?;
+    Ok(bytes.to_vec())
+}
+
+pub fn deserialize_tree(bytes: &[u8]) -> Result<Tree> {
+    rkyv::from_bytes::<Tree, rkyv::rancor::Error>(bytes)
+        .context("failed to deserialize archived tree")
+}
+
 pub fn serialize_file_entry(entry: &FileEntry) -> Result<Vec<u8>> {
     let bytes =
         rkyv::to_bytes::<rkyv::rancor::Error>(entry).context("failed to serialize file entry")?;
@@ -106,3 +233,24 @@ pub fn deserialize_file_entry(bytes: &[u8]) -> Result<FileEntry> {
     rkyv::from_bytes::<FileEntry, rkyv::rancor::Error>(bytes)
         .context("failed to deserialize archived file entry")
 }
+
+pub fn serialize_tag(tag: &Tag) -> Result<Vec<u8>> {
+    let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(tag).context("failed to serialize tag")?;
+    Ok(bytes.to_vec())
+}
+
+pub fn deserialize_tag(bytes: &[u8]) -> Result<Tag> {
+    rkyv::from_bytes::<Tag, rkyv::rancor::Error>(bytes)
+        .context("failed to deserialize archived tag")
+}
+
+pub fn serialize_conflict(conflict: &MergeConflict) -> Result<Vec<u8>> {
+    let bytes =
+        rkyv::to_bytes::<rkyv::rancor::Error>(conflict).context("failed to serialize conflict")?;
+    Ok(bytes.to_vec())
+}
+
+pub fn deserialize_conflict(bytes: &[u8]) -> Result<MergeConflict> {
+    rkyv::from_bytes::<MergeConflict, rkyv::rancor::Error>(bytes)
+        .context("failed to deserialize archived conflict")
+}
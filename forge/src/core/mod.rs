@@ -1,4 +1,10 @@
 pub mod chunk;
+pub mod graph;
 pub mod hash;
 pub mod manifest;
+pub mod partial;
+pub mod remote;
 pub mod repository;
+pub mod revision;
+pub mod tree;
+pub mod worktree;
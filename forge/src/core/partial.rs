@@ -0,0 +1,98 @@
+//! Partial clones: commits and trees are complete, chunks are fetched from
+//! one remote when first read and may be evicted again once the local copy
+//! outgrows the configured cache size.
+
+use std::collections::HashSet;
+use std::fs;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::core::graph::ancestors;
+use crate::core::manifest::TreeNode;
+use crate::core::repository::Repository;
+use crate::core::tree;
+use crate::store::cas::ChunkStore;
+
+/// The `[partial]` table of `config.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialConfig {
+    /// Remote missing chunks are fetched from.
+    pub remote: String,
+    /// Bytes of chunks to keep locally before evicting; unlimited when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_size: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct EvictStats {
+    pub chunks: usize,
+    pub bytes: u64,
+}
+
+/// Chunks of every commit reachable from `refs/remotes/<remote>/*`: the
+/// server checks a pushed commit's chunks, so it holds all of these.
+pub fn remote_chunks(repo: &Repository, remote: &str) -> Result<HashSet<[u8; 32]>> {
+    let mut commits = HashSet::new();
+    for (_, tip) in repo.list_refs(&format!("refs/remotes/{remote}"))? {
+        if repo.manifest_path(&tip).exists() {
+            commits.extend(ancestors(repo, &tip)?);
+        }
+    }
+
+    let mut chunks = HashSet::new();
+    let mut seen = HashSet::new();
+    for id in &commits {
+        let root = repo.read_commit(id)?.tree;
+        tree::walk(repo, &root, &mut seen, &mut |_, tree| {
+            for entry in &tree.entries {
+                if let TreeNode::File(file) = &entry.node {
+                    chunks.extend(file.chunks.iter().map(|chunk| chunk.hash));
+                }
+            }
+        })?;
+    }
+    Ok(chunks)
+}
+
+/// Delete loose chunks the remote holds, least recently fetched first, until
+/// the store fits in `limit` bytes. Chunks in `keep` stay, as do chunks only
+/// this repository has; packed chunks are left for `forge gc`.
+pub fn evict(
+    repo: &Repository,
+    store: &ChunkStore,
+    config: &PartialConfig,
+    limit: u64,
+    keep: &HashSet<[u8; 32]>,
+) -> Result<EvictStats> {
+    let mut stats = EvictStats::default();
+    let mut usage = store.total_size()?;
+    if usage <= limit {
+        return Ok(stats);
+    }
+
+    let on_remote = remote_chunks(repo, &config.remote)?;
+    let mut candidates: Vec<(SystemTime, u64, blake3::Hash)> = Vec::new();
+    for hash in store.list_loose()? {
+        if keep.contains(hash.as_bytes()) || !on_remote.contains(hash.as_bytes()) {
+            continue;
+        }
+        let path = store.chunk_path(&hash);
+        let meta = fs::metadata(&path).with_context(|| format!("stat {}", path.display()))?;
+        candidates.push((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), hash));
+    }
+    candidates.sort_by_key(|(modified, _, _)| *modified);
+
+    for (_, len, hash) in candidates {
+        if usage <= limit {
+            break;
+        }
+        if store.remove(&hash)? {
+            usage = usage.saturating_sub(len);
+            stats.chunks += 1;
+            stats.bytes += len;
+        }
+    }
+    Ok(stats)
+}
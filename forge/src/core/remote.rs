@@ -0,0 +1,120 @@
+//! Named remotes, stored as `[remote.<name>]` tables in `config.toml`.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::core::repository::{validate_ref_name, Repository};
+use crate::transport::quic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    /// A `forge serve` endpoint reached over `quic://`.
+    Quic,
+    /// Third-party mirror backends (`forge push --mirror`).
+    Mirror,
+}
+
+impl TransportKind {
+    pub fn for_url(url: &str) -> Self {
+        if quic::is_quic_url(url) {
+            Self::Quic
+        } else {
+            Self::Mirror
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Quic => "quic",
+            Self::Mirror => "mirror",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    pub url: String,
+    /// Inferred from `url` when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<TransportKind>,
+    /// Mirror modes or backends `forge push` uses for this remote
+    /// (`all-free`, `pro`, `youtube`, ...). Defaults to `all-free`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// `owner/repo` for the GitHub mirror backend; taken from a
+    /// `https://github.com/` URL when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_repo: Option<String>,
+    /// PEM certificate to trust for this remote, relative to the repository
+    /// root; overrides the global `quic_ca`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca: Option<String>,
+}
+
+impl RemoteConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            transport: Some(TransportKind::for_url(url)),
+            ..Self::default()
+        }
+    }
+
+    pub fn kind(&self) -> TransportKind {
+        self.transport.unwrap_or_else(|| TransportKind::for_url(&self.url))
+    }
+
+    pub fn github_repo(&self) -> Option<String> {
+        if let Some(repo) = &self.github_repo {
+            return Some(repo.clone());
+        }
+        let path = self.url.strip_prefix("https://github.com/")?;
+        Some(path.trim_end_matches('/').trim_end_matches(".git").to_string())
+    }
+}
+
+/// A remote picked on the command line: a configured name, or a bare URL.
+#[derive(Debug, Clone)]
+pub struct Remote {
+    /// `None` for a URL given directly; such remotes get no tracking refs.
+    pub name: Option<String>,
+    pub config: RemoteConfig,
+}
+
+impl Remote {
+    /// Name for messages: the remote's name, else its URL.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.config.url)
+    }
+}
+
+/// Resolve `spec`, a remote name or a `quic://` URL. `None` when it is neither.
+pub fn resolve(repo: &Repository, spec: &str) -> Result<Option<Remote>> {
+    if TransportKind::for_url(spec) == TransportKind::Quic {
+        return Ok(Some(Remote {
+            name: None,
+            config: RemoteConfig::new(spec),
+        }));
+    }
+    Ok(repo.read_config()?.remotes.remove(spec).map(|config| Remote {
+        name: Some(spec.to_string()),
+        config,
+    }))
+}
+
+/// Like [`resolve`], but an unknown name is an error.
+pub fn require(repo: &Repository, spec: &str) -> Result<Remote> {
+    match resolve(repo, spec)? {
+        Some(remote) => Ok(remote),
+        None => bail!("no remote named '{spec}' (add one with `forge remote add {spec} <url>`)"),
+    }
+}
+
+/// Remote names become ref path components under `refs/remotes`.
+pub fn validate_remote_name(name: &str) -> Result<()> {
+    if name.contains('/') {
+        bail!("invalid remote name '{name}': it cannot contain '/'");
+    }
+    validate_ref_name(name)
+}
@@ -1,10 +1,24 @@
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
 
+use crate::core::manifest::{
+    deserialize_commit, deserialize_legacy_commit, deserialize_tag, deserialize_tree, serialize_tag,
+    serialize_tree, Commit, FileEntry, Tag, Tree,
+};
+use crate::core::partial::PartialConfig;
+use crate::core::remote::RemoteConfig;
+use crate::core::tree;
 use crate::db::metadata::MetadataDb;
+use crate::store::cas::ChunkStore;
+use crate::store::compression::Dictionaries;
+use crate::transport::lazy::RemoteChunkSource;
 
 #[derive(Debug, Clone)]
 pub struct Repository {
@@ -19,7 +33,23 @@ pub struct Config {
     pub chunk_max: u32,
     pub compression_level: i32,
     pub dict_size: usize,
+    /// Single remote from before `[remote.<name>]` tables; read as `origin`
+    /// and dropped from the file on the next write.
+    #[serde(default, skip_serializing)]
     pub remote_url: Option<String>,
+    /// PEM certificate trusted when connecting to `quic://` remotes, for
+    /// servers using the self-signed certificate `forge serve` generates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quic_ca: Option<String>,
+    /// Extension or file type name → zstd dictionary path, relative to `.forge`.
+    #[serde(default)]
+    pub dictionaries: BTreeMap<String, String>,
+    /// `[remote.<name>]` tables.
+    #[serde(default, rename = "remote", skip_serializing_if = "BTreeMap::is_empty")]
+    pub remotes: BTreeMap<String, RemoteConfig>,
+    /// Set in partial clones, which fetch chunks from a remote on demand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialConfig>,
 }
 
 impl Default for Config {
@@ -31,6 +61,10 @@ impl Default for Config {
             compression_level: 8,
             dict_size: 112_640,
             remote_url: None,
+            quic_ca: None,
+            dictionaries: BTreeMap::new(),
+            remotes: BTreeMap::new(),
+            partial: None,
         }
     }
 }
@@ -44,10 +78,12 @@ impl Repository {
         loop {
             let forge_dir = current.join(".forge");
             if forge_dir.is_dir() {
-                return Ok(Self {
+                let repo = Self {
                     root: current,
                     forge_dir,
-                });
+                };
+                repo.migrate_legacy_manifests()?;
+                return Ok(repo);
             }
             if !current.pop() {
                 bail!("not inside a Forge repository (missing .forge directory)");
@@ -61,11 +97,54 @@ impl Repository {
             .canonicalize()
             .with_context(|| format!("failed to canonicalize {}", path.display()))?;
         let forge_dir = root.join(".forge");
+        Self::create_layout(&forge_dir)?;
+        Ok(Self { root, forge_dir })
+    }
+
+    /// Create a repository without a working tree: the repository layout lives
+    /// directly in `path`, as served by `forge serve`.
+    pub fn init_bare(path: &Path) -> Result<Self> {
+        fs::create_dir_all(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let root = path
+            .canonicalize()
+            .with_context(|| format!("failed to canonicalize {}", path.display()))?;
+        Self::create_layout(&root)?;
+        Ok(Self {
+            root: root.clone(),
+            forge_dir: root,
+        })
+    }
 
+    pub fn open_bare(path: &Path) -> Result<Self> {
+        let root = path
+            .canonicalize()
+            .with_context(|| format!("unable to canonicalize {}", path.display()))?;
+        if !root.join("HEAD").is_file() || !root.join("manifests").is_dir() {
+            bail!(
+                "{} is not a bare Forge repository (create one with `forge init --bare`)",
+                root.display()
+            );
+        }
+        let repo = Self {
+            root: root.clone(),
+            forge_dir: root,
+        };
+        repo.migrate_legacy_manifests()?;
+        Ok(repo)
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.root == self.forge_dir
+    }
+
+    fn create_layout(forge_dir: &Path) -> Result<()> {
         for rel in [
             "objects/chunks",
             "objects/packs",
+            "objects/tags",
+            "objects/trees",
             "refs/heads",
+            "refs/tags",
             "refs/remotes",
             "manifests",
             "dictionaries",
@@ -82,8 +161,7 @@ impl Repository {
 
         let db_path = forge_dir.join("metadata.redb");
         MetadataDb::create(&db_path)?;
-
-        Ok(Self { root, forge_dir })
+        Ok(())
     }
 
     pub fn objects_dir(&self) -> PathBuf {
@@ -98,6 +176,22 @@ impl Repository {
             .join(&hex_hash[2..])
     }
 
+    /// Repo-relative, forward-slash form of a user-supplied path.
+    pub fn relative_path(&self, raw: &str) -> String {
+        let path = Path::new(raw);
+        let abs = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        };
+        pathdiff::diff_paths(&abs, &self.root)
+            .unwrap_or(abs)
+            .to_string_lossy()
+            .replace('\\', "/")
+            .trim_end_matches('/')
+            .to_string()
+    }
+
     pub fn metadata_db_path(&self) -> PathBuf {
         self.forge_dir.join("metadata.redb")
     }
@@ -110,60 +204,361 @@ impl Repository {
         self.forge_dir.join("HEAD")
     }
 
-    pub fn read_head(&self) -> Result<Option<[u8; 32]>> {
-        let head = fs::read_to_string(self.head_path()).context("failed to read HEAD")?;
-        if let Some(reference) = head.strip_prefix("ref: ") {
-            let rel = reference.trim();
-            let ref_path = self.forge_dir.join(rel);
-            if !ref_path.exists() {
-                return Ok(None);
+    pub fn manifest_path(&self, commit_id: &[u8; 32]) -> PathBuf {
+        self.forge_dir.join("manifests").join(hex::encode(commit_id))
+    }
+
+    pub fn tag_object_path(&self, tag_id: &[u8; 32]) -> PathBuf {
+        self.forge_dir.join("objects/tags").join(hex::encode(tag_id))
+    }
+
+    pub fn tree_object_path(&self, tree_id: &[u8; 32]) -> PathBuf {
+        self.forge_dir.join("objects/trees").join(hex::encode(tree_id))
+    }
+
+    pub fn ref_path(&self, name: &str) -> PathBuf {
+        self.forge_dir.join(name)
+    }
+
+    pub fn read_commit(&self, commit_id: &[u8; 32]) -> Result<Commit> {
+        let path = self.manifest_path(commit_id);
+        let bytes = fs::read(&path).with_context(|| format!("read manifest {}", path.display()))?;
+        self.parse_manifest(&bytes)
+            .with_context(|| format!("decode manifest {}", path.display()))
+    }
+
+    /// Decode manifest bytes in either layout. Manifests written before tree
+    /// objects carry a flat file list; the id of the equivalent tree is
+    /// computed so callers only ever see the current layout. Nothing is
+    /// written: `migrate_legacy_manifests` stores those trees.
+    pub fn parse_manifest(&self, bytes: &[u8]) -> Result<Commit> {
+        if let Ok(commit) = deserialize_commit(bytes) {
+            return Ok(commit);
+        }
+        let legacy = deserialize_legacy_commit(bytes)?;
+        let files = legacy.files.into_iter().map(|f| (f.path.clone(), f)).collect();
+        Ok(Commit {
+            id: legacy.id,
+            parents: legacy.parents,
+            tree: tree::tree_id(self, &files)?,
+            message: legacy.message,
+            author: legacy.author,
+            timestamp_ns: legacy.timestamp_ns,
+        })
+    }
+
+    /// Write the trees of manifests from before tree objects, once, when a
+    /// repository without `objects/trees` is opened. They are written aside
+    /// and moved into place at the end, so an interrupted run starts over.
+    fn migrate_legacy_manifests(&self) -> Result<()> {
+        let trees = self.forge_dir.join("objects/trees");
+        let manifests = self.forge_dir.join("manifests");
+        if trees.is_dir() || !manifests.is_dir() {
+            return Ok(());
+        }
+        let staging = self.forge_dir.join("objects/trees.migrating");
+        fs::create_dir_all(&staging).with_context(|| format!("create {}", staging.display()))?;
+        for entry in fs::read_dir(&manifests).with_context(|| format!("read {}", manifests.display()))? {
+            let path = entry.context("read manifest dir entry")?.path();
+            let bytes = fs::read(&path).with_context(|| format!("read manifest {}", path.display()))?;
+            if deserialize_commit(&bytes).is_ok() {
+                continue;
+            }
+            // Undecodable manifests are left for fsck to report.
+            let Ok(legacy) = deserialize_legacy_commit(&bytes) else {
+                continue;
+            };
+            let files = legacy.files.into_iter().map(|f| (f.path.clone(), f)).collect();
+            tree::snapshot(self, &files, &mut |tree| {
+                let bytes = serialize_tree(tree)?;
+                let id = *blake3::hash(&bytes).as_bytes();
+                let path = staging.join(hex::encode(id));
+                fs::write(&path, bytes).with_context(|| format!("write tree {}", path.display()))?;
+                Ok(id)
+            })
+            .with_context(|| format!("migrate manifest {}", path.display()))?;
+        }
+        fs::rename(&staging, &trees).with_context(|| format!("install {}", trees.display()))
+    }
+
+    /// Store `tree` under its content id, skipping the write when it already exists.
+    pub fn write_tree(&self, tree: &Tree) -> Result<[u8; 32]> {
+        let bytes = serialize_tree(tree)?;
+        let id = *blake3::hash(&bytes).as_bytes();
+        let path = self.tree_object_path(&id);
+        if path.exists() {
+            return Ok(id);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        fs::write(&path, bytes).with_context(|| format!("write tree {}", path.display()))?;
+        Ok(id)
+    }
+
+    pub fn read_tree(&self, tree_id: &[u8; 32]) -> Result<Tree> {
+        let path = self.tree_object_path(tree_id);
+        let bytes = fs::read(&path).with_context(|| format!("read tree {}", path.display()))?;
+        deserialize_tree(&bytes)
+    }
+
+    /// Resolve a unique (possibly abbreviated) hex commit id against `manifests/`.
+    pub fn resolve_commit_prefix(&self, prefix: &str) -> Result<[u8; 32]> {
+        let prefix = prefix.to_ascii_lowercase();
+        if prefix.len() < 4 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("'{prefix}' is not a valid commit id (need at least 4 hex digits)");
+        }
+        if prefix.len() == 64 {
+            let id = parse_object_id(&prefix)?;
+            if !self.manifest_path(&id).exists() {
+                bail!("unknown commit {prefix}");
+            }
+            return Ok(id);
+        }
+
+        let dir = self.forge_dir.join("manifests");
+        let mut matches = Vec::new();
+        for entry in fs::read_dir(&dir).with_context(|| format!("read {}", dir.display()))? {
+            let name = entry.context("read manifests entry")?.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(&prefix) {
+                matches.push(name.to_string());
             }
-            let commit_hex = fs::read_to_string(&ref_path)
-                .with_context(|| format!("failed to read ref {}", rel))?
-                .trim()
-                .to_string();
-            if commit_hex.is_empty() {
-                return Ok(None);
+        }
+        match matches.as_slice() {
+            [] => bail!("unknown commit {prefix}"),
+            [only] => parse_object_id(only),
+            _ => {
+                matches.sort();
+                bail!(
+                    "short commit id {prefix} is ambiguous; candidates:\n  {}",
+                    matches.join("\n  ")
+                )
             }
-            let bytes = hex::decode(&commit_hex).context("invalid commit id in ref")?;
-            let id = bytes
-                .try_into()
-                .map_err(|_| anyhow::anyhow!("invalid commit id length in ref"))?;
-            return Ok(Some(id));
+        }
+    }
+
+    /// Full file snapshot of a commit keyed by path.
+    pub fn read_commit_files(&self, commit_id: &[u8; 32]) -> Result<BTreeMap<String, FileEntry>> {
+        tree::flatten(self, &self.read_commit(commit_id)?.tree)
+    }
+
+    /// Returns the ref HEAD points at (e.g. `refs/heads/main`), or `None` when detached.
+    pub fn head_ref(&self) -> Result<Option<String>> {
+        let head = fs::read_to_string(self.head_path()).context("failed to read HEAD")?;
+        Ok(head
+            .strip_prefix("ref: ")
+            .map(|reference| reference.trim().to_string()))
+    }
+
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        Ok(self
+            .head_ref()?
+            .and_then(|r| r.strip_prefix("refs/heads/").map(str::to_string)))
+    }
+
+    pub fn read_head(&self) -> Result<Option<[u8; 32]>> {
+        if let Some(reference) = self.head_ref()? {
+            return self.read_ref(&reference);
         }
 
+        let head = fs::read_to_string(self.head_path()).context("failed to read HEAD")?;
         let commit_hex = head.trim();
         if commit_hex.is_empty() {
             return Ok(None);
         }
-        let bytes = hex::decode(commit_hex).context("invalid detached HEAD id")?;
-        let id = bytes
-            .try_into()
-            .map_err(|_| anyhow::anyhow!("invalid detached HEAD length"))?;
-        Ok(Some(id))
+        parse_object_id(commit_hex).map(Some).context("invalid detached HEAD")
     }
 
     pub fn update_head(&self, commit_id: &[u8; 32]) -> Result<()> {
-        let head = fs::read_to_string(self.head_path()).context("failed to read HEAD")?;
-        let hex_id = hex::encode(commit_id);
-        if let Some(reference) = head.strip_prefix("ref: ") {
-            let rel = reference.trim();
-            let ref_path = self.forge_dir.join(rel);
-            if let Some(parent) = ref_path.parent() {
-                fs::create_dir_all(parent).with_context(|| {
-                    format!("failed to create parent dirs for ref {}", parent.display())
-                })?;
+        match self.head_ref()? {
+            Some(reference) => self.write_ref(&reference, commit_id),
+            None => self.detach_head(commit_id),
+        }
+    }
+
+    /// Point HEAD symbolically at `reference` (e.g. `refs/heads/feature`).
+    pub fn set_head_ref(&self, reference: &str) -> Result<()> {
+        fs::write(self.head_path(), format!("ref: {reference}\n")).context("failed to write HEAD")
+    }
+
+    pub fn detach_head(&self, commit_id: &[u8; 32]) -> Result<()> {
+        fs::write(self.head_path(), format!("{}\n", hex::encode(commit_id)))
+            .context("failed to update detached HEAD")
+    }
+
+    pub fn read_ref(&self, name: &str) -> Result<Option<[u8; 32]>> {
+        let ref_path = self.ref_path(name);
+        if !ref_path.exists() {
+            return Ok(None);
+        }
+        let raw = fs::read_to_string(&ref_path).with_context(|| format!("failed to read ref {name}"))?;
+        let id_hex = raw.trim();
+        if id_hex.is_empty() {
+            return Ok(None);
+        }
+        parse_object_id(id_hex)
+            .map(Some)
+            .with_context(|| format!("invalid object id in ref {name}"))
+    }
+
+    pub fn write_ref(&self, name: &str, id: &[u8; 32]) -> Result<()> {
+        let ref_path = self.ref_path(name);
+        if let Some(parent) = ref_path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create parent dirs for ref {}", parent.display())
+            })?;
+        }
+        fs::write(&ref_path, format!("{}\n", hex::encode(id)))
+            .with_context(|| format!("failed to update ref {name}"))
+    }
+
+    pub fn delete_ref(&self, name: &str) -> Result<bool> {
+        match fs::remove_file(self.ref_path(name)) {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("failed to delete ref {name}")),
+        }
+    }
+
+    /// Lists refs below `prefix` (e.g. `refs/heads`), returning names relative to it.
+    pub fn list_refs(&self, prefix: &str) -> Result<Vec<(String, [u8; 32])>> {
+        let base = self.ref_path(prefix);
+        if !base.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut out = Vec::new();
+        for entry in WalkDir::new(&base)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|e| e.file_type().is_file())
+        {
+            let rel = entry
+                .path()
+                .strip_prefix(&base)
+                .context("strip refs prefix")?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if let Some(id) = self.read_ref(&format!("{prefix}/{rel}"))? {
+                out.push((rel, id));
             }
-            fs::write(ref_path, format!("{hex_id}\n")).context("failed to update branch ref")?;
-        } else {
-            fs::write(self.head_path(), format!("{hex_id}\n")).context("failed to update detached HEAD")?;
         }
-        Ok(())
+        Ok(out)
+    }
+
+    pub fn write_tag(&self, tag: &Tag) -> Result<()> {
+        let path = self.tag_object_path(&tag.id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        fs::write(&path, serialize_tag(tag)?).with_context(|| format!("write tag {}", path.display()))
+    }
+
+    pub fn read_tag(&self, tag_id: &[u8; 32]) -> Result<Option<Tag>> {
+        let path = self.tag_object_path(tag_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&path).with_context(|| format!("read tag {}", path.display()))?;
+        deserialize_tag(&bytes).map(Some)
+    }
+
+    /// Follows annotated tag objects until a commit id is reached.
+    pub fn peel_to_commit(&self, id: &[u8; 32]) -> Result<[u8; 32]> {
+        let mut current = *id;
+        loop {
+            if self.manifest_path(&current).exists() {
+                return Ok(current);
+            }
+            match self.read_tag(&current)? {
+                Some(tag) => current = tag.target,
+                None => bail!("object {} is not a commit or tag", hex::encode(current)),
+            }
+        }
+    }
+
+    pub fn merge_head_path(&self) -> PathBuf {
+        self.forge_dir.join("MERGE_HEAD")
+    }
+
+    /// Commit being merged into HEAD while conflicts are outstanding.
+    pub fn read_merge_head(&self) -> Result<Option<[u8; 32]>> {
+        let path = self.merge_head_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw = fs::read_to_string(&path).context("failed to read MERGE_HEAD")?;
+        parse_object_id(raw.trim()).map(Some).context("invalid MERGE_HEAD")
+    }
+
+    pub fn write_merge_head(&self, commit_id: &[u8; 32]) -> Result<()> {
+        fs::write(self.merge_head_path(), format!("{}\n", hex::encode(commit_id)))
+            .context("failed to write MERGE_HEAD")
+    }
+
+    pub fn clear_merge_head(&self) -> Result<()> {
+        match fs::remove_file(self.merge_head_path()) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).context("failed to remove MERGE_HEAD"),
+        }
     }
 
     pub fn read_config(&self) -> Result<Config> {
         let raw = fs::read_to_string(self.config_path()).context("failed to read config.toml")?;
-        let cfg: Config = toml::from_str(&raw).context("failed to parse config.toml")?;
+        let mut cfg: Config = toml::from_str(&raw).context("failed to parse config.toml")?;
+        if let Some(url) = cfg.remote_url.take() {
+            cfg.remotes
+                .entry("origin".to_string())
+                .or_insert_with(|| RemoteConfig::new(&url));
+        }
         Ok(cfg)
     }
+
+    pub fn write_config(&self, cfg: &Config) -> Result<()> {
+        let raw = toml::to_string_pretty(cfg).context("serialize config")?;
+        fs::write(self.config_path(), raw).context("write config.toml")
+    }
+
+    /// Chunk store with the dictionaries registered in `config.toml` loaded.
+    pub fn chunk_store(&self) -> Result<ChunkStore> {
+        let mut cfg = self.read_config()?;
+        let dictionaries = Dictionaries::load(&self.forge_dir, &cfg.dictionaries, cfg.compression_level)?;
+        let store = ChunkStore::new(self.forge_dir.join("objects/chunks")).with_dictionaries(dictionaries);
+        let Some(partial) = cfg.partial else {
+            return Ok(store);
+        };
+        let Some(remote) = cfg.remotes.remove(&partial.remote) else {
+            bail!("partial clone remote '{}' is not configured", partial.remote);
+        };
+        Ok(store.with_source(Arc::new(RemoteChunkSource::new(self, partial, remote))))
+    }
+}
+
+pub fn parse_object_id(hex_str: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hex_str).context("invalid hex object id")?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("object id must be exactly 32 bytes"))
+}
+
+/// Rejects branch/tag names that would escape `refs/` or be ambiguous on disk.
+pub fn validate_ref_name(name: &str) -> Result<()> {
+    let invalid = name.is_empty()
+        || name.starts_with('-')
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with(".lock")
+        || name.contains("..")
+        || name.contains("//")
+        || name == "HEAD"
+        || name
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "~^:?*[\\".contains(c));
+    if invalid {
+        bail!("'{name}' is not a valid ref name");
+    }
+    Ok(())
 }
@@ -0,0 +1,118 @@
+//! Revision parsing shared by every command that takes a commit.
+//!
+//! Accepted forms: `HEAD` / `@`, branch, tag and remote-tracking names
+//! (optionally qualified as `heads/x`, `tags/x`, `remotes/x` or `refs/...`),
+//! unique hex prefixes of at least four digits, and any of those followed
+//! by `~N`, `~`, `^N` or `^` ancestry suffixes.
+
+use anyhow::{bail, Context, Result};
+
+use crate::core::repository::Repository;
+
+const REF_SEARCH_PATH: [&str; 4] = ["refs/{}", "refs/heads/{}", "refs/tags/{}", "refs/remotes/{}"];
+
+enum Step {
+    /// `~N`: walk N first-parent links.
+    Ancestor(usize),
+    /// `^N`: select the Nth parent (`^0` is the commit itself).
+    Parent(usize),
+}
+
+fn parse_suffixes(spec: &str, suffix: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut rest = suffix;
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[op.len_utf8()..];
+        let digits_len = rest.chars().take_while(char::is_ascii_digit).count();
+        let count = if digits_len == 0 {
+            1
+        } else {
+            rest[..digits_len]
+                .parse()
+                .with_context(|| format!("invalid number in revision '{spec}'"))?
+        };
+        rest = &rest[digits_len..];
+        match op {
+            '~' => steps.push(Step::Ancestor(count)),
+            '^' => steps.push(Step::Parent(count)),
+            _ => bail!("invalid revision '{spec}'"),
+        }
+    }
+    Ok(steps)
+}
+
+fn resolve_base(repo: &Repository, name: &str) -> Result<[u8; 32]> {
+    if name == "HEAD" || name == "@" {
+        return repo
+            .read_head()?
+            .ok_or_else(|| anyhow::anyhow!("HEAD does not point at a commit yet"));
+    }
+
+    let mut candidates: Vec<(String, [u8; 32])> = Vec::new();
+    for pattern in REF_SEARCH_PATH {
+        let reference = pattern.replace("{}", name);
+        if candidates.iter().any(|(r, _)| *r == reference) {
+            continue;
+        }
+        if let Some(id) = repo.read_ref(&reference)? {
+            candidates.push((reference, repo.peel_to_commit(&id)?));
+        }
+    }
+
+    let looks_like_id = name.len() >= 4 && name.chars().all(|c| c.is_ascii_hexdigit());
+    if looks_like_id {
+        match repo.resolve_commit_prefix(name) {
+            Ok(id) => candidates.push((format!("commit {}", hex::encode(id)), id)),
+            Err(err) if candidates.is_empty() => return Err(err),
+            Err(_) => {}
+        }
+    }
+
+    match candidates.as_slice() {
+        [] => bail!("unknown revision '{name}'"),
+        [(_, id)] => Ok(*id),
+        [(_, first), rest @ ..] if rest.iter().all(|(_, id)| id == first) => Ok(*first),
+        _ => bail!(
+            "revision '{name}' is ambiguous; it matches:\n  {}",
+            candidates
+                .iter()
+                .map(|(r, _)| r.as_str())
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        ),
+    }
+}
+
+/// Resolve a revision expression to a commit id.
+pub fn resolve_revision(repo: &Repository, spec: &str) -> Result<[u8; 32]> {
+    let spec = spec.trim();
+    let split = spec.find(['~', '^']).unwrap_or(spec.len());
+    let (base, suffix) = spec.split_at(split);
+    if base.is_empty() {
+        bail!("invalid revision '{spec}'");
+    }
+
+    let mut current = resolve_base(repo, base)?;
+    for step in parse_suffixes(spec, suffix)? {
+        match step {
+            Step::Ancestor(n) => {
+                for _ in 0..n {
+                    let commit = repo.read_commit(&current)?;
+                    current = *commit
+                        .parents
+                        .first()
+                        .ok_or_else(|| anyhow::anyhow!("revision '{spec}' goes past the root commit"))?;
+                }
+            }
+            Step::Parent(0) => {}
+            Step::Parent(n) => {
+                let commit = repo.read_commit(&current)?;
+                current = *commit
+                    .parents
+                    .get(n - 1)
+                    .ok_or_else(|| anyhow::anyhow!("revision '{spec}': commit has no parent #{n}"))?;
+            }
+        }
+    }
+    Ok(current)
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{bail, Result};
+
+use crate::core::manifest::{serialize_tree, FileEntry, Tree, TreeEntry, TreeNode};
+use crate::core::repository::Repository;
+
+type Change<'a> = (&'a str, Option<&'a FileEntry>);
+
+/// Stores one tree object and returns its id.
+pub type TreeSink<'a> = dyn FnMut(&Tree) -> Result<[u8; 32]> + 'a;
+
+/// Write the tree objects for a full snapshot and return the root id.
+pub fn write_tree(repo: &Repository, files: &BTreeMap<String, FileEntry>) -> Result<[u8; 32]> {
+    snapshot(repo, files, &mut |tree| repo.write_tree(tree))
+}
+
+/// The root id `write_tree` would return for `files`, without writing anything.
+pub fn tree_id(repo: &Repository, files: &BTreeMap<String, FileEntry>) -> Result<[u8; 32]> {
+    snapshot(repo, files, &mut |tree| Ok(*blake3::hash(&serialize_tree(tree)?).as_bytes()))
+}
+
+/// Build the trees for a full snapshot, handing each to `sink`, and return
+/// the root id.
+pub fn snapshot(repo: &Repository, files: &BTreeMap<String, FileEntry>, sink: &mut TreeSink) -> Result<[u8; 32]> {
+    let changes: Vec<Change> = files.iter().map(|(p, e)| (p.as_str(), Some(e))).collect();
+    match apply(repo, None, &changes, sink)?.0 {
+        Some(id) => Ok(id),
+        None => sink(&Tree::default()),
+    }
+}
+
+/// Apply `changes` (a `None` value removes the path) on top of the tree `base`.
+///
+/// Only directories along a changed path are read and rewritten; every other
+/// subtree keeps its id, so the new root shares it with `base`.
+pub fn update_tree(
+    repo: &Repository,
+    base: Option<&[u8; 32]>,
+    changes: &BTreeMap<String, Option<FileEntry>>,
+) -> Result<[u8; 32]> {
+    let changes: Vec<Change> = changes.iter().map(|(p, e)| (p.as_str(), e.as_ref())).collect();
+    let (id, _, _) = apply(repo, base, &changes, &mut |tree| repo.write_tree(tree))?;
+    match id {
+        Some(id) => Ok(id),
+        None => repo.write_tree(&Tree::default()),
+    }
+}
+
+/// Files below a directory entry, reading the subtree only when it is one.
+fn node_count(repo: &Repository, node: Option<&TreeNode>) -> Result<u64> {
+    Ok(match node {
+        Some(TreeNode::File(_)) => 1,
+        Some(TreeNode::Dir(id)) => repo.read_tree(id)?.file_count,
+        None => 0,
+    })
+}
+
+/// Returns the new tree id (`None` once it holds no files) with its file count,
+/// and the file count of `base`. Every new tree goes to `sink`.
+fn apply(
+    repo: &Repository,
+    base: Option<&[u8; 32]>,
+    changes: &[Change],
+    sink: &mut TreeSink,
+) -> Result<(Option<[u8; 32]>, u64, u64)> {
+    let tree = match base {
+        Some(id) => repo.read_tree(id)?,
+        None => Tree::default(),
+    };
+    let old_count = tree.file_count;
+    let mut count = tree.file_count;
+    let mut entries: BTreeMap<String, TreeNode> =
+        tree.entries.into_iter().map(|e| (e.name, e.node)).collect();
+
+    let mut groups: BTreeMap<&str, Vec<Change>> = BTreeMap::new();
+    for &(path, entry) in changes {
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        groups.entry(name).or_default().push((rest, entry));
+    }
+
+    for (name, group) in groups {
+        let mut node = entries.remove(name);
+        let mut direct = None;
+        let mut nested = Vec::new();
+        for (rest, entry) in group {
+            if rest.is_empty() {
+                direct = Some(entry);
+            } else {
+                nested.push((rest, entry));
+            }
+        }
+
+        // Removing `name` comes before the changes beneath it and adding it as
+        // a file after them, so a file can become a directory and vice versa.
+        if matches!(direct, Some(None)) {
+            count -= node_count(repo, node.as_ref())?;
+            node = None;
+        }
+
+        if !nested.is_empty() {
+            let sub_base = match &node {
+                Some(TreeNode::Dir(id)) => Some(*id),
+                _ => {
+                    // A file is being replaced by a directory of the same name.
+                    count -= node_count(repo, node.as_ref())?;
+                    None
+                }
+            };
+            let (id, new_sub, old_sub) = apply(repo, sub_base.as_ref(), &nested, sink)?;
+            count = count - old_sub + new_sub;
+            node = id.map(TreeNode::Dir);
+        }
+
+        if let Some(Some(file)) = direct {
+            count -= node_count(repo, node.as_ref())?;
+            node = Some(TreeNode::File(file.clone()));
+            count += 1;
+        }
+
+        if let Some(node) = node {
+            entries.insert(name.to_string(), node);
+        }
+    }
+
+    if count == 0 {
+        return Ok((None, 0, old_count));
+    }
+    let tree = Tree {
+        entries: entries
+            .into_iter()
+            .map(|(name, node)| TreeEntry { name, node })
+            .collect(),
+        file_count: count,
+    };
+    Ok((Some(sink(&tree)?), count, old_count))
+}
+
+/// Every file under `root`, keyed by full path.
+pub fn flatten(repo: &Repository, root: &[u8; 32]) -> Result<BTreeMap<String, FileEntry>> {
+    let mut out = BTreeMap::new();
+    collect(repo, root, &mut out)?;
+    Ok(out)
+}
+
+fn collect(repo: &Repository, id: &[u8; 32], out: &mut BTreeMap<String, FileEntry>) -> Result<()> {
+    for entry in repo.read_tree(id)?.entries {
+        collect_node(repo, entry.node, out)?;
+    }
+    Ok(())
+}
+
+fn collect_node(repo: &Repository, node: TreeNode, out: &mut BTreeMap<String, FileEntry>) -> Result<()> {
+    match node {
+        TreeNode::File(file) => {
+            out.insert(file.path.clone(), file);
+            Ok(())
+        }
+        TreeNode::Dir(id) => collect(repo, &id, out),
+    }
+}
+
+/// Files that differ between two trees, as (old side, new side) maps keyed by
+/// path. Subtrees with equal ids are skipped without being read.
+pub fn diff_trees(
+    repo: &Repository,
+    old: Option<&[u8; 32]>,
+    new: Option<&[u8; 32]>,
+) -> Result<(BTreeMap<String, FileEntry>, BTreeMap<String, FileEntry>)> {
+    let mut old_out = BTreeMap::new();
+    let mut new_out = BTreeMap::new();
+    diff_into(repo, old, new, &mut old_out, &mut new_out)?;
+    Ok((old_out, new_out))
+}
+
+fn diff_into(
+    repo: &Repository,
+    old: Option<&[u8; 32]>,
+    new: Option<&[u8; 32]>,
+    old_out: &mut BTreeMap<String, FileEntry>,
+    new_out: &mut BTreeMap<String, FileEntry>,
+) -> Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let read = |id: Option<&[u8; 32]>| -> Result<BTreeMap<String, TreeNode>> {
+        Ok(match id {
+            Some(id) => repo.read_tree(id)?.entries.into_iter().map(|e| (e.name, e.node)).collect(),
+            None => BTreeMap::new(),
+        })
+    };
+    let mut old_entries = read(old)?;
+    let mut new_entries = read(new)?;
+
+    let names: Vec<String> = old_entries.keys().chain(new_entries.keys()).cloned().collect();
+    for name in names {
+        match (old_entries.remove(&name), new_entries.remove(&name)) {
+            (Some(TreeNode::Dir(a)), Some(TreeNode::Dir(b))) => {
+                diff_into(repo, Some(&a), Some(&b), old_out, new_out)?;
+            }
+            (Some(TreeNode::File(a)), Some(TreeNode::File(b))) => {
+                if a.file_hash != b.file_hash {
+                    old_out.insert(a.path.clone(), a);
+                    new_out.insert(b.path.clone(), b);
+                }
+            }
+            (a, b) => {
+                if let Some(a) = a {
+                    collect_node(repo, a, old_out)?;
+                }
+                if let Some(b) = b {
+                    collect_node(repo, b, new_out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rejects tree entry names that are not a single plain path component, or
+/// that would write into the repository's own `.forge` directory.
+pub fn validate_entry_name(name: &str) -> Result<()> {
+    let invalid = name.is_empty()
+        || name == "."
+        || name == ".."
+        || name == ".forge"
+        || name.contains(['/', '\\', '\0']);
+    if invalid {
+        bail!("'{name}' is not a valid tree entry name");
+    }
+    Ok(())
+}
+
+/// Visit each tree reachable from `root` once, skipping ids already in `seen`
+/// so subtrees shared between commits are only read the first time.
+pub fn walk(
+    repo: &Repository,
+    root: &[u8; 32],
+    seen: &mut HashSet<[u8; 32]>,
+    visit: &mut dyn FnMut(&[u8; 32], &Tree),
+) -> Result<()> {
+    if !seen.insert(*root) {
+        return Ok(());
+    }
+    let tree = repo.read_tree(root)?;
+    visit(root, &tree);
+    for entry in &tree.entries {
+        if let TreeNode::Dir(id) = &entry.node {
+            walk(repo, id, seen, visit)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use crossbeam_channel::unbounded;
+
+use crate::core::hash::hash_file;
+use crate::core::manifest::FileEntry;
+use crate::db::metadata::MetadataDb;
+use crate::util::ignore::ForgeIgnore;
+
+/// Files whose mtime or ctime falls this close to the moment their stat was
+/// recorded may still change without the stat changing, so they are not cached.
+pub const RACY_WINDOW_NS: i64 = 2_000_000_000;
+
+/// What the working tree looked like when a file's content hash was last
+/// computed; an exact stat match lets `status` reuse the hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatRecord {
+    pub size: u64,
+    pub mtime_ns: i64,
+    pub ctime_ns: i64,
+    pub ino: u64,
+    pub dev: u64,
+    pub file_hash: [u8; 32],
+}
+
+impl StatRecord {
+    const ENCODED_LEN: usize = 8 * 5 + 32;
+
+    pub fn new(meta: &fs::Metadata, file_hash: [u8; 32]) -> Self {
+        let (ctime_ns, ino, dev) = inode_fields(meta);
+        Self {
+            size: meta.len(),
+            mtime_ns: mtime_ns(meta),
+            ctime_ns,
+            ino,
+            dev,
+            file_hash,
+        }
+    }
+
+    /// Whether `meta` describes the same file state this record was taken from.
+    pub fn matches(&self, meta: &fs::Metadata) -> bool {
+        let (ctime_ns, ino, dev) = inode_fields(meta);
+        self.size == meta.len()
+            && self.mtime_ns == mtime_ns(meta)
+            && self.ctime_ns == ctime_ns
+            && self.ino == ino
+            && self.dev == dev
+    }
+
+    pub fn is_racy(&self, recorded_at_ns: i64) -> bool {
+        self.mtime_ns.max(self.ctime_ns) >= recorded_at_ns - RACY_WINDOW_NS
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::ENCODED_LEN);
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out.extend_from_slice(&self.mtime_ns.to_le_bytes());
+        out.extend_from_slice(&self.ctime_ns.to_le_bytes());
+        out.extend_from_slice(&self.ino.to_le_bytes());
+        out.extend_from_slice(&self.dev.to_le_bytes());
+        out.extend_from_slice(&self.file_hash);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 8] { bytes[i * 8..i * 8 + 8].try_into().expect("slice of 8 bytes") };
+        Some(Self {
+            size: u64::from_le_bytes(word(0)),
+            mtime_ns: i64::from_le_bytes(word(1)),
+            ctime_ns: i64::from_le_bytes(word(2)),
+            ino: u64::from_le_bytes(word(3)),
+            dev: u64::from_le_bytes(word(4)),
+            file_hash: bytes[40..72].try_into().expect("slice of 32 bytes"),
+        })
+    }
+}
+
+pub fn mtime_ns(meta: &fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn inode_fields(meta: &fs::Metadata) -> (i64, u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (meta.ctime() * 1_000_000_000 + meta.ctime_nsec(), meta.ino(), meta.dev())
+}
+
+#[cfg(not(unix))]
+fn inode_fields(_meta: &fs::Metadata) -> (i64, u64, u64) {
+    (0, 0, 0)
+}
+
+fn now_ns() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_nanos() as i64
+}
+
+/// Persist stat records for files whose content hash is known, skipping racy ones.
+pub fn record_stats<'a, I>(db: &MetadataDb, files: I) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a fs::Metadata, [u8; 32])>,
+{
+    let now = now_ns();
+    let records: Vec<(String, Vec<u8>)> = files
+        .into_iter()
+        .map(|(path, meta, hash)| (path, StatRecord::new(meta, hash)))
+        .filter(|(_, record)| !record.is_racy(now))
+        .map(|(path, record)| (path.to_string(), record.to_bytes()))
+        .collect();
+    if records.is_empty() {
+        return Ok(());
+    }
+    db.update_stat_cache(&records, &[])
+}
+
+#[derive(Debug)]
+pub struct WorkingFile {
+    pub abs: PathBuf,
+    pub meta: fs::Metadata,
+}
+
+fn rel_path(root: &Path, path: &Path) -> String {
+    pathdiff::diff_paths(path, root)
+        .unwrap_or_else(|| path.to_path_buf())
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Every non-ignored file under `root`, keyed by repo-relative path. Directories
+/// are read in parallel, one `read_dir` per work item.
+pub fn scan(root: &Path, ignore: &ForgeIgnore) -> Result<BTreeMap<String, WorkingFile>> {
+    let (dir_tx, dir_rx) = unbounded::<PathBuf>();
+    let pending = AtomicUsize::new(1);
+    dir_tx.send(root.to_path_buf()).expect("scan queue open");
+    // The only sender. It is dropped once no directory is queued or being
+    // read, which closes the queue and wakes every idle worker.
+    let dir_tx = RwLock::new(Some(dir_tx));
+
+    let found = Mutex::new(BTreeMap::new());
+    let first_error = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..num_cpus::get().max(1) {
+            let dir_rx = dir_rx.clone();
+            let (dir_tx, pending, found, first_error) = (&dir_tx, &pending, &found, &first_error);
+            scope.spawn(move || {
+                let mut local = Vec::new();
+                while let Ok(dir) = dir_rx.recv() {
+                    let result = scan_dir(root, ignore, &dir, &mut local, |sub| {
+                        pending.fetch_add(1, Ordering::AcqRel);
+                        let queue = dir_tx.read().expect("scan queue poisoned");
+                        queue.as_ref().expect("scan queue open").send(sub).expect("scan queue open");
+                    });
+                    if let Err(err) = result {
+                        first_error.lock().expect("scan error poisoned").get_or_insert(err);
+                    }
+                    if pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        dir_tx.write().expect("scan queue poisoned").take();
+                    }
+                }
+                found.lock().expect("scan results poisoned").extend(local);
+            });
+        }
+    });
+
+    if let Some(err) = first_error.into_inner().expect("scan error poisoned") {
+        return Err(err);
+    }
+    Ok(found.into_inner().expect("scan results poisoned"))
+}
+
+fn scan_dir(
+    root: &Path,
+    ignore: &ForgeIgnore,
+    dir: &Path,
+    out: &mut Vec<(String, WorkingFile)>,
+    mut push_dir: impl FnMut(PathBuf),
+) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let entry = entry.context("read directory entry")?;
+        let file_type = entry.file_type().context("read file type")?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            if entry.file_name() != ".forge" && !ignore.excludes_dir(&path) {
+                push_dir(path);
+            }
+        } else if file_type.is_file() && !ignore.is_ignored(&path) {
+            let meta = entry.metadata().context("read file metadata")?;
+            out.push((rel_path(root, &path), WorkingFile { abs: path, meta }));
+        }
+    }
+    Ok(())
+}
+
+/// Tracked paths present in `working` whose content differs from `tracked`.
+///
+/// Sizes are compared first; otherwise a cached hash is reused when the file's
+/// stat matches its stat record exactly. Everything else is hashed in parallel
+/// and the stat cache is refreshed with the results.
+pub fn modified_files(
+    db: &MetadataDb,
+    tracked: &BTreeMap<String, FileEntry>,
+    working: &BTreeMap<String, WorkingFile>,
+) -> Result<BTreeSet<String>> {
+    let cache: HashMap<String, StatRecord> = db
+        .get_stat_cache()?
+        .into_iter()
+        .filter_map(|(path, bytes)| StatRecord::from_bytes(&bytes).map(|r| (path, r)))
+        .collect();
+
+    let mut modified = BTreeSet::new();
+    let mut candidates: Vec<(&String, &WorkingFile, &FileEntry)> = Vec::new();
+    for (path, entry) in tracked {
+        let Some(file) = working.get(path) else {
+            continue;
+        };
+        if file.meta.len() != entry.size {
+            modified.insert(path.clone());
+            continue;
+        }
+        match cache.get(path) {
+            Some(record) if record.matches(&file.meta) => {
+                if record.file_hash != entry.file_hash {
+                    modified.insert(path.clone());
+                }
+            }
+            _ => candidates.push((path, file, entry)),
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let hashed: Mutex<Vec<(usize, [u8; 32])>> = Mutex::new(Vec::with_capacity(candidates.len()));
+    let first_error = Mutex::new(None);
+    let workers = num_cpus::get().clamp(1, candidates.len().max(1));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some((_, file, _)) = candidates.get(i) else {
+                    break;
+                };
+                match hash_file(&file.abs) {
+                    Ok(hash) => hashed.lock().expect("hash results poisoned").push((i, *hash.as_bytes())),
+                    Err(err) => {
+                        first_error.lock().expect("hash error poisoned").get_or_insert(err);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    if let Some(err) = first_error.into_inner().expect("hash error poisoned") {
+        return Err(err);
+    }
+
+    let hashed = hashed.into_inner().expect("hash results poisoned");
+    for (i, hash) in &hashed {
+        let (path, _, entry) = candidates[*i];
+        if hash != &entry.file_hash {
+            modified.insert(path.clone());
+        }
+    }
+    record_stats(
+        db,
+        hashed.iter().map(|(i, hash)| {
+            let (path, file, _) = candidates[*i];
+            (path.as_str(), &file.meta, *hash)
+        }),
+    )?;
+
+    // Forget records for paths that are no longer tracked or no longer on disk.
+    let stale: Vec<String> = cache
+        .keys()
+        .filter(|path| !tracked.contains_key(*path) || !working.contains_key(*path))
+        .cloned()
+        .collect();
+    if !stale.is_empty() {
+        db.update_stat_cache(&[], &stale)?;
+    }
+    Ok(modified)
+}
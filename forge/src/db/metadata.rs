@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::Path;
 
 use anyhow::{Context, Result};
@@ -7,6 +8,13 @@ pub const FILES_TABLE: redb::TableDefinition<&str, &[u8]> = redb::TableDefinitio
 pub const CHUNKS_TABLE: redb::TableDefinition<&str, u32> = redb::TableDefinition::new("chunks");
 pub const COMMITS_TABLE: redb::TableDefinition<&str, &[u8]> = redb::TableDefinition::new("commits");
 pub const STAGING_TABLE: redb::TableDefinition<&str, &[u8]> = redb::TableDefinition::new("staging");
+/// Maps a staged-for-removal path → rename destination (empty for a plain delete).
+pub const REMOVALS_TABLE: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("removals");
+/// Maps a path with an unresolved merge conflict → archived `MergeConflict`.
+pub const CONFLICTS_TABLE: redb::TableDefinition<&str, &[u8]> = redb::TableDefinition::new("conflicts");
+/// Maps a working-tree path → encoded `StatRecord` (stat fields + content hash).
+pub const STAT_CACHE_TABLE: redb::TableDefinition<&str, &[u8]> =
+    redb::TableDefinition::new("stat_cache");
 /// Maps "file_path" → JSON array of mirror targets for that file.
 pub const MIRRORS_TABLE: redb::TableDefinition<&str, &[u8]> = redb::TableDefinition::new("mirrors");
 
@@ -34,6 +42,15 @@ impl MetadataDb {
             write_txn
                 .open_table(MIRRORS_TABLE)
                 .context("open MIRRORS_TABLE")?;
+            write_txn
+                .open_table(REMOVALS_TABLE)
+                .context("open REMOVALS_TABLE")?;
+            write_txn
+                .open_table(CONFLICTS_TABLE)
+                .context("open CONFLICTS_TABLE")?;
+            write_txn
+                .open_table(STAT_CACHE_TABLE)
+                .context("open STAT_CACHE_TABLE")?;
         }
         write_txn.commit().context("commit create schema")?;
         Ok(Self { db })
@@ -41,10 +58,13 @@ impl MetadataDb {
 
     pub fn open(path: &Path) -> Result<Self> {
         let db = redb::Database::open(path).with_context(|| format!("open redb {}", path.display()))?;
-        // Ensure MIRRORS_TABLE exists for older repos that were init'd before it was added.
+        // Ensure tables added after v0.1 exist for older repos.
         {
             let write_txn = db.begin_write().context("begin write txn for schema migration")?;
             write_txn.open_table(MIRRORS_TABLE).context("ensure MIRRORS_TABLE")?;
+            write_txn.open_table(REMOVALS_TABLE).context("ensure REMOVALS_TABLE")?;
+            write_txn.open_table(CONFLICTS_TABLE).context("ensure CONFLICTS_TABLE")?;
+            write_txn.open_table(STAT_CACHE_TABLE).context("ensure STAT_CACHE_TABLE")?;
             write_txn.commit().context("commit schema migration")?;
         }
         Ok(Self { db })
@@ -103,6 +123,35 @@ impl MetadataDb {
         Ok(reached_zero)
     }
 
+    /// Replace all chunk reference counts in one transaction (used by `forge gc`).
+    pub fn rebuild_chunk_counts(&self, counts: &HashMap<[u8; 32], u32>) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn.open_table(CHUNKS_TABLE).context("open chunks table")?;
+            table.retain(|_, _| false).context("clear chunks table")?;
+            for (hash, count) in counts {
+                table
+                    .insert(chunk_hex(hash).as_str(), *count)
+                    .context("insert chunk count")?;
+            }
+        }
+        write_txn.commit().context("commit chunk count rebuild")?;
+        Ok(())
+    }
+
+    /// Drop chunks from the known set so the next `forge add` stores them again.
+    pub fn forget_chunks(&self, hashes: &[[u8; 32]]) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn.open_table(CHUNKS_TABLE).context("open chunks table")?;
+            for hash in hashes {
+                table.remove(chunk_hex(hash).as_str()).context("remove chunk key")?;
+            }
+        }
+        write_txn.commit().context("commit forget chunks")?;
+        Ok(())
+    }
+
     pub fn stage_file(&self, path: &str, entry_bytes: &[u8]) -> Result<()> {
         let write_txn = self.db.begin_write().context("begin write transaction")?;
         {
@@ -110,11 +159,55 @@ impl MetadataDb {
                 .open_table(STAGING_TABLE)
                 .context("open staging table")?;
             table.insert(path, entry_bytes).context("insert staged file")?;
+            let mut removals = write_txn
+                .open_table(REMOVALS_TABLE)
+                .context("open removals table")?;
+            removals.remove(path).context("drop pending removal")?;
+            let mut conflicts = write_txn
+                .open_table(CONFLICTS_TABLE)
+                .context("open conflicts table")?;
+            conflicts.remove(path).context("mark conflict resolved")?;
         }
         write_txn.commit().context("commit stage file")?;
         Ok(())
     }
 
+    /// Count one reference per entry of `chunk_refs` and stage `entries`, all in
+    /// a single transaction.
+    pub fn stage_batch(&self, chunk_refs: &[[u8; 32]], entries: &[(String, Vec<u8>)]) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut chunks = write_txn.open_table(CHUNKS_TABLE).context("open chunks table")?;
+            for hash in chunk_refs {
+                let hex = chunk_hex(hash);
+                let count = chunks
+                    .get(hex.as_str())
+                    .context("get chunk count")?
+                    .map(|v| v.value())
+                    .unwrap_or(0);
+                chunks
+                    .insert(hex.as_str(), count.saturating_add(1))
+                    .context("insert chunk count")?;
+            }
+            let mut table = write_txn
+                .open_table(STAGING_TABLE)
+                .context("open staging table")?;
+            let mut removals = write_txn
+                .open_table(REMOVALS_TABLE)
+                .context("open removals table")?;
+            let mut conflicts = write_txn
+                .open_table(CONFLICTS_TABLE)
+                .context("open conflicts table")?;
+            for (path, entry_bytes) in entries {
+                table.insert(path.as_str(), entry_bytes.as_slice()).context("insert staged file")?;
+                removals.remove(path.as_str()).context("drop pending removal")?;
+                conflicts.remove(path.as_str()).context("mark conflict resolved")?;
+            }
+        }
+        write_txn.commit().context("commit staged batch")?;
+        Ok(())
+    }
+
     pub fn unstage_file(&self, path: &str) -> Result<()> {
         let write_txn = self.db.begin_write().context("begin write transaction")?;
         {
@@ -127,6 +220,59 @@ impl MetadataDb {
         Ok(())
     }
 
+    /// Stage `path` for deletion, or as the source of a rename when `renamed_to` is set.
+    pub fn stage_removal(&self, path: &str, renamed_to: Option<&str>) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut removals = write_txn
+                .open_table(REMOVALS_TABLE)
+                .context("open removals table")?;
+            removals
+                .insert(path, renamed_to.unwrap_or(""))
+                .context("insert staged removal")?;
+            let mut staging = write_txn
+                .open_table(STAGING_TABLE)
+                .context("open staging table")?;
+            staging.remove(path).context("drop staged addition")?;
+            let mut conflicts = write_txn
+                .open_table(CONFLICTS_TABLE)
+                .context("open conflicts table")?;
+            conflicts.remove(path).context("mark conflict resolved")?;
+        }
+        write_txn.commit().context("commit stage removal")?;
+        Ok(())
+    }
+
+    pub fn unstage_removal(&self, path: &str) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn
+                .open_table(REMOVALS_TABLE)
+                .context("open removals table")?;
+            table.remove(path).context("remove staged removal")?;
+        }
+        write_txn.commit().context("commit unstage removal")?;
+        Ok(())
+    }
+
+    /// Staged removals as `(path, rename destination)`.
+    pub fn get_staged_removals(&self) -> Result<Vec<(String, Option<String>)>> {
+        let read_txn = self.db.begin_read().context("begin read transaction")?;
+        let table = read_txn
+            .open_table(REMOVALS_TABLE)
+            .context("open removals table")?;
+        let mut out = Vec::new();
+        for entry in table.iter().context("iterate removals table")? {
+            let (key, val) = entry.context("read removals row")?;
+            let dest = val.value();
+            out.push((
+                key.value().to_string(),
+                (!dest.is_empty()).then(|| dest.to_string()),
+            ));
+        }
+        Ok(out)
+    }
+
     pub fn get_staged_files(&self) -> Result<Vec<(String, Vec<u8>)>> {
         let read_txn = self.db.begin_read().context("begin read transaction")?;
         let table = read_txn
@@ -154,11 +300,52 @@ impl MetadataDb {
             for key in keys {
                 table.remove(key.as_str()).context("remove staged key")?;
             }
+            let mut removals = write_txn
+                .open_table(REMOVALS_TABLE)
+                .context("open removals table")?;
+            removals.retain(|_, _| false).context("clear removals table")?;
         }
         write_txn.commit().context("commit clear staging")?;
         Ok(())
     }
 
+    pub fn record_conflict(&self, path: &str, conflict_bytes: &[u8]) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn
+                .open_table(CONFLICTS_TABLE)
+                .context("open conflicts table")?;
+            table.insert(path, conflict_bytes).context("insert conflict")?;
+        }
+        write_txn.commit().context("commit record conflict")?;
+        Ok(())
+    }
+
+    pub fn get_conflicts(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let read_txn = self.db.begin_read().context("begin read transaction")?;
+        let table = read_txn
+            .open_table(CONFLICTS_TABLE)
+            .context("open conflicts table")?;
+        let mut out = Vec::new();
+        for entry in table.iter().context("iterate conflicts table")? {
+            let (key, val) = entry.context("read conflicts row")?;
+            out.push((key.value().to_string(), val.value().to_vec()));
+        }
+        Ok(out)
+    }
+
+    pub fn clear_conflicts(&self) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn
+                .open_table(CONFLICTS_TABLE)
+                .context("open conflicts table")?;
+            table.retain(|_, _| false).context("clear conflicts table")?;
+        }
+        write_txn.commit().context("commit clear conflicts")?;
+        Ok(())
+    }
+
     pub fn store_commit(&self, id_hex: &str, commit_bytes: &[u8]) -> Result<()> {
         let write_txn = self.db.begin_write().context("begin write transaction")?;
         {
@@ -184,6 +371,19 @@ impl MetadataDb {
             .map(|v| v.value().to_vec()))
     }
 
+    pub fn list_commit_ids(&self) -> Result<Vec<String>> {
+        let read_txn = self.db.begin_read().context("begin read transaction")?;
+        let table = read_txn
+            .open_table(COMMITS_TABLE)
+            .context("open commits table")?;
+        let mut out = Vec::new();
+        for entry in table.iter().context("iterate commits table")? {
+            let (key, _) = entry.context("read commits row")?;
+            out.push(key.value().to_string());
+        }
+        Ok(out)
+    }
+
     pub fn store_file_entry(&self, path: &str, entry_bytes: &[u8]) -> Result<()> {
         let write_txn = self.db.begin_write().context("begin write transaction")?;
         {
@@ -194,6 +394,16 @@ impl MetadataDb {
         Ok(())
     }
 
+    pub fn remove_file_entry(&self, path: &str) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn.open_table(FILES_TABLE).context("open files table")?;
+            table.remove(path).context("remove file entry")?;
+        }
+        write_txn.commit().context("commit file entry removal")?;
+        Ok(())
+    }
+
     pub fn get_file_entry(&self, path: &str) -> Result<Option<Vec<u8>>> {
         let read_txn = self.db.begin_read().context("begin read transaction")?;
         let table = read_txn.open_table(FILES_TABLE).context("open files table")?;
@@ -214,6 +424,58 @@ impl MetadataDb {
         Ok(out)
     }
 
+    /// Replace the whole tracked-file table in one transaction (used after switching trees).
+    pub fn replace_tracked_files(&self, entries: &[(String, Vec<u8>)]) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn.open_table(FILES_TABLE).context("open files table")?;
+            table.retain(|_, _| false).context("clear files table")?;
+            for (path, bytes) in entries {
+                table
+                    .insert(path.as_str(), bytes.as_slice())
+                    .context("insert file entry")?;
+            }
+        }
+        write_txn.commit().context("commit tracked files replace")?;
+        Ok(())
+    }
+
+    // ---- stat cache --------------------------------------------------------
+
+    /// Every cached stat record, keyed by path.
+    pub fn get_stat_cache(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let read_txn = self.db.begin_read().context("begin read transaction")?;
+        let table = read_txn
+            .open_table(STAT_CACHE_TABLE)
+            .context("open stat cache table")?;
+        let mut out = Vec::new();
+        for entry in table.iter().context("iterate stat cache")? {
+            let (key, value) = entry.context("read stat cache row")?;
+            out.push((key.value().to_string(), value.value().to_vec()));
+        }
+        Ok(out)
+    }
+
+    /// Insert or replace the records in `upserts` and drop those for `removed`.
+    pub fn update_stat_cache(&self, upserts: &[(String, Vec<u8>)], removed: &[String]) -> Result<()> {
+        let write_txn = self.db.begin_write().context("begin write transaction")?;
+        {
+            let mut table = write_txn
+                .open_table(STAT_CACHE_TABLE)
+                .context("open stat cache table")?;
+            for path in removed {
+                table.remove(path.as_str()).context("remove stat record")?;
+            }
+            for (path, record) in upserts {
+                table
+                    .insert(path.as_str(), record.as_slice())
+                    .context("insert stat record")?;
+            }
+        }
+        write_txn.commit().context("commit stat cache")?;
+        Ok(())
+    }
+
     // ---- mirror target persistence -----------------------------------------
 
     /// Store mirror targets (as JSON bytes) for a file path.
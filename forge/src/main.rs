@@ -1,6 +1,7 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use forge::cli;
+use forge::cli::merge::Side;
 use mimalloc::MiMalloc;
 use tracing_subscriber::EnvFilter;
 
@@ -25,6 +26,9 @@ enum Command {
     Init {
         #[arg(default_value = ".")]
         path: String,
+        /// Create a repository without a working tree, for serving
+        #[arg(long)]
+        bare: bool,
     },
     Add {
         paths: Vec<String>,
@@ -36,6 +40,25 @@ enum Command {
         message: String,
     },
     Status,
+    /// Remove files from the working tree and stage their deletion
+    Rm {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Only stage the deletion; keep the file on disk
+        #[arg(long)]
+        cached: bool,
+        /// Remove even if the file has local modifications
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+    /// Move or rename a tracked file or directory
+    Mv {
+        source: String,
+        destination: String,
+        /// Overwrite an existing destination
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
     Log {
         #[arg(short = 'n', long, default_value_t = 20)]
         count: usize,
@@ -46,9 +69,100 @@ enum Command {
         commit1: Option<String>,
         #[arg(long)]
         commit2: Option<String>,
+        /// Note modified PNGs whose decoded pixels are unchanged
+        #[arg(long)]
+        pixels: bool,
     },
     Checkout {
+        /// Commit id (unique prefixes accepted)
         commit_id: String,
+        /// Only write these files or directories from the commit, after `--`
+        #[arg(last = true)]
+        paths: Vec<String>,
+        /// Discard local modifications and staged changes
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+    /// List, create or delete branches
+    Branch {
+        name: Option<String>,
+        /// Commit or branch the new branch starts from (defaults to HEAD)
+        start_point: Option<String>,
+        #[arg(short = 'd', long)]
+        delete: bool,
+    },
+    /// List, create or delete tags
+    Tag {
+        name: Option<String>,
+        /// Commit, branch or tag to tag (defaults to HEAD)
+        target: Option<String>,
+        /// Tag message; implies an annotated tag
+        #[arg(short = 'm', long)]
+        message: Option<String>,
+        #[arg(short = 'a', long)]
+        annotate: bool,
+        #[arg(short = 'd', long)]
+        delete: bool,
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+    /// Switch HEAD to another branch and update the working tree
+    Switch {
+        branch: String,
+        /// Create the branch at HEAD before switching
+        #[arg(short = 'c', long)]
+        create: bool,
+        /// Discard local modifications and staged changes
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+    /// Three-way merge another branch, tag or commit into HEAD
+    Merge {
+        revision: Option<String>,
+        /// Resolve every conflict with our version
+        #[arg(long, conflicts_with = "theirs")]
+        ours: bool,
+        /// Resolve every conflict with their version
+        #[arg(long)]
+        theirs: bool,
+        #[arg(short = 'm', long)]
+        message: Option<String>,
+        /// Abandon an in-progress merge and restore HEAD
+        #[arg(long, conflicts_with_all = ["revision", "ours", "theirs"])]
+        abort: bool,
+    },
+    /// Resolve merge conflicts by picking one side per path
+    Resolve {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[arg(long, conflicts_with = "theirs", required_unless_present = "theirs")]
+        ours: bool,
+        #[arg(long)]
+        theirs: bool,
+    },
+    /// Delete chunks no longer reachable from any ref
+    Gc {
+        /// Keep unreachable chunks written within this many hours
+        #[arg(long, default_value_t = 24)]
+        grace_hours: u64,
+        /// Report what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Verify chunks, manifests and refs
+    Fsck {
+        /// Move corrupt chunks and manifests into .forge/quarantine
+        #[arg(long)]
+        quarantine: bool,
+    },
+    /// Consolidate loose chunks into packs
+    Repack {
+        /// Also merge existing packs into the new ones
+        #[arg(long)]
+        all: bool,
+        /// Upper bound on each pack's size, in MiB
+        #[arg(long, default_value_t = 1024)]
+        max_pack_size: u64,
     },
     Push {
         #[arg(default_value = "origin")]
@@ -75,6 +189,51 @@ enum Command {
         #[arg(default_value = "origin")]
         remote: String,
     },
+    /// Copy a `quic://` repository into a new directory and check it out
+    Clone {
+        url: String,
+        /// Defaults to the remote's host name
+        directory: Option<String>,
+        /// Fetch commits and trees only; chunks are downloaded when first read
+        #[arg(long)]
+        partial: bool,
+        /// Local chunk cache limit for a partial clone (e.g. 20G)
+        #[arg(long, requires = "partial")]
+        cache_size: Option<String>,
+    },
+    /// Write a file's contents, as of a revision, to stdout
+    Cat {
+        path: String,
+        /// Revision to read from (defaults to HEAD)
+        #[arg(short = 'r', long, default_value = "HEAD")]
+        rev: String,
+    },
+    /// Update remote-tracking refs from a remote without touching the working tree
+    Fetch {
+        #[arg(default_value = "origin")]
+        remote: String,
+    },
+    /// Add, list or remove named remotes
+    Remote {
+        #[command(subcommand)]
+        command: Option<RemoteCommand>,
+        /// Show each remote's URL and settings
+        #[arg(short = 'v', long)]
+        verbose: bool,
+    },
+    /// Serve a bare repository to `quic://` remotes
+    Serve {
+        #[arg(default_value = ".")]
+        path: String,
+        #[arg(long, default_value = "0.0.0.0:4433")]
+        listen: String,
+        /// PEM certificate; a self-signed one is generated when omitted
+        #[arg(long)]
+        cert: Option<String>,
+        /// PEM private key for --cert
+        #[arg(long)]
+        key: Option<String>,
+    },
     #[command(name = "train-dict")]
     TrainDict {
         #[arg(long)]
@@ -82,10 +241,43 @@ enum Command {
         #[arg(long)]
         samples: String,
         #[arg(long)]
-        output: String,
+        output: Option<String>,
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum RemoteCommand {
+    /// Register a remote: a `quic://` server, or mirror backends
+    Add {
+        name: String,
+        url: String,
+        /// Mirror modes or backends to push to (mirror remotes; defaults to all-free)
+        #[arg(long, value_delimiter = ',')]
+        mirror: Vec<String>,
+        /// owner/repo for the GitHub mirror backend
+        #[arg(long)]
+        github_repo: Option<String>,
+        /// PEM certificate to trust for this server, relative to the repository root
+        #[arg(long)]
+        ca: Option<String>,
+    },
+    /// List remotes
+    List {
+        #[arg(short = 'v', long)]
+        verbose: bool,
+    },
+    /// Remove a remote and its remote-tracking refs
+    Remove { name: String },
+}
+
+fn side(ours: bool, theirs: bool) -> Option<Side> {
+    match (ours, theirs) {
+        (true, _) => Some(Side::Ours),
+        (_, true) => Some(Side::Theirs),
+        _ => None,
+    }
+}
+
 fn init_tracing(verbose: bool) {
     let default_level = if verbose { "debug" } else { "info" };
     let filter = EnvFilter::try_from_default_env()
@@ -102,7 +294,7 @@ fn main() -> Result<()> {
     }
 
     match args.command {
-        Command::Init { path } => cli::init::run(&path),
+        Command::Init { path, bare } => cli::init::run(&path, bare),
         Command::Add { mut paths, force } => {
             if paths.is_empty() {
                 paths.push(".".to_string());
@@ -111,21 +303,110 @@ fn main() -> Result<()> {
         }
         Command::Commit { message } => cli::commit::run(&message),
         Command::Status => cli::status::run(),
+        Command::Rm {
+            paths,
+            cached,
+            force,
+        } => cli::rm::run(&paths, cached, force),
+        Command::Mv {
+            source,
+            destination,
+            force,
+        } => cli::mv::run(&source, &destination, force),
         Command::Log { count } => cli::log::run(count),
         Command::Diff {
             path,
             commit1,
             commit2,
-        } => cli::diff::run(path.as_deref(), commit1.as_deref(), commit2.as_deref()),
-        Command::Checkout { commit_id } => cli::checkout::run(&commit_id),
+            pixels,
+        } => cli::diff::run(path.as_deref(), commit1.as_deref(), commit2.as_deref(), pixels),
+        Command::Checkout {
+            commit_id,
+            paths,
+            force,
+        } => cli::checkout::run(&commit_id, &paths, force),
+        Command::Branch {
+            name,
+            start_point,
+            delete,
+        } => cli::branch::run(name.as_deref(), start_point.as_deref(), delete),
+        Command::Tag {
+            name,
+            target,
+            message,
+            annotate,
+            delete,
+            force,
+        } => cli::tag::run(
+            name.as_deref(),
+            target.as_deref(),
+            message.as_deref(),
+            annotate,
+            delete,
+            force,
+        ),
+        Command::Switch {
+            branch,
+            create,
+            force,
+        } => cli::switch::run(&branch, create, force),
+        Command::Merge {
+            revision,
+            ours,
+            theirs,
+            message,
+            abort,
+        } => cli::merge::run(
+            revision.as_deref(),
+            side(ours, theirs),
+            message.as_deref(),
+            abort,
+        ),
+        Command::Resolve {
+            paths,
+            ours,
+            theirs,
+        } => cli::resolve::run(&paths, side(ours, theirs).unwrap_or(Side::Ours)),
+        Command::Gc {
+            grace_hours,
+            dry_run,
+        } => cli::gc::run(grace_hours, dry_run),
+        Command::Fsck { quarantine } => cli::fsck::run(quarantine),
+        Command::Repack { all, max_pack_size } => cli::repack::run(all, max_pack_size),
         Command::Push { remote, mirror, pro } => cli::push::run(&remote, mirror.as_deref(), pro),
         Command::Pull { remote } => cli::pull::run(&remote),
+        Command::Clone {
+            url,
+            directory,
+            partial,
+            cache_size,
+        } => cli::clone::run(&url, directory.as_deref(), partial, cache_size.as_deref()),
+        Command::Cat { path, rev } => cli::cat::run(&path, &rev),
+        Command::Fetch { remote } => cli::fetch::run(&remote),
+        Command::Remote { command, verbose } => match command {
+            Some(RemoteCommand::Add {
+                name,
+                url,
+                mirror,
+                github_repo,
+                ca,
+            }) => cli::remote::add(&name, &url, &mirror, github_repo.as_deref(), ca.as_deref()),
+            Some(RemoteCommand::List { verbose: list_verbose }) => cli::remote::list(verbose || list_verbose),
+            Some(RemoteCommand::Remove { name }) => cli::remote::remove(&name),
+            None => cli::remote::list(verbose),
+        },
+        Command::Serve {
+            path,
+            listen,
+            cert,
+            key,
+        } => cli::serve::run(&path, &listen, cert.as_deref(), key.as_deref()),
         Command::Auth { backend, token } => cli::auth::run(&backend, token.as_deref()),
         Command::VibeDemo => cli::vibe_demo::run(),
         Command::TrainDict {
             file_type,
             samples,
             output,
-        } => cli::train_dict::run(&file_type, &samples, &output),
+        } => cli::train_dict::run(&file_type, &samples, output.as_deref()),
     }
 }
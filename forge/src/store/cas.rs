@@ -1,18 +1,121 @@
 use std::fs;
 use std::io::ErrorKind;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use walkdir::WalkDir;
 
+use crate::store::compression::Dictionaries;
+use crate::store::pack::{list_packs, PackFile};
+
+/// Distinguishes temp files when several threads store chunks at once.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Where a partial clone gets the chunks it does not hold.
+pub trait ChunkSource: Send + Sync + std::fmt::Debug {
+    /// Store every chunk of `hashes` in `store`.
+    fn fetch(&self, store: &ChunkStore, hashes: &[[u8; 32]]) -> Result<()>;
+}
+
+/// Content-addressed chunk storage: loose files under `base_dir`, plus packs
+/// in the sibling `packs` directory. Reads look in both tiers, then in the
+/// source of a partial clone.
 #[derive(Debug, Clone)]
 pub struct ChunkStore {
     pub base_dir: PathBuf,
+    pub pack_dir: PathBuf,
+    packs: Arc<RwLock<Option<Arc<Vec<PackFile>>>>>,
+    dictionaries: Arc<Dictionaries>,
+    source: Option<Arc<dyn ChunkSource>>,
 }
 
 impl ChunkStore {
     pub fn new(base_dir: PathBuf) -> Self {
-        Self { base_dir }
+        let pack_dir = base_dir.with_file_name("packs");
+        Self {
+            base_dir,
+            pack_dir,
+            packs: Arc::default(),
+            dictionaries: Arc::default(),
+            source: None,
+        }
+    }
+
+    pub fn with_dictionaries(mut self, dictionaries: Dictionaries) -> Self {
+        self.dictionaries = Arc::new(dictionaries);
+        self
+    }
+
+    pub fn dictionaries(&self) -> &Dictionaries {
+        &self.dictionaries
+    }
+
+    pub fn with_source(mut self, source: Arc<dyn ChunkSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Whether missing chunks can be fetched on demand.
+    pub fn is_partial(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Fetch whichever of `hashes` are not held locally, in one batch.
+    /// Returns how many were fetched; without a source, nothing is.
+    pub fn fetch_missing(&self, hashes: &[[u8; 32]]) -> Result<usize> {
+        let Some(source) = &self.source else {
+            return Ok(0);
+        };
+        let mut missing: Vec<[u8; 32]> = hashes
+            .iter()
+            .filter(|hash| !self.contains(&blake3::Hash::from(**hash)))
+            .copied()
+            .collect();
+        missing.sort();
+        missing.dedup();
+        if !missing.is_empty() {
+            source.fetch(self, &missing)?;
+        }
+        Ok(missing.len())
+    }
+
+    /// Mapped packs, loaded on first use.
+    pub fn packs(&self) -> Result<Arc<Vec<PackFile>>> {
+        if let Some(packs) = self.packs.read().expect("pack cache poisoned").as_ref() {
+            return Ok(Arc::clone(packs));
+        }
+        let loaded = Arc::new(
+            list_packs(&self.pack_dir)?
+                .iter()
+                .map(|path| PackFile::open(path))
+                .collect::<Result<Vec<_>>>()?,
+        );
+        *self.packs.write().expect("pack cache poisoned") = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+
+    /// Forget the mapped packs so the next lookup sees packs written or removed since.
+    pub fn reload_packs(&self) {
+        *self.packs.write().expect("pack cache poisoned") = None;
+    }
+
+    pub fn is_loose(&self, hash: &blake3::Hash) -> bool {
+        self.chunk_path(hash).exists()
+    }
+
+    pub fn is_packed(&self, hash: &blake3::Hash) -> Result<bool> {
+        Ok(self.packs()?.iter().any(|pack| pack.find(hash.as_bytes()).is_some()))
+    }
+
+    fn read_packed(&self, hash: &blake3::Hash) -> Result<Option<Vec<u8>>> {
+        for pack in self.packs()?.iter() {
+            if let Some(entry) = pack.find(hash.as_bytes()) {
+                return pack.read(&entry).map(Some);
+            }
+        }
+        Ok(None)
     }
 
     pub fn chunk_path(&self, hash: &blake3::Hash) -> PathBuf {
@@ -23,19 +126,23 @@ impl ChunkStore {
     }
 
     pub fn contains(&self, hash: &blake3::Hash) -> bool {
-        self.chunk_path(hash).exists()
+        self.is_loose(hash) || self.is_packed(hash).unwrap_or(false)
     }
 
     pub fn store(&self, hash: &blake3::Hash, compressed_data: &[u8]) -> Result<bool> {
         let target = self.chunk_path(hash);
-        if target.exists() {
+        if self.contains(hash) {
             return Ok(false);
         }
 
         let parent = target.parent().context("chunk path missing parent")?;
         fs::create_dir_all(parent).with_context(|| format!("create shard dir {}", parent.display()))?;
 
-        let tmp = target.with_extension(format!("tmp.{}", std::process::id()));
+        let tmp = target.with_extension(format!(
+            "tmp.{}.{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
         fs::write(&tmp, compressed_data).with_context(|| format!("write temp chunk {}", tmp.display()))?;
 
         match fs::rename(&tmp, &target) {
@@ -53,9 +160,28 @@ impl ChunkStore {
 
     pub fn read(&self, hash: &blake3::Hash) -> Result<Vec<u8>> {
         let path = self.chunk_path(hash);
-        fs::read(&path).with_context(|| format!("read chunk {}", path.display()))
+        match fs::read(&path) {
+            Ok(bytes) => Ok(bytes),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if let Some(bytes) = self.read_packed(hash)? {
+                    return Ok(bytes);
+                }
+                let Some(source) = &self.source else {
+                    return Err(anyhow!("chunk {} not found in loose storage or packs", hash.to_hex()));
+                };
+                source.fetch(self, &[*hash.as_bytes()])?;
+                fs::read(&path).with_context(|| format!("read fetched chunk {}", hash.to_hex()))
+            }
+            Err(err) => Err(err).with_context(|| format!("read chunk {}", path.display())),
+        }
+    }
+
+    /// Read and decompress a chunk, using whichever dictionary its frame names.
+    pub fn read_decompressed(&self, hash: &blake3::Hash) -> Result<Vec<u8>> {
+        self.dictionaries.decompress(&self.read(hash)?)
     }
 
+    /// Remove the loose copy of a chunk; packed copies are only dropped by rewriting the pack.
     pub fn remove(&self, hash: &blake3::Hash) -> Result<bool> {
         let path = self.chunk_path(hash);
         match fs::remove_file(&path) {
@@ -65,7 +191,7 @@ impl ChunkStore {
         }
     }
 
-    pub fn list_all(&self) -> Result<Vec<blake3::Hash>> {
+    pub fn list_loose(&self) -> Result<Vec<blake3::Hash>> {
         if !self.base_dir.exists() {
             return Ok(Vec::new());
         }
@@ -111,10 +237,16 @@ impl ChunkStore {
         {
             total += entry.metadata().with_context(|| "metadata read failed")?.len();
         }
+        for pack in self.packs()?.iter() {
+            total += fs::metadata(&pack.pack_path)
+                .with_context(|| format!("stat {}", pack.pack_path.display()))?
+                .len();
+        }
         Ok(total)
     }
 
     pub fn chunk_count(&self) -> Result<usize> {
-        Ok(self.list_all()?.len())
+        let packed: usize = self.packs()?.iter().map(PackFile::len).sum();
+        Ok(self.list_loose()?.len() + packed)
     }
 }
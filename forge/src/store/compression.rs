@@ -1,4 +1,12 @@
-use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use crate::core::manifest::FileType;
 
 pub fn compress(data: &[u8], level: i32) -> Result<Vec<u8>> {
     zstd::encode_all(data, level).context("zstd compress failed")
@@ -28,3 +36,112 @@ pub fn train_dictionary(samples: &[Vec<u8>], dict_size: usize) -> Result<Vec<u8>
     }
     zstd::dict::from_samples(samples, dict_size).context("dictionary training failed")
 }
+
+/// Dictionary id stamped in a zstd frame header, or `None` for frames compressed without one.
+pub fn frame_dict_id(data: &[u8]) -> Option<u32> {
+    zstd::zstd_safe::get_dict_id_from_frame(data).map(|id| id.get())
+}
+
+/// Zstd dictionaries: every one ever trained in the repository is kept for
+/// decompressing by the frame's dictionary id, and those registered in
+/// `config.toml` are looked up by extension or file type when compressing.
+#[derive(Default)]
+pub struct Dictionaries {
+    encoders: HashMap<u32, EncoderDictionary<'static>>,
+    decoders: HashMap<u32, DecoderDictionary<'static>>,
+    by_key: HashMap<String, u32>,
+}
+
+impl fmt::Debug for Dictionaries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dictionaries").field("by_key", &self.by_key).finish()
+    }
+}
+
+/// Dictionary id of a trained zstd dictionary.
+pub fn dictionary_id(raw: &[u8]) -> Option<u32> {
+    zstd::zstd_safe::get_dict_id_from_dict(raw).map(|id| id.get())
+}
+
+/// File a dictionary is archived under in `.forge/dictionaries`.
+pub fn dictionary_file_name(id: u32) -> String {
+    format!("{id:08x}.dict")
+}
+
+impl Dictionaries {
+    /// Load every dictionary in `base/dictionaries` for decompression, then
+    /// each `key = "path"` entry of `registry` for compression; relative
+    /// paths resolve against `base`.
+    pub fn load(base: &Path, registry: &BTreeMap<String, String>, level: i32) -> Result<Self> {
+        let mut dicts = Self::default();
+        let archive = base.join("dictionaries");
+        if archive.is_dir() {
+            for entry in fs::read_dir(&archive).with_context(|| format!("read {}", archive.display()))? {
+                let path = entry.context("read dictionaries entry")?.path();
+                if path.extension().is_some_and(|ext| ext == "dict") {
+                    let raw = fs::read(&path).with_context(|| format!("read dictionary {}", path.display()))?;
+                    if let Some(id) = dictionary_id(&raw) {
+                        dicts.decoders.entry(id).or_insert_with(|| DecoderDictionary::copy(&raw));
+                    }
+                }
+            }
+        }
+
+        for (key, rel) in registry {
+            let path = base.join(rel);
+            let raw = fs::read(&path).with_context(|| format!("read dictionary {}", path.display()))?;
+            let Some(id) = dictionary_id(&raw) else {
+                bail!("{} is not a trained zstd dictionary", path.display());
+            };
+            dicts.encoders.entry(id).or_insert_with(|| EncoderDictionary::copy(&raw, level));
+            dicts.decoders.entry(id).or_insert_with(|| DecoderDictionary::copy(&raw));
+            dicts.by_key.insert(dictionary_key(key), id);
+        }
+        Ok(dicts)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_key.is_empty()
+    }
+
+    /// Dictionary to compress `path` with: its extension wins over its detected file type.
+    pub fn for_file(&self, path: &str, file_type: FileType) -> Option<u32> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(dictionary_key);
+        ext.and_then(|ext| self.by_key.get(&ext))
+            .or_else(|| self.by_key.get(file_type.name()))
+            .copied()
+    }
+
+    pub fn compress(&self, data: &[u8], level: i32, dict_id: Option<u32>) -> Result<Vec<u8>> {
+        let Some(dict) = dict_id.and_then(|id| self.encoders.get(&id)) else {
+            return compress(data, level);
+        };
+        let mut encoder = zstd::stream::Encoder::with_prepared_dictionary(Vec::new(), dict)
+            .context("encoder with dict")?;
+        std::io::Write::write_all(&mut encoder, data).context("write payload to zstd encoder")?;
+        encoder.finish().context("finalize zstd dict compression")
+    }
+
+    /// Decompress a chunk, picking the dictionary named in its frame header.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(id) = frame_dict_id(data) else {
+            return decompress(data);
+        };
+        let Some(dict) = self.decoders.get(&id) else {
+            bail!("chunk was compressed with dictionary {id:#010x}, which is not in .forge/dictionaries");
+        };
+        let mut decoder =
+            zstd::stream::Decoder::with_prepared_dictionary(data, dict).context("decoder with dict")?;
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut decoder, &mut out).context("read zstd dict payload")?;
+        Ok(out)
+    }
+}
+
+/// Registry keys are case-insensitive and may be written with a leading dot.
+pub fn dictionary_key(raw: &str) -> String {
+    raw.trim_start_matches('.').to_ascii_lowercase()
+}
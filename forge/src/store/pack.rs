@@ -1,15 +1,13 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use memmap2::Mmap;
 
 use crate::store::cas::ChunkStore;
 
-#[derive(Debug, Clone)]
-pub struct PackFile;
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PackIndexEntry {
     pub hash: [u8; 32],
     pub offset: u64,
@@ -21,24 +19,187 @@ pub struct PackIndex {
     pub entries: Vec<PackIndexEntry>,
 }
 
-pub fn create_pack(store: &ChunkStore, chunk_hashes: &[[u8; 32]], output: &Path) -> Result<PackIndex> {
+const TRAILER_ENTRY_SIZE: usize = 32 + 8 + 4;
+
+/// `.idx` layout: magic, version, a 256-slot cumulative fanout keyed on the
+/// first hash byte, then the trailer entries sorted by hash.
+const IDX_MAGIC: &[u8; 4] = b"FIDX";
+const IDX_VERSION: u32 = 1;
+const IDX_HEADER_SIZE: usize = 4 + 4 + 256 * 4;
+
+/// Path of the lookup index that sits next to `pack_path`.
+pub fn pack_idx_path(pack_path: &Path) -> PathBuf {
+    pack_path.with_extension("idx")
+}
+
+/// Write the sorted fanout index for a pack whose trailer holds `index`.
+pub fn write_pack_idx(pack_path: &Path, index: &PackIndex) -> Result<PathBuf> {
+    let mut entries = index.entries.clone();
+    entries.sort_by_key(|e| e.hash);
+
+    let mut fanout = [0u32; 256];
+    for entry in &entries {
+        fanout[entry.hash[0] as usize] += 1;
+    }
+    for i in 1..256 {
+        fanout[i] += fanout[i - 1];
+    }
+
+    let mut buf = Vec::with_capacity(IDX_HEADER_SIZE + entries.len() * TRAILER_ENTRY_SIZE);
+    buf.extend_from_slice(IDX_MAGIC);
+    buf.extend_from_slice(&IDX_VERSION.to_le_bytes());
+    for slot in fanout {
+        buf.extend_from_slice(&slot.to_le_bytes());
+    }
+    for e in &entries {
+        buf.extend_from_slice(&e.hash);
+        buf.extend_from_slice(&e.offset.to_le_bytes());
+        buf.extend_from_slice(&e.length.to_le_bytes());
+    }
+
+    let idx_path = pack_idx_path(pack_path);
+    let tmp = idx_path.with_extension(format!("idx.tmp.{}", std::process::id()));
+    fs::write(&tmp, &buf).with_context(|| format!("write pack index {}", tmp.display()))?;
+    fs::rename(&tmp, &idx_path).with_context(|| format!("install pack index {}", idx_path.display()))?;
+    Ok(idx_path)
+}
+
+/// Delete a pack together with its lookup index.
+pub fn remove_pack(pack_path: &Path) -> Result<()> {
+    fs::remove_file(pack_path).with_context(|| format!("remove pack {}", pack_path.display()))?;
+    let idx_path = pack_idx_path(pack_path);
+    match fs::remove_file(&idx_path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("remove pack index {}", idx_path.display())),
+    }
+}
+
+/// A pack and its `.idx`, both memory-mapped, answering hash lookups by
+/// fanout bucket plus binary search.
+#[derive(Debug)]
+pub struct PackFile {
+    pub pack_path: PathBuf,
+    data: Mmap,
+    idx: Mmap,
+}
+
+impl PackFile {
+    /// Map `pack_path` and its index, regenerating the index from the pack
+    /// trailer when it is missing or unreadable.
+    pub fn open(pack_path: &Path) -> Result<Self> {
+        let idx_path = pack_idx_path(pack_path);
+        let idx = match Self::map_idx(&idx_path) {
+            Ok(idx) => idx,
+            Err(_) => {
+                write_pack_idx(pack_path, &read_pack_index(pack_path)?)?;
+                Self::map_idx(&idx_path)?
+            }
+        };
+
+        let file = File::open(pack_path).with_context(|| format!("open pack {}", pack_path.display()))?;
+        // SAFETY: packs are immutable once installed; rewrites go to a new file.
+        let data = unsafe { Mmap::map(&file) }.with_context(|| format!("mmap pack {}", pack_path.display()))?;
+
+        Ok(Self {
+            pack_path: pack_path.to_path_buf(),
+            data,
+            idx,
+        })
+    }
+
+    fn map_idx(idx_path: &Path) -> Result<Mmap> {
+        let file = File::open(idx_path).with_context(|| format!("open pack index {}", idx_path.display()))?;
+        // SAFETY: indexes are written to a temp file and renamed into place.
+        let idx = unsafe { Mmap::map(&file) }.with_context(|| format!("mmap pack index {}", idx_path.display()))?;
+        if idx.len() < IDX_HEADER_SIZE || &idx[0..4] != IDX_MAGIC {
+            bail!("pack index {} has a bad header", idx_path.display());
+        }
+        if u32::from_le_bytes(idx[4..8].try_into().expect("slice of 4 bytes")) != IDX_VERSION {
+            bail!("pack index {} has an unsupported version", idx_path.display());
+        }
+        let count = u32::from_le_bytes(idx[IDX_HEADER_SIZE - 4..IDX_HEADER_SIZE].try_into().expect("slice of 4 bytes"));
+        if idx.len() != IDX_HEADER_SIZE + count as usize * TRAILER_ENTRY_SIZE {
+            bail!("pack index {} is truncated", idx_path.display());
+        }
+        Ok(idx)
+    }
+
+    fn fanout(&self, slot: usize) -> usize {
+        let at = 8 + slot * 4;
+        u32::from_le_bytes(self.idx[at..at + 4].try_into().expect("slice of 4 bytes")) as usize
+    }
+
+    fn entry_at(&self, i: usize) -> PackIndexEntry {
+        let raw = &self.idx[IDX_HEADER_SIZE + i * TRAILER_ENTRY_SIZE..][..TRAILER_ENTRY_SIZE];
+        PackIndexEntry {
+            hash: raw[0..32].try_into().expect("slice of 32 bytes"),
+            offset: u64::from_le_bytes(raw[32..40].try_into().expect("slice of 8 bytes")),
+            length: u32::from_le_bytes(raw[40..44].try_into().expect("slice of 4 bytes")),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fanout(255)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entries in hash order.
+    pub fn entries(&self) -> impl Iterator<Item = PackIndexEntry> + '_ {
+        (0..self.len()).map(|i| self.entry_at(i))
+    }
+
+    pub fn find(&self, hash: &[u8; 32]) -> Option<PackIndexEntry> {
+        let slot = hash[0] as usize;
+        let mut lo = if slot == 0 { 0 } else { self.fanout(slot - 1) };
+        let mut hi = self.fanout(slot);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let entry = self.entry_at(mid);
+            match entry.hash.cmp(hash) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(entry),
+            }
+        }
+        None
+    }
+
+    /// Compressed bytes of `entry`, bounds-checked against the mapped pack.
+    pub fn read(&self, entry: &PackIndexEntry) -> Result<Vec<u8>> {
+        let start = entry.offset as usize;
+        let end = start + entry.length as usize;
+        match self.data.get(start..end) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => bail!("pack {} entry points past end of file", self.pack_path.display()),
+        }
+    }
+}
+
+/// Write `chunks` (hash, compressed bytes) followed by the index trailer.
+fn write_pack<I>(output: &Path, chunks: I) -> Result<PackIndex>
+where
+    I: IntoIterator<Item = Result<([u8; 32], Vec<u8>)>>,
+{
     let mut file = File::create(output).with_context(|| format!("create pack {}", output.display()))?;
     let mut offset = 0u64;
-    let mut entries = Vec::with_capacity(chunk_hashes.len());
+    let mut entries = Vec::new();
 
-    for hash_arr in chunk_hashes {
-        let hash = blake3::Hash::from(*hash_arr);
-        let data = store.read(&hash)?;
+    for chunk in chunks {
+        let (hash, data) = chunk?;
         file.write_all(&data).context("write chunk to pack")?;
         entries.push(PackIndexEntry {
-            hash: *hash_arr,
+            hash,
             offset,
             length: data.len() as u32,
         });
         offset += data.len() as u64;
     }
 
-    let mut trailer = Vec::new();
+    let mut trailer = Vec::with_capacity(4 + entries.len() * TRAILER_ENTRY_SIZE);
     trailer.extend_from_slice(&(entries.len() as u32).to_le_bytes());
     for e in &entries {
         trailer.extend_from_slice(&e.hash);
@@ -49,10 +210,66 @@ pub fn create_pack(store: &ChunkStore, chunk_hashes: &[[u8; 32]], output: &Path)
     file.write_all(&trailer).context("write index trailer")?;
     file.write_all(&(trailer.len() as u64).to_le_bytes())
         .context("write index trailer size")?;
+    file.sync_all().context("sync pack")?;
 
     Ok(PackIndex { entries })
 }
 
+/// Pack `chunk_hashes` into `output` and write its lookup index alongside.
+pub fn create_pack(store: &ChunkStore, chunk_hashes: &[[u8; 32]], output: &Path) -> Result<PackIndex> {
+    let tmp = output.with_extension(format!("tmp.{}", std::process::id()));
+    let index = write_pack(
+        &tmp,
+        chunk_hashes
+            .iter()
+            .map(|hash| Ok((*hash, store.read(&blake3::Hash::from(*hash))?))),
+    )?;
+    fs::rename(&tmp, output).with_context(|| format!("install pack {}", output.display()))?;
+    write_pack_idx(output, &index)?;
+    Ok(index)
+}
+
+/// Parse the index trailer at the end of a pack file.
+pub fn read_pack_index(pack_path: &Path) -> Result<PackIndex> {
+    let mut file = File::open(pack_path).with_context(|| format!("open pack {}", pack_path.display()))?;
+    let file_len = file.metadata().context("stat pack")?.len();
+    if file_len < 8 {
+        bail!("pack {} is truncated", pack_path.display());
+    }
+
+    file.seek(SeekFrom::End(-8)).context("seek to trailer size")?;
+    let mut size_buf = [0u8; 8];
+    file.read_exact(&mut size_buf).context("read trailer size")?;
+    let trailer_len = u64::from_le_bytes(size_buf);
+    let Some(data_len) = file_len
+        .checked_sub(8)
+        .and_then(|len| len.checked_sub(trailer_len))
+        .filter(|_| trailer_len >= 4)
+    else {
+        bail!("pack {} has a corrupt index trailer", pack_path.display());
+    };
+    file.seek(SeekFrom::Start(data_len)).context("seek to trailer")?;
+    let mut trailer = vec![0u8; trailer_len as usize];
+    file.read_exact(&mut trailer).context("read index trailer")?;
+
+    let count = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) as usize;
+    if trailer.len() != 4 + count * TRAILER_ENTRY_SIZE {
+        bail!("pack {} index entry count mismatch", pack_path.display());
+    }
+
+    let mut entries = Vec::with_capacity(count);
+    for raw in trailer[4..].chunks_exact(TRAILER_ENTRY_SIZE) {
+        let hash: [u8; 32] = raw[0..32].try_into().expect("slice of 32 bytes");
+        let offset = u64::from_le_bytes(raw[32..40].try_into().expect("slice of 8 bytes"));
+        let length = u32::from_le_bytes(raw[40..44].try_into().expect("slice of 4 bytes"));
+        if offset.checked_add(length as u64).is_none_or(|end| end > data_len) {
+            bail!("pack {} entry points past the data section", pack_path.display());
+        }
+        entries.push(PackIndexEntry { hash, offset, length });
+    }
+    Ok(PackIndex { entries })
+}
+
 pub fn read_from_pack(pack_path: &Path, index_entry: &PackIndexEntry) -> Result<Vec<u8>> {
     let mut file = File::open(pack_path).with_context(|| format!("open pack {}", pack_path.display()))?;
     file.seek(SeekFrom::Start(index_entry.offset))
@@ -61,3 +278,61 @@ pub fn read_from_pack(pack_path: &Path, index_entry: &PackIndexEntry) -> Result<
     file.read_exact(&mut buf).context("read chunk from pack")?;
     Ok(buf)
 }
+
+/// All `*.pack` files in `pack_dir`, sorted by name.
+pub fn list_packs(pack_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !pack_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for entry in fs::read_dir(pack_dir).with_context(|| format!("read {}", pack_dir.display()))? {
+        let path = entry.context("read pack dir entry")?.path();
+        if path.extension().is_some_and(|ext| ext == "pack") {
+            out.push(path);
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Content-derived file name for a pack holding `hashes`.
+pub fn pack_name(hashes: &[[u8; 32]]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for hash in hashes {
+        hasher.update(hash);
+    }
+    format!("pack-{}.pack", hasher.finalize().to_hex())
+}
+
+/// Rewrite `pack_path` keeping only the chunks for which `keep` returns true.
+///
+/// Returns the path of the replacement pack, or `None` when nothing survived
+/// and the pack was deleted outright.
+pub fn rewrite_pack<F>(pack_path: &Path, index: &PackIndex, keep: F) -> Result<Option<PathBuf>>
+where
+    F: Fn(&[u8; 32]) -> bool,
+{
+    let survivors: Vec<&PackIndexEntry> = index.entries.iter().filter(|e| keep(&e.hash)).collect();
+    if survivors.is_empty() {
+        remove_pack(pack_path)?;
+        return Ok(None);
+    }
+
+    let dir = pack_path.parent().context("pack path missing parent")?;
+    let hashes: Vec<[u8; 32]> = survivors.iter().map(|e| e.hash).collect();
+    let target = dir.join(pack_name(&hashes));
+    let tmp = target.with_extension(format!("tmp.{}", std::process::id()));
+
+    let new_index = write_pack(
+        &tmp,
+        survivors
+            .iter()
+            .map(|e| Ok((e.hash, read_from_pack(pack_path, e)?))),
+    )?;
+    fs::rename(&tmp, &target).with_context(|| format!("install pack {}", target.display()))?;
+    write_pack_idx(&target, &new_index)?;
+    if target != pack_path {
+        remove_pack(pack_path)?;
+    }
+    Ok(Some(target))
+}
@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::graph::ancestors;
+use crate::core::repository::Repository;
+use crate::db::metadata::MetadataDb;
+use crate::store::cas::ChunkStore;
+use crate::transport::negotiate::{missing, partition, unpack_bits, BloomFilter, EXACT_OFFER_LIMIT};
+use crate::transport::objects::{
+    accept_chunk, accept_manifest, accept_tree, check_complete, commits_between, have_bitmaps, objects_for,
+    wire_chunk,
+};
+use crate::transport::protocol::{
+    Channel, ClientMessage, ServerMessage, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+/// Manifests a push sends ahead of their acks. The server answers every one,
+/// so an unbounded run fills the stream in both directions and stalls.
+const UNACKED_MANIFESTS: usize = 256;
+
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    pub commits: usize,
+    pub trees: usize,
+    pub chunks: usize,
+    /// Compressed chunk bytes sent or received.
+    pub chunk_bytes: u64,
+}
+
+/// Refs a server advertises after the handshake.
+#[derive(Debug, Default)]
+pub struct RemoteRefs {
+    /// Full ref names (`refs/heads/main`) with their commits.
+    pub refs: Vec<(String, [u8; 32])>,
+    /// Ref the server's HEAD points at.
+    pub head: Option<String>,
+    /// Protocol version agreed in the handshake.
+    pub version: u32,
+}
+
+impl RemoteRefs {
+    pub fn get(&self, name: &str) -> Option<[u8; 32]> {
+        self.refs.iter().find(|(n, _)| n == name).map(|(_, id)| *id)
+    }
+
+    /// Branches by short name.
+    pub fn branches(&self) -> impl Iterator<Item = (&str, [u8; 32])> {
+        self.refs
+            .iter()
+            .filter_map(|(name, id)| Some((name.strip_prefix("refs/heads/")?, *id)))
+    }
+
+    /// Branch a clone checks out: the server's HEAD when it names an existing
+    /// branch, else `main`, else the first branch.
+    pub fn default_branch(&self) -> Option<(&str, [u8; 32])> {
+        let head = self.head.as_deref().and_then(|h| h.strip_prefix("refs/heads/"));
+        self.branches()
+            .find(|(name, _)| Some(*name) == head)
+            .or_else(|| self.branches().find(|(name, _)| *name == "main"))
+            .or_else(|| self.branches().next())
+    }
+}
+
+/// Greet the server and return its refs.
+pub async fn handshake<S: AsyncRead + AsyncWrite>(chan: &mut Channel<S>) -> Result<RemoteRefs> {
+    chan.send(&ClientMessage::Hello {
+        client: format!("forge {}", env!("CARGO_PKG_VERSION")),
+        min_version: MIN_PROTOCOL_VERSION,
+        max_version: PROTOCOL_VERSION,
+    })
+    .await?;
+    let ServerMessage::Welcome { server, version } = chan.reply().await? else {
+        bail!("unexpected reply to Hello");
+    };
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        bail!("{server} chose protocol {version}, which this client does not speak");
+    }
+
+    chan.send(&ClientMessage::ListRefs).await?;
+    let ServerMessage::Refs { refs, head } = chan.reply().await? else {
+        bail!("unexpected reply to ListRefs");
+    };
+    Ok(RemoteRefs { refs, head, version })
+}
+
+/// Send whatever the server lacks for `local` and move its `refname` from
+/// `remote` to `local`. `advertised` is every ref from the handshake.
+pub async fn push<S: AsyncRead + AsyncWrite>(
+    repo: &Repository,
+    chan: &mut Channel<S>,
+    refname: &str,
+    local: &[u8; 32],
+    remote: Option<&[u8; 32]>,
+    advertised: &[(String, [u8; 32])],
+) -> Result<TransferStats> {
+    let mut stats = TransferStats::default();
+    if let Some(remote) = remote {
+        if !repo.manifest_path(remote).exists() || !ancestors(repo, local)?.contains(remote) {
+            bail!("{refname} on the remote has commits you do not have; pull before pushing");
+        }
+    }
+
+    let (commits, known) = common_history(repo, chan, local, advertised).await?;
+    let (trees, chunks) = objects_for(repo, &commits, &known)?;
+    let (trees, chunks) = negotiate_objects(chan, trees, chunks).await?;
+
+    let store = repo.chunk_store()?;
+    let level = repo.read_config()?.compression_level;
+    for id in &trees {
+        let data = fs::read(repo.tree_object_path(id))
+            .with_context(|| format!("read tree {}", hex::encode(id)))?;
+        chan.send(&ClientMessage::TreeData { id: *id, data })
+        .await?;
+    }
+    for hash in &chunks {
+        let data = wire_chunk(&store, hash, level)?;
+        stats.chunk_bytes += data.len() as u64;
+        chan.send(&ClientMessage::ChunkData { hash: *hash, data }).await?;
+    }
+    stats.trees = trees.len();
+    stats.chunks = chunks.len();
+
+    let mut unacked = 0;
+    for id in &commits {
+        let path = repo.manifest_path(id);
+        let data = fs::read(&path).with_context(|| format!("read manifest {}", path.display()))?;
+        chan.send(&ClientMessage::PushManifest { commit_id: *id, data }).await?;
+        unacked += 1;
+        if unacked == UNACKED_MANIFESTS {
+            expect_ack(chan).await?;
+            unacked -= 1;
+        }
+    }
+    for _ in 0..unacked {
+        expect_ack(chan).await?;
+    }
+    stats.commits = commits.len();
+
+    chan.send(&ClientMessage::UpdateRef {
+        name: refname.to_string(),
+        old: remote.copied(),
+        new: *local,
+    })
+    .await?;
+    let ServerMessage::Ok { .. } = chan.reply().await? else {
+        bail!("unexpected reply to UpdateRef");
+    };
+    Ok(stats)
+}
+
+async fn expect_ack<S: AsyncRead + AsyncWrite>(chan: &mut Channel<S>) -> Result<()> {
+    let ServerMessage::AckCommit { .. } = chan.reply().await? else {
+        bail!("unexpected reply to PushManifest");
+    };
+    Ok(())
+}
+
+/// Commits the server is missing behind `local`, and the commits known to be
+/// on both sides. Advertised tips we have locally count as shared outright;
+/// the server is then asked about the remaining candidates, and everything
+/// behind the ones it has is dropped too.
+async fn common_history<S: AsyncRead + AsyncWrite>(
+    repo: &Repository,
+    chan: &mut Channel<S>,
+    local: &[u8; 32],
+    advertised: &[(String, [u8; 32])],
+) -> Result<(Vec<[u8; 32]>, Vec<[u8; 32]>)> {
+    let mut known: Vec<[u8; 32]> = advertised
+        .iter()
+        .map(|(_, id)| *id)
+        .filter(|id| repo.manifest_path(id).exists())
+        .collect();
+    known.sort();
+    known.dedup();
+    let mut exclude = HashSet::new();
+    for id in &known {
+        exclude.extend(ancestors(repo, id)?);
+    }
+
+    let commits = commits_between(repo, local, &exclude)?;
+    if commits.is_empty() {
+        return Ok((commits, known));
+    }
+    chan.send(&ClientMessage::HaveCommits { ids: commits.clone() }).await?;
+    let ServerMessage::Present { bitmap } = chan.reply().await? else {
+        bail!("unexpected reply to HaveCommits");
+    };
+    let present = unpack_bits(&bitmap, commits.len())?;
+    let shared: Vec<[u8; 32]> = commits
+        .iter()
+        .zip(&present)
+        .filter(|(_, &has)| has)
+        .map(|(id, _)| *id)
+        .collect();
+    if shared.is_empty() {
+        return Ok((commits, known));
+    }
+    for id in &shared {
+        exclude.extend(ancestors(repo, id)?);
+    }
+    known.extend(shared);
+    Ok((commits_between(repo, local, &exclude)?, known))
+}
+
+/// Narrow a push's candidate objects down to those the server lacks. Large
+/// chunk sets are first checked against the server's bloom filter, so only
+/// its hits are listed in the offer.
+#[allow(clippy::type_complexity)]
+async fn negotiate_objects<S: AsyncRead + AsyncWrite>(
+    chan: &mut Channel<S>,
+    trees: Vec<[u8; 32]>,
+    chunks: Vec<[u8; 32]>,
+) -> Result<(Vec<[u8; 32]>, Vec<[u8; 32]>)> {
+    if trees.is_empty() && chunks.is_empty() {
+        return Ok((trees, chunks));
+    }
+    let (mut send, offered) = if chunks.len() > EXACT_OFFER_LIMIT {
+        chan.send(&ClientMessage::FilterRequest).await?;
+        let ServerMessage::Filter { data } = chan.reply().await? else {
+            bail!("unexpected reply to FilterRequest");
+        };
+        partition(&BloomFilter::from_bytes(&data)?, chunks)
+    } else {
+        (Vec::new(), chunks)
+    };
+
+    chan.send(&ClientMessage::Offer {
+        trees: trees.clone(),
+        chunks: offered.clone(),
+    })
+    .await?;
+    let ServerMessage::Have {
+        trees: have_trees,
+        chunks: have_chunks,
+    } = chan.reply().await?
+    else {
+        bail!("unexpected reply to Offer");
+    };
+    let have_trees = unpack_bits(&have_trees, trees.len())?;
+    let have_chunks = unpack_bits(&have_chunks, offered.len())?;
+    send.extend(missing(offered, &have_chunks));
+    Ok((missing(trees, &have_trees), send))
+}
+
+/// Download `tips` and the history behind them that is missing locally.
+/// Manifests are only recorded once every object they need has arrived;
+/// without `with_chunks` that is just their trees, as in a partial clone.
+pub async fn fetch<S: AsyncRead + AsyncWrite>(
+    repo: &Repository,
+    db: &MetadataDb,
+    chan: &mut Channel<S>,
+    tips: &[[u8; 32]],
+    with_chunks: bool,
+) -> Result<TransferStats> {
+    let mut have: Vec<[u8; 32]> = Vec::new();
+    have.extend(repo.read_head()?);
+    for (_, id) in repo.list_refs("refs")? {
+        have.push(id);
+    }
+
+    let mut stats = TransferStats::default();
+    for tip in tips {
+        if repo.manifest_path(tip).exists() {
+            continue;
+        }
+        have.sort();
+        have.dedup();
+        fetch_one(repo, db, chan, tip, &have, with_chunks, &mut stats).await?;
+        // Later tips usually share this one's history.
+        have.push(*tip);
+    }
+    Ok(stats)
+}
+
+async fn fetch_one<S: AsyncRead + AsyncWrite>(
+    repo: &Repository,
+    db: &MetadataDb,
+    chan: &mut Channel<S>,
+    tip: &[u8; 32],
+    have: &[[u8; 32]],
+    with_chunks: bool,
+    stats: &mut TransferStats,
+) -> Result<()> {
+    let (commit_id, have) = (*tip, have.to_vec());
+    if with_chunks {
+        chan.send(&ClientMessage::PullRequest { commit_id, have }).await?;
+    } else {
+        chan.send(&ClientMessage::PullManifests { commit_id, have }).await?;
+    }
+
+    let store = repo.chunk_store()?;
+    let mut manifests = Vec::new();
+    let (trees, chunks) = loop {
+        match chan.reply().await? {
+            ServerMessage::Manifest { commit_id, data } => manifests.push((commit_id, data)),
+            ServerMessage::FilterRequest => {
+                let data = BloomFilter::for_store(&store)?.to_bytes();
+                chan.send(&ClientMessage::Filter { data }).await?;
+            }
+            ServerMessage::Offer { trees, chunks } => break (trees, chunks),
+            _ => bail!("unexpected reply to PullRequest"),
+        }
+    };
+
+    let (trees, chunks) = have_bitmaps(repo, &store, &trees, &chunks);
+    chan.send(&ClientMessage::Have { trees, chunks }).await?;
+
+    loop {
+        match chan.reply().await? {
+            ServerMessage::TreeData { id, data } => {
+                accept_tree(repo, &id, &data)?;
+                stats.trees += 1;
+            }
+            ServerMessage::ChunkData { hash, data } => {
+                accept_chunk(&store, &hash, &data)?;
+                stats.chunks += 1;
+                stats.chunk_bytes += data.len() as u64;
+            }
+            ServerMessage::Done => break,
+            _ => bail!("unexpected message while receiving objects"),
+        }
+    }
+
+    // A server that stopped early must not leave refs to commits whose
+    // objects never arrived.
+    let mut verified_trees = HashSet::new();
+    for (id, bytes) in &manifests {
+        if repo.manifest_path(id).exists() {
+            continue;
+        }
+        check_complete(repo, &store, id, bytes, &mut verified_trees, with_chunks)?;
+        accept_manifest(repo, db, id, bytes)?;
+    }
+    stats.commits += manifests.len();
+    Ok(())
+}
+
+/// Download `hashes` into `store`, for a partial clone reading chunks it
+/// skipped.
+pub async fn fetch_chunks<S: AsyncRead + AsyncWrite>(
+    store: &ChunkStore,
+    chan: &mut Channel<S>,
+    hashes: &[[u8; 32]],
+) -> Result<TransferStats> {
+    chan.send(&ClientMessage::ChunkRequest {
+        hashes: hashes.to_vec(),
+    })
+    .await?;
+    let mut stats = TransferStats::default();
+    loop {
+        match chan.reply().await? {
+            ServerMessage::ChunkData { hash, data } => {
+                accept_chunk(store, &hash, &data)?;
+                stats.chunks += 1;
+                stats.chunk_bytes += data.len() as u64;
+            }
+            ServerMessage::Done => break,
+            _ => bail!("unexpected reply to ChunkRequest"),
+        }
+    }
+    if stats.chunks != hashes.len() {
+        bail!("remote sent {} of {} requested chunks", stats.chunks, hashes.len());
+    }
+    Ok(stats)
+}
@@ -0,0 +1,82 @@
+//! Chunk source of a partial clone: chunks come from its remote on first read.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::core::partial::{self, PartialConfig};
+use crate::core::remote::RemoteConfig;
+use crate::core::repository::Repository;
+use crate::store::cas::{ChunkSource, ChunkStore};
+use crate::transport::protocol::PARTIAL_CLONE_VERSION;
+use crate::transport::{client, quic};
+use crate::util::human::human_bytes;
+
+#[derive(Debug)]
+pub struct RemoteChunkSource {
+    repo: Repository,
+    config: PartialConfig,
+    remote: RemoteConfig,
+}
+
+impl RemoteChunkSource {
+    pub fn new(repo: &Repository, config: PartialConfig, remote: RemoteConfig) -> Self {
+        Self {
+            repo: repo.clone(),
+            config,
+            remote,
+        }
+    }
+
+    async fn download(&self, store: &ChunkStore, hashes: &[[u8; 32]]) -> Result<client::TransferStats> {
+        let mut session = quic::open_session(&self.repo, &self.remote).await?;
+        let refs = client::handshake(&mut session.channel).await?;
+        if refs.version < PARTIAL_CLONE_VERSION {
+            bail!(
+                "{} speaks protocol {}; fetching chunks on demand needs {PARTIAL_CLONE_VERSION}",
+                self.remote.url,
+                refs.version
+            );
+        }
+        let stats = client::fetch_chunks(store, &mut session.channel, hashes).await?;
+        session.channel.finish().await?;
+        Ok(stats)
+    }
+}
+
+impl ChunkSource for RemoteChunkSource {
+    fn fetch(&self, store: &ChunkStore, hashes: &[[u8; 32]]) -> Result<()> {
+        // Chunks are also read from inside a runtime (a push streaming them
+        // out), so the download gets a thread and runtime of its own.
+        let stats = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let rt = tokio::runtime::Runtime::new().context("create tokio runtime")?;
+                    rt.block_on(self.download(store, hashes))
+                })
+                .join()
+                .map_err(|_| anyhow!("chunk download thread panicked"))?
+        })
+        .with_context(|| format!("fetch {} chunks from '{}'", hashes.len(), self.config.remote))?;
+        tracing::debug!(
+            "fetched {} chunks ({}) from {}",
+            stats.chunks,
+            human_bytes(stats.chunk_bytes),
+            self.remote.url
+        );
+
+        if let Some(limit) = self.config.cache_size {
+            let keep: HashSet<[u8; 32]> = hashes.iter().copied().collect();
+            let evicted = partial::evict(&self.repo, store, &self.config, limit, &keep)?;
+            if evicted.chunks > 0 {
+                tracing::debug!(
+                    "evicted {} chunks ({}) to stay under {}",
+                    evicted.chunks,
+                    human_bytes(evicted.bytes),
+                    human_bytes(limit)
+                );
+            }
+        }
+        Ok(())
+    }
+}
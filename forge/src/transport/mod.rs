@@ -1,2 +1,7 @@
+pub mod client;
+pub mod lazy;
+pub mod negotiate;
+pub mod objects;
 pub mod protocol;
 pub mod quic;
+pub mod server;
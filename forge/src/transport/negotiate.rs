@@ -0,0 +1,124 @@
+//! Have/want negotiation helpers shared by both ends.
+//!
+//! Object lists are answered with one bit per entry instead of echoing ids.
+//! Above [`EXACT_OFFER_LIMIT`] chunks, the receiver first sends a bloom filter
+//! of its store: chunks the filter rules out are sent without being offered,
+//! and only the filter's hits are confirmed one by one.
+
+use anyhow::{bail, Result};
+
+use crate::store::cas::ChunkStore;
+
+/// Largest chunk offer sent as a plain list before asking for a filter.
+pub const EXACT_OFFER_LIMIT: usize = 4096;
+
+/// Target false-positive rate of [`BloomFilter::for_store`].
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Set bit `i` for every `true` entry.
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut out = vec![0u8; bits.len().div_ceil(8)];
+    for (i, _) in bits.iter().enumerate().filter(|(_, &bit)| bit) {
+        out[i / 8] |= 1 << (i % 8);
+    }
+    out
+}
+
+/// Inverse of [`pack_bits`] for a list of `len` entries.
+pub fn unpack_bits(bytes: &[u8], len: usize) -> Result<Vec<bool>> {
+    if bytes.len() != len.div_ceil(8) {
+        bail!("bitmap covers {} entries, expected {len}", bytes.len() * 8);
+    }
+    Ok((0..len).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect())
+}
+
+/// Bloom filter over object ids. Ids are blake3 hashes, so the probe
+/// positions are taken from the id itself rather than rehashing it.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Empty filter sized for `count` ids at `fp_rate` false positives.
+    pub fn with_capacity(count: usize, fp_rate: f64) -> Self {
+        let count = count.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-count * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hashes = ((bits as f64 / count) * ln2).round().clamp(1.0, 16.0) as u32;
+        Self {
+            bits: vec![0; bits.div_ceil(8)],
+            hashes,
+        }
+    }
+
+    /// Every chunk in `store`, loose or packed.
+    pub fn for_store(store: &ChunkStore) -> Result<Self> {
+        let loose = store.list_loose()?;
+        let packs = store.packs()?;
+        let packed: usize = packs.iter().map(|pack| pack.len()).sum();
+        let mut filter = Self::with_capacity(loose.len() + packed, FALSE_POSITIVE_RATE);
+        for hash in &loose {
+            filter.insert(hash.as_bytes());
+        }
+        for pack in packs.iter() {
+            for entry in pack.entries() {
+                filter.insert(&entry.hash);
+            }
+        }
+        Ok(filter)
+    }
+
+    fn probes(&self, id: &[u8; 32]) -> impl Iterator<Item = usize> {
+        let h1 = u64::from_le_bytes(id[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(id[8..16].try_into().unwrap()) | 1;
+        let size = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+
+    pub fn insert(&mut self, id: &[u8; 32]) {
+        for bit in self.probes(id) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// `false` means `id` is certainly absent.
+    pub fn may_contain(&self, id: &[u8; 32]) -> bool {
+        self.probes(id).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Wire form: probe count as one byte, then the bit array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.bits.len() + 1);
+        out.push(self.hashes as u8);
+        out.extend_from_slice(&self.bits);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&hashes, bits)) if (1..=16).contains(&hashes) && !bits.is_empty() => Ok(Self {
+                bits: bits.to_vec(),
+                hashes: hashes as u32,
+            }),
+            _ => bail!("malformed bloom filter"),
+        }
+    }
+}
+
+/// Split `chunks` by `filter`: those it rules out, which are certainly
+/// missing on the other side, and those that still need asking about.
+#[allow(clippy::type_complexity)]
+pub fn partition(filter: &BloomFilter, chunks: Vec<[u8; 32]>) -> (Vec<[u8; 32]>, Vec<[u8; 32]>) {
+    chunks.into_iter().partition(|hash| !filter.may_contain(hash))
+}
+
+/// Entries of `ids` whose bit in `have` is clear.
+pub fn missing(ids: Vec<[u8; 32]>, have: &[bool]) -> Vec<[u8; 32]> {
+    ids.into_iter()
+        .zip(have)
+        .filter(|(_, &has)| !has)
+        .map(|(id, _)| id)
+        .collect()
+}
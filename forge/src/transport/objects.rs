@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+
+use crate::core::manifest::{deserialize_tree, verified_manifest_id, TreeNode};
+use crate::core::repository::Repository;
+use crate::core::tree;
+use crate::db::metadata::MetadataDb;
+use crate::store::cas::ChunkStore;
+use crate::store::compression::{self, frame_dict_id};
+use crate::transport::negotiate::pack_bits;
+
+/// Commits reachable from `tip` but not in `exclude`, parents before children.
+pub fn commits_between(
+    repo: &Repository,
+    tip: &[u8; 32],
+    exclude: &HashSet<[u8; 32]>,
+) -> Result<Vec<[u8; 32]>> {
+    let mut ordered = Vec::new();
+    let mut visited = HashSet::new();
+    // (commit, parents already pushed) — a manual post-order walk so deep
+    // histories do not recurse.
+    let mut stack = vec![(*tip, false)];
+    while let Some((id, expanded)) = stack.pop() {
+        if expanded {
+            ordered.push(id);
+            continue;
+        }
+        if exclude.contains(&id) || !visited.insert(id) {
+            continue;
+        }
+        stack.push((id, true));
+        for parent in repo.read_commit(&id)?.parents.iter().rev() {
+            stack.push((*parent, false));
+        }
+    }
+    Ok(ordered)
+}
+
+/// Trees and chunks referenced by `commits`, skipping everything reachable
+/// from the trees of the `known` commits the other side already has.
+#[allow(clippy::type_complexity)]
+pub fn objects_for(
+    repo: &Repository,
+    commits: &[[u8; 32]],
+    known: &[[u8; 32]],
+) -> Result<(Vec<[u8; 32]>, Vec<[u8; 32]>)> {
+    let mut seen = HashSet::new();
+    for id in known {
+        let root = repo.read_commit(id)?.tree;
+        tree::walk(repo, &root, &mut seen, &mut |_, _| {})?;
+    }
+
+    let mut trees = Vec::new();
+    let mut chunks = Vec::new();
+    let mut seen_chunks = HashSet::new();
+    for id in commits {
+        let root = repo.read_commit(id)?.tree;
+        tree::walk(repo, &root, &mut seen, &mut |tree_id, tree| {
+            trees.push(*tree_id);
+            for entry in &tree.entries {
+                if let TreeNode::File(file) = &entry.node {
+                    for chunk in &file.chunks {
+                        if seen_chunks.insert(chunk.hash) {
+                            chunks.push(chunk.hash);
+                        }
+                    }
+                }
+            }
+        })?;
+    }
+    Ok((trees, chunks))
+}
+
+/// Answer an offer: which of `trees` and `chunks` this side already has, as
+/// packed bitmaps.
+pub fn have_bitmaps(
+    repo: &Repository,
+    store: &ChunkStore,
+    trees: &[[u8; 32]],
+    chunks: &[[u8; 32]],
+) -> (Vec<u8>, Vec<u8>) {
+    let have_trees: Vec<bool> = trees.iter().map(|id| repo.tree_object_path(id).exists()).collect();
+    let have_chunks: Vec<bool> = chunks
+        .iter()
+        .map(|hash| store.contains(&blake3::Hash::from(*hash)))
+        .collect();
+    (pack_bits(&have_trees), pack_bits(&have_chunks))
+}
+
+/// The stored frame for `hash`, recompressed without a dictionary when it
+/// used one, since the receiving repository may not have it.
+pub fn wire_chunk(store: &ChunkStore, hash: &[u8; 32], level: i32) -> Result<Vec<u8>> {
+    let hash = blake3::Hash::from(*hash);
+    let stored = store.read(&hash)?;
+    if frame_dict_id(&stored).is_none() {
+        return Ok(stored);
+    }
+    compression::compress(&store.dictionaries().decompress(&stored)?, level)
+}
+
+/// Verify a received chunk frame against its hash and store it as is.
+pub fn accept_chunk(store: &ChunkStore, hash: &[u8; 32], frame: &[u8]) -> Result<()> {
+    let hash = blake3::Hash::from(*hash);
+    let raw = compression::decompress(frame).with_context(|| format!("decode chunk {}", hash.to_hex()))?;
+    if blake3::hash(&raw) != hash {
+        bail!("chunk {} does not match its content", hash.to_hex());
+    }
+    store.store(&hash, frame)?;
+    Ok(())
+}
+
+/// Verify a received tree object against its id and store it.
+pub fn accept_tree(repo: &Repository, id: &[u8; 32], bytes: &[u8]) -> Result<()> {
+    let tree = deserialize_tree(bytes).with_context(|| format!("decode tree {}", hex::encode(id)))?;
+    for entry in &tree.entries {
+        tree::validate_entry_name(&entry.name).with_context(|| format!("tree {}", hex::encode(id)))?;
+    }
+    if repo.write_tree(&tree)? != *id {
+        bail!("tree {} does not match its content", hex::encode(id));
+    }
+    Ok(())
+}
+
+/// Trees already checked, with the directory each was found under: file
+/// entries carry their full path, so a tree is only valid in one place.
+pub type VerifiedTrees = HashSet<([u8; 32], String)>;
+
+/// Check that a received commit can be recorded: its parents are known,
+/// every tree under it is present and lists each file under the path its
+/// entry names lead to, and, with `with_chunks`, every chunk it lists is
+/// present. `verified_trees` carries trees already checked across commits.
+pub fn check_complete(
+    repo: &Repository,
+    store: &ChunkStore,
+    id: &[u8; 32],
+    bytes: &[u8],
+    verified_trees: &mut VerifiedTrees,
+    with_chunks: bool,
+) -> Result<()> {
+    let commit_id = hex::encode(id);
+    let commit = repo
+        .parse_manifest(bytes)
+        .with_context(|| format!("decode manifest {commit_id}"))?;
+    for parent in &commit.parents {
+        if !repo.manifest_path(parent).exists() {
+            bail!("commit {commit_id} has unknown parent {}", hex::encode(parent));
+        }
+    }
+    let store = with_chunks.then_some(store);
+    check_tree(repo, store, &commit.tree, "", verified_trees).with_context(|| format!("commit {commit_id}"))
+}
+
+fn check_tree(
+    repo: &Repository,
+    store: Option<&ChunkStore>,
+    id: &[u8; 32],
+    prefix: &str,
+    verified_trees: &mut VerifiedTrees,
+) -> Result<()> {
+    if !verified_trees.insert((*id, prefix.to_string())) {
+        return Ok(());
+    }
+    let tree = repo
+        .read_tree(id)
+        .with_context(|| format!("refers to a missing tree {}", hex::encode(id)))?;
+    for entry in &tree.entries {
+        let path = format!("{prefix}{}", entry.name);
+        match &entry.node {
+            TreeNode::Dir(sub) => check_tree(repo, store, sub, &format!("{path}/"), verified_trees)?,
+            TreeNode::File(file) => {
+                if file.path != path {
+                    bail!("tree {} lists {} under {path}", hex::encode(id), file.path);
+                }
+                let Some(store) = store else {
+                    continue;
+                };
+                for chunk in &file.chunks {
+                    if !store.contains(&blake3::Hash::from(chunk.hash)) {
+                        bail!("{path} needs missing chunk {}", hex::encode(chunk.hash));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Verify a received manifest against its id and record it. Callers check
+/// that the objects it refers to are present first, with `check_complete`.
+pub fn accept_manifest(repo: &Repository, db: &MetadataDb, id: &[u8; 32], bytes: &[u8]) -> Result<()> {
+    let id_hex = hex::encode(id);
+    if verified_manifest_id(bytes) != Some(*id) {
+        bail!("manifest {id_hex} does not match its content");
+    }
+    let path = repo.manifest_path(id);
+    std::fs::write(&path, bytes).with_context(|| format!("write manifest {}", path.display()))?;
+    db.store_commit(&id_hex, bytes)?;
+    Ok(())
+}
@@ -1,36 +1,268 @@
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::{bail, Context, Result};
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter, ReadHalf,
+    WriteHalf,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Wire protocol spoken by this build. Version 1 was JSON lines with
+/// base64 payloads; it is only recognised well enough to report the mismatch.
+/// Version 3 added `PullManifests` and `ChunkRequest` for partial clones.
+pub const PROTOCOL_VERSION: u32 = 3;
+/// Oldest version this build still speaks.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+/// Oldest version that serves partial clones.
+pub const PARTIAL_CLONE_VERSION: u32 = 3;
+
+/// Sent by the client before its first frame. It ends in a newline so a
+/// version 1 server, which reads lines, rejects it with a JSON error line
+/// instead of waiting for more input.
+pub const PREAMBLE: &[u8] = b"forge-wire\n";
+
+/// Upper bound on one frame, well above the largest chunk the chunkers emit.
+pub const MAX_FRAME: usize = 128 * 1024 * 1024;
+
+/// Frame kinds. Error frames carry plain UTF-8 rather than an archived
+/// message, so every protocol version can read them whatever the peer's
+/// message layout.
+const FRAME_MESSAGE: u8 = 0;
+const FRAME_ERROR: u8 = 1;
+
+/// Ids and hashes travel as raw 32-byte arrays, payloads as raw bytes.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 pub enum ClientMessage {
-    Hello { client: String, version: String },
-    PushManifest { commit_id: String },
-    ChunkData { hash: String, data_b64: String },
-    PullRequest { commit_id: String },
-    Ack { id: String },
+    /// Protocol versions the client can speak, inclusive.
+    Hello { client: String, min_version: u32, max_version: u32 },
+    /// Ask for every branch and tag on the server.
+    ListRefs,
+    /// Commits a push would send; the server answers with `Present`.
+    HaveCommits { ids: Vec<[u8; 32]> },
+    /// Ask for a bloom filter of the server's chunks; answered with `Filter`.
+    FilterRequest,
+    /// Trees and chunks a push may need to send; the server answers with `Have`.
+    Offer { trees: Vec<[u8; 32]>, chunks: Vec<[u8; 32]> },
+    TreeData { id: [u8; 32], data: Vec<u8> },
+    /// Compressed chunk frame, never dependent on a local dictionary.
+    ChunkData { hash: [u8; 32], data: Vec<u8> },
+    /// Sent parents first, after the objects the commit refers to.
+    PushManifest { commit_id: [u8; 32], data: Vec<u8> },
+    /// Compare-and-swap a ref; `old` is the value the client last saw.
+    UpdateRef { name: String, old: Option<[u8; 32]>, new: [u8; 32] },
+    /// Fetch `commit_id` and its history, excluding what `have` already covers.
+    PullRequest { commit_id: [u8; 32], have: Vec<[u8; 32]> },
+    /// Bloom filter of the client's chunks, sent when a pull asks for it.
+    Filter { data: Vec<u8> },
+    /// Bitmaps over a server `Offer`: bit `i` set when the client has entry `i`.
+    Have { trees: Vec<u8>, chunks: Vec<u8> },
+    /// `PullRequest` without chunks: the server's `Offer` lists only trees.
+    PullManifests { commit_id: [u8; 32], have: Vec<[u8; 32]> },
+    /// Chunks a partial clone needs now; answered with `ChunkData` for each,
+    /// then `Done`.
+    ChunkRequest { hashes: Vec<[u8; 32]> },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 pub enum ServerMessage {
+    /// Answer to `Hello` with the version both sides will speak.
+    Welcome { server: String, version: u32 },
     Ok { message: String },
-    NeedChunks { hashes: Vec<String> },
-    Manifest { commit_id: String, data_b64: String },
-    ChunkData { hash: String, data_b64: String },
-    AckCommit { commit_id: String },
+    /// Full ref names (`refs/heads/main`) with the commit they point at, and
+    /// the branch the server's HEAD names, which clones check out.
+    Refs { refs: Vec<(String, [u8; 32])>, head: Option<String> },
+    /// Bit `i` set when the server has commit `i` of `HaveCommits`.
+    Present { bitmap: Vec<u8> },
+    Filter { data: Vec<u8> },
+    /// Bitmaps over a client `Offer`, as for `ClientMessage::Have`.
+    Have { trees: Vec<u8>, chunks: Vec<u8> },
+    /// Ask the client for a bloom filter of its chunks during a pull.
+    FilterRequest,
+    Manifest { commit_id: [u8; 32], data: Vec<u8> },
+    Offer { trees: Vec<[u8; 32]>, chunks: Vec<[u8; 32]> },
+    TreeData { id: [u8; 32], data: Vec<u8> },
+    ChunkData { hash: [u8; 32], data: Vec<u8> },
+    AckCommit { commit_id: [u8; 32] },
+    /// End of the objects sent for a `PullRequest` or `ChunkRequest`.
+    Done,
 }
 
-pub fn serialize_client_message(msg: &ClientMessage) -> Result<Vec<u8>> {
-    serde_json::to_vec(msg).context("serialize client message")
+pub fn serialize_client_message(msg: &ClientMessage) -> Result<AlignedVec> {
+    rkyv::to_bytes::<rkyv::rancor::Error>(msg).context("serialize client message")
 }
 
 pub fn deserialize_client_message(bytes: &[u8]) -> Result<ClientMessage> {
-    serde_json::from_slice(bytes).context("deserialize client message")
+    rkyv::from_bytes::<ClientMessage, rkyv::rancor::Error>(bytes).context("deserialize client message")
 }
 
-pub fn serialize_server_message(msg: &ServerMessage) -> Result<Vec<u8>> {
-    serde_json::to_vec(msg).context("serialize server message")
+pub fn serialize_server_message(msg: &ServerMessage) -> Result<AlignedVec> {
+    rkyv::to_bytes::<rkyv::rancor::Error>(msg).context("serialize server message")
 }
 
 pub fn deserialize_server_message(bytes: &[u8]) -> Result<ServerMessage> {
-    serde_json::from_slice(bytes).context("deserialize server message")
+    rkyv::from_bytes::<ServerMessage, rkyv::rancor::Error>(bytes).context("deserialize server message")
+}
+
+/// A message type that can travel in a frame.
+pub trait Message: Sized {
+    fn encode(&self) -> Result<AlignedVec>;
+    fn decode(bytes: &[u8]) -> Result<Self>;
+}
+
+impl Message for ClientMessage {
+    fn encode(&self) -> Result<AlignedVec> {
+        serialize_client_message(self)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        deserialize_client_message(bytes)
+    }
+}
+
+impl Message for ServerMessage {
+    fn encode(&self) -> Result<AlignedVec> {
+        serialize_server_message(self)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        deserialize_server_message(bytes)
+    }
+}
+
+/// Length-prefixed frames over a bidirectional stream: a kind byte, the body
+/// length as a big-endian `u32`, then the body. Writes are buffered and
+/// flushed whenever the peer's answer is awaited, so long runs of chunk
+/// frames stream out under the transport's flow control without either side
+/// holding the whole batch.
+pub struct Channel<S> {
+    reader: BufReader<ReadHalf<S>>,
+    writer: BufWriter<WriteHalf<S>>,
+    frame: AlignedVec,
+}
+
+impl<S: AsyncRead + AsyncWrite> Channel<S> {
+    fn new(stream: S) -> Self {
+        let (read, write) = tokio::io::split(stream);
+        Self {
+            reader: BufReader::new(read),
+            writer: BufWriter::new(write),
+            frame: AlignedVec::new(),
+        }
+    }
+
+    /// Client side: the preamble goes out with the first frame.
+    pub async fn open(stream: S) -> Result<Self> {
+        let mut chan = Self::new(stream);
+        chan.writer.write_all(PREAMBLE).await.context("send preamble")?;
+        Ok(chan)
+    }
+
+    /// Server side: check the client's preamble. A version 1 client is told
+    /// to upgrade in the JSON it can still read.
+    pub async fn accept(stream: S) -> Result<Self> {
+        let mut chan = Self::new(stream);
+        if chan.legacy_peer().await? {
+            let line = serde_json::json!({
+                "Error": {
+                    "message": format!(
+                        "this server speaks forge protocol {PROTOCOL_VERSION}; upgrade forge to connect"
+                    )
+                }
+            });
+            chan.writer.write_all(format!("{line}\n").as_bytes()).await.context("send error")?;
+            let _ = chan.finish().await;
+            bail!("client speaks protocol 1");
+        }
+        let mut preamble = vec![0u8; PREAMBLE.len()];
+        chan.reader.read_exact(&mut preamble).await.context("receive preamble")?;
+        if preamble != PREAMBLE {
+            bail!("peer is not a forge client");
+        }
+        Ok(chan)
+    }
+
+    /// Whether the peer's next bytes are a JSON line from protocol 1. No
+    /// frame kind can start with `{`.
+    async fn legacy_peer(&mut self) -> Result<bool> {
+        let buf = self.reader.fill_buf().await.context("receive frame")?;
+        Ok(buf.first() == Some(&b'{'))
+    }
+
+    async fn write_frame(&mut self, kind: u8, body: &[u8]) -> Result<()> {
+        if body.len() > MAX_FRAME {
+            bail!("frame of {} bytes exceeds the {MAX_FRAME}-byte limit", body.len());
+        }
+        let mut header = [0u8; 5];
+        header[0] = kind;
+        header[1..].copy_from_slice(&(body.len() as u32).to_be_bytes());
+        self.writer.write_all(&header).await.context("send frame")?;
+        self.writer.write_all(body).await.context("send frame")
+    }
+
+    pub async fn send<M: Message>(&mut self, msg: &M) -> Result<()> {
+        let body = msg.encode()?;
+        self.write_frame(FRAME_MESSAGE, &body).await
+    }
+
+    /// Report a failure to the peer; any protocol version can decode it.
+    pub async fn send_error(&mut self, message: &str) -> Result<()> {
+        self.write_frame(FRAME_ERROR, message.as_bytes()).await
+    }
+
+    /// Next message, or `None` once the peer has closed its side. Error
+    /// frames, and the JSON error lines of protocol 1 peers, become errors.
+    pub async fn recv<M: Message>(&mut self) -> Result<Option<M>> {
+        self.writer.flush().await.context("flush frames")?;
+        if self.reader.fill_buf().await.context("receive frame")?.is_empty() {
+            return Ok(None);
+        }
+        if self.legacy_peer().await? {
+            let mut line = Vec::new();
+            self.reader.read_until(b'\n', &mut line).await.context("receive frame")?;
+            let value: serde_json::Value = serde_json::from_slice(&line).unwrap_or_default();
+            match value["Error"]["message"].as_str() {
+                Some(message) => bail!("remote speaks an older forge protocol: {message}"),
+                None => bail!("remote speaks an older forge protocol; upgrade it to connect"),
+            }
+        }
+
+        let mut header = [0u8; 5];
+        self.reader
+            .read_exact(&mut header)
+            .await
+            .context("connection closed mid-frame")?;
+        let len = u32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+        if len > MAX_FRAME {
+            bail!("peer sent a {len}-byte frame, over the {MAX_FRAME}-byte limit");
+        }
+        self.frame.clear();
+        self.frame.resize(len, 0);
+        self.reader
+            .read_exact(&mut self.frame)
+            .await
+            .context("connection closed mid-frame")?;
+
+        match header[0] {
+            FRAME_MESSAGE => M::decode(&self.frame).map(Some),
+            FRAME_ERROR => bail!("remote: {}", String::from_utf8_lossy(&self.frame)),
+            kind => bail!("unknown frame kind {kind}"),
+        }
+    }
+
+    pub async fn flush(&mut self) -> Result<()> {
+        self.writer.flush().await.context("flush frames")
+    }
+
+    /// Flush and close the sending side.
+    pub async fn finish(&mut self) -> Result<()> {
+        self.writer.flush().await.context("flush frames")?;
+        self.writer.shutdown().await.context("close stream")
+    }
+
+    /// Receive the server's next message, treating a disconnect as an error.
+    pub async fn reply(&mut self) -> Result<ServerMessage> {
+        match self.recv::<ServerMessage>().await? {
+            Some(msg) => Ok(msg),
+            None => bail!("remote closed the connection"),
+        }
+    }
 }
@@ -1,9 +1,218 @@
-use anyhow::{bail, Result};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-pub async fn start_server(_listen_addr: &str) -> Result<()> {
-    bail!("QUIC server is not implemented in v0.1; network transport arrives in v0.2")
+use anyhow::{anyhow, bail, Context, Result};
+use s2n_quic::client::Connect;
+use s2n_quic::provider::tls;
+use s2n_quic::stream::BidirectionalStream;
+use s2n_quic::{Client, Connection};
+
+use crate::core::remote::RemoteConfig;
+use crate::core::repository::Repository;
+use crate::transport::protocol::Channel;
+use crate::transport::server::{self, ServerState};
+
+pub const DEFAULT_PORT: u16 = 4433;
+
+pub fn is_quic_url(url: &str) -> bool {
+    url.starts_with("quic://")
+}
+
+/// Split `quic://host[:port][/]` into host and port.
+pub fn parse_url(url: &str) -> Result<(String, u16)> {
+    let rest = url
+        .strip_prefix("quic://")
+        .ok_or_else(|| anyhow!("'{url}' is not a quic:// URL"))?
+        .trim_end_matches('/');
+    if rest.is_empty() {
+        bail!("'{url}' has no host");
+    }
+    // Bracketed IPv6 literals carry colons of their own.
+    let (host, port) = if let Some(v6) = rest.strip_prefix('[') {
+        let (host, tail) = v6
+            .split_once(']')
+            .ok_or_else(|| anyhow!("unterminated IPv6 address in '{url}'"))?;
+        (host, tail.strip_prefix(':'))
+    } else {
+        match rest.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (rest, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse().with_context(|| format!("invalid port in '{url}'"))?,
+        None => DEFAULT_PORT,
+    };
+    Ok((host.to_string(), port))
+}
+
+/// Extra certificate to trust for `remote`: `FORGE_QUIC_CA`, else the
+/// remote's `ca`, else the global `quic_ca` (relative paths are taken from the
+/// repository root).
+pub fn trusted_ca(repo: &Repository, remote: &RemoteConfig) -> Result<Option<PathBuf>> {
+    if let Some(path) = std::env::var_os("FORGE_QUIC_CA") {
+        return Ok(Some(PathBuf::from(path)));
+    }
+    let ca = match &remote.ca {
+        Some(ca) => Some(ca.clone()),
+        None => repo.read_config()?.quic_ca,
+    };
+    Ok(ca.map(|path| repo.root.join(path)))
+}
+
+/// Certificate and key `forge serve` uses when none are given: generated once,
+/// self-signed, and kept under `tls/` in the served repository.
+pub fn ensure_certificate(repo: &Repository) -> Result<(PathBuf, PathBuf)> {
+    let dir = repo.forge_dir.join("tls");
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()];
+    if let Ok(host) = fs::read_to_string("/etc/hostname") {
+        let host = host.trim();
+        if !host.is_empty() {
+            names.push(host.to_string());
+        }
+    }
+    let cert = rcgen::generate_simple_self_signed(names).context("generate certificate")?;
+
+    fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+    fs::write(&cert_path, cert.cert.pem()).with_context(|| format!("write {}", cert_path.display()))?;
+    fs::write(&key_path, cert.key_pair.serialize_pem())
+        .with_context(|| format!("write {}", key_path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("restrict {}", key_path.display()))?;
+    }
+    Ok((cert_path, key_path))
+}
+
+/// A bound QUIC endpoint serving one bare repository.
+pub struct Server {
+    quic: s2n_quic::Server,
+    state: Arc<ServerState>,
+}
+
+impl Server {
+    /// Bind `listen_addr`. Must be called from within a tokio runtime.
+    pub fn bind(repo: Repository, listen_addr: &str, cert: &Path, key: &Path) -> Result<Self> {
+        let cert_pem = fs::read_to_string(cert).with_context(|| format!("read {}", cert.display()))?;
+        let key_pem = fs::read_to_string(key).with_context(|| format!("read {}", key.display()))?;
+        let addr: SocketAddr = listen_addr
+            .parse()
+            .with_context(|| format!("invalid listen address '{listen_addr}'"))?;
+
+        let quic = s2n_quic::Server::builder()
+            .with_tls((cert_pem.as_str(), key_pem.as_str()))
+            .map_err(|e| anyhow!("configure TLS: {e}"))?
+            .with_io(addr)
+            .map_err(|e| anyhow!("bind {addr}: {e}"))?
+            .start()
+            .map_err(|e| anyhow!("start QUIC server: {e}"))?;
+        Ok(Self {
+            quic,
+            state: Arc::new(ServerState::open(repo)?),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.quic.local_addr().context("query listen address")
+    }
+
+    /// Accept connections until the endpoint shuts down. Each bidirectional
+    /// stream carries one protocol session.
+    pub async fn run(mut self) -> Result<()> {
+        while let Some(mut connection) = self.quic.accept().await {
+            let state = Arc::clone(&self.state);
+            tokio::spawn(async move {
+                let peer = connection.remote_addr().ok();
+                while let Ok(Some(stream)) = connection.accept_bidirectional_stream().await {
+                    let state = Arc::clone(&state);
+                    tokio::spawn(async move {
+                        if let Err(err) = server::handle(&state, stream).await {
+                            tracing::warn!("session from {peer:?} failed: {err:#}");
+                        }
+                    });
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+pub async fn start_server(repo: Repository, listen_addr: &str, cert: &Path, key: &Path) -> Result<()> {
+    let root = repo.root.clone();
+    let server = Server::bind(repo, listen_addr, cert, key)?;
+    println!(
+        "Serving {} on quic://{} (certificate: {})",
+        root.display(),
+        server.local_addr()?,
+        cert.display()
+    );
+    server.run().await
+}
+
+/// An open session with a remote. The endpoint and connection are held so the
+/// stream stays usable for the session's lifetime.
+pub struct Session {
+    pub channel: Channel<BidirectionalStream>,
+    _connection: Connection,
+    _client: Client,
+}
+
+/// Connect to `remote` with the repository's trust settings.
+pub async fn open_session(repo: &Repository, remote: &RemoteConfig) -> Result<Session> {
+    connect_client(&remote.url, trusted_ca(repo, remote)?.as_deref()).await
 }
 
-pub async fn connect_client(_remote_addr: &str) -> Result<()> {
-    bail!("QUIC client is not implemented in v0.1; network transport arrives in v0.2")
+/// Connect to `remote_addr` (a `quic://` URL), trusting `ca` in addition to
+/// the system roots when given.
+pub async fn connect_client(remote_addr: &str, ca: Option<&Path>) -> Result<Session> {
+    let (host, port) = parse_url(remote_addr)?;
+    let addr = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .with_context(|| format!("resolve {host}"))?
+        .next()
+        .ok_or_else(|| anyhow!("{host} did not resolve to an address"))?;
+
+    let mut tls = tls::default::Client::builder();
+    if let Some(ca) = ca {
+        let pem = fs::read_to_string(ca).with_context(|| format!("read {}", ca.display()))?;
+        tls = tls
+            .with_certificate(pem.as_str())
+            .map_err(|e| anyhow!("load {}: {e}", ca.display()))?;
+    }
+    let tls = tls.build().map_err(|e| anyhow!("configure TLS: {e}"))?;
+
+    let bind = if addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let client = Client::builder()
+        .with_tls(tls)
+        .map_err(|e| anyhow!("configure TLS: {e}"))?
+        .with_io(bind)
+        .map_err(|e| anyhow!("bind {bind}: {e}"))?
+        .start()
+        .map_err(|e| anyhow!("start QUIC client: {e}"))?;
+
+    let mut connection = client
+        .connect(Connect::new(addr).with_server_name(host.as_str()))
+        .await
+        .map_err(|e| anyhow!("connect to {remote_addr}: {e}"))?;
+    connection.keep_alive(true).context("enable keep-alive")?;
+    let stream = connection
+        .open_bidirectional_stream()
+        .await
+        .map_err(|e| anyhow!("open stream to {remote_addr}: {e}"))?;
+
+    Ok(Session {
+        channel: Channel::open(stream).await?,
+        _connection: connection,
+        _client: client,
+    })
 }
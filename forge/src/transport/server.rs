@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+use std::fs;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::core::graph::{ancestors, is_ancestor};
+use crate::core::repository::{validate_ref_name, Repository};
+use crate::db::metadata::MetadataDb;
+use crate::store::cas::ChunkStore;
+use crate::transport::negotiate::{
+    missing, pack_bits, partition, unpack_bits, BloomFilter, EXACT_OFFER_LIMIT,
+};
+use crate::transport::objects::{
+    accept_chunk, accept_manifest, accept_tree, check_complete, commits_between, have_bitmaps, objects_for,
+    wire_chunk, VerifiedTrees,
+};
+use crate::transport::protocol::{
+    Channel, ClientMessage, ServerMessage, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::util::human::short_hex;
+
+/// Repository handles shared by every session of one server.
+pub struct ServerState {
+    pub repo: Repository,
+    db: MetadataDb,
+    store: ChunkStore,
+    compression_level: i32,
+    /// Serializes ref updates so compare-and-swap checks hold.
+    refs: Mutex<()>,
+}
+
+impl ServerState {
+    pub fn open(repo: Repository) -> Result<Self> {
+        let db = MetadataDb::open(&repo.metadata_db_path())?;
+        let store = repo.chunk_store()?;
+        let compression_level = repo.read_config()?.compression_level;
+        Ok(Self {
+            repo,
+            db,
+            store,
+            compression_level,
+            refs: Mutex::new(()),
+        })
+    }
+}
+
+/// Serve one session. Any failure is reported to the client as an error
+/// frame before the stream is closed.
+pub async fn handle<S: AsyncRead + AsyncWrite>(state: &ServerState, stream: S) -> Result<()> {
+    let mut chan = Channel::accept(stream).await?;
+    let mut verified_trees = HashSet::new();
+    let result = async {
+        while let Some(msg) = chan.recv::<ClientMessage>().await? {
+            dispatch(state, &mut chan, msg, &mut verified_trees).await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Err(err) = &result {
+        let _ = chan.send_error(&format!("{err:#}")).await;
+    }
+    let _ = chan.finish().await;
+    result
+}
+
+async fn dispatch<S: AsyncRead + AsyncWrite>(
+    state: &ServerState,
+    chan: &mut Channel<S>,
+    msg: ClientMessage,
+    verified_trees: &mut VerifiedTrees,
+) -> Result<()> {
+    let repo = &state.repo;
+    match msg {
+        ClientMessage::Hello {
+            client,
+            min_version,
+            max_version,
+        } => {
+            tracing::debug!("hello from {client} (protocol {min_version}..={max_version})");
+            let version = max_version.min(PROTOCOL_VERSION);
+            if version < min_version.max(MIN_PROTOCOL_VERSION) {
+                bail!(
+                    "no common protocol version: client speaks {min_version}..={max_version}, \
+                     server speaks {MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION}"
+                );
+            }
+            chan.send(&ServerMessage::Welcome {
+                server: format!("forge {}", env!("CARGO_PKG_VERSION")),
+                version,
+            })
+            .await
+        }
+        ClientMessage::ListRefs => {
+            let mut refs = Vec::new();
+            for prefix in ["refs/heads", "refs/tags"] {
+                for (name, id) in repo.list_refs(prefix)? {
+                    refs.push((format!("{prefix}/{name}"), id));
+                }
+            }
+            let head = repo.head_ref()?;
+            chan.send(&ServerMessage::Refs { refs, head }).await
+        }
+        ClientMessage::HaveCommits { ids } => {
+            let present: Vec<bool> = ids.iter().map(|id| repo.manifest_path(id).exists()).collect();
+            chan.send(&ServerMessage::Present {
+                bitmap: pack_bits(&present),
+            })
+            .await
+        }
+        ClientMessage::FilterRequest => {
+            let data = BloomFilter::for_store(&state.store)?.to_bytes();
+            chan.send(&ServerMessage::Filter { data }).await
+        }
+        ClientMessage::Offer { trees, chunks } => {
+            let (trees, chunks) = have_bitmaps(repo, &state.store, &trees, &chunks);
+            chan.send(&ServerMessage::Have { trees, chunks }).await
+        }
+        ClientMessage::TreeData { id, data } => accept_tree(repo, &id, &data),
+        ClientMessage::ChunkData { hash, data } => accept_chunk(&state.store, &hash, &data),
+        ClientMessage::PushManifest { commit_id, data } => {
+            receive_manifest(state, &commit_id, &data, verified_trees)?;
+            chan.send(&ServerMessage::AckCommit { commit_id }).await
+        }
+        ClientMessage::UpdateRef { name, old, new } => {
+            update_ref(state, &name, old.as_ref(), &new)?;
+            chan.send(&ServerMessage::Ok {
+                message: format!("{name} -> {}", short_hex(&new)),
+            })
+            .await
+        }
+        ClientMessage::PullRequest { commit_id, have } => {
+            serve_pull(state, chan, &commit_id, &have, true).await
+        }
+        ClientMessage::PullManifests { commit_id, have } => {
+            serve_pull(state, chan, &commit_id, &have, false).await
+        }
+        ClientMessage::ChunkRequest { hashes } => serve_chunks(state, chan, &hashes).await,
+        ClientMessage::Filter { .. } | ClientMessage::Have { .. } => bail!("unexpected reply outside of a pull"),
+    }
+}
+
+/// Store a pushed manifest once its parents, trees and chunks are all present.
+fn receive_manifest(
+    state: &ServerState,
+    id: &[u8; 32],
+    bytes: &[u8],
+    verified_trees: &mut VerifiedTrees,
+) -> Result<()> {
+    let repo = &state.repo;
+    if repo.manifest_path(id).exists() {
+        return Ok(());
+    }
+
+    check_complete(repo, &state.store, id, bytes, verified_trees, true)?;
+    accept_manifest(repo, &state.db, id, bytes)
+}
+
+fn update_ref(state: &ServerState, name: &str, old: Option<&[u8; 32]>, new: &[u8; 32]) -> Result<()> {
+    let repo = &state.repo;
+    let short = name
+        .strip_prefix("refs/heads/")
+        .or_else(|| name.strip_prefix("refs/tags/"))
+        .ok_or_else(|| anyhow!("refusing to update '{name}': only branches and tags can be pushed"))?;
+    validate_ref_name(short)?;
+    if !repo.manifest_path(new).exists() {
+        bail!("cannot point {name} at unknown commit {}", hex::encode(new));
+    }
+
+    let _guard = state.refs.lock().map_err(|_| anyhow!("ref lock poisoned"))?;
+    let current = repo.read_ref(name)?;
+    if current.as_ref() != old {
+        bail!("{name} changed on the server since it was read; fetch and try again");
+    }
+    if let Some(current) = current {
+        if name.starts_with("refs/tags/") {
+            bail!("tag {short} already exists on the server");
+        }
+        if !is_ancestor(repo, &current, new)? {
+            bail!("non-fast-forward update of {name}; pull and merge first");
+        }
+    }
+    repo.write_ref(name, new)
+}
+
+/// Send `want` and its history past `have`; chunks only `with_chunks`.
+async fn serve_pull<S: AsyncRead + AsyncWrite>(
+    state: &ServerState,
+    chan: &mut Channel<S>,
+    want: &[u8; 32],
+    have: &[[u8; 32]],
+    with_chunks: bool,
+) -> Result<()> {
+    let repo = &state.repo;
+    if !repo.manifest_path(want).exists() {
+        bail!("unknown commit {}", hex::encode(want));
+    }
+
+    let mut known = Vec::new();
+    let mut exclude = HashSet::new();
+    for id in have {
+        if repo.manifest_path(id).exists() {
+            exclude.extend(ancestors(repo, id)?);
+            known.push(*id);
+        }
+    }
+
+    let commits = commits_between(repo, want, &exclude)?;
+    for id in &commits {
+        let path = repo.manifest_path(id);
+        let data = fs::read(&path).with_context(|| format!("read manifest {}", path.display()))?;
+        chan.send(&ServerMessage::Manifest { commit_id: *id, data }).await?;
+    }
+
+    let (trees, mut chunks) = objects_for(repo, &commits, &known)?;
+    if !with_chunks {
+        chunks.clear();
+    }
+    let (mut send, offered) = if chunks.len() > EXACT_OFFER_LIMIT {
+        chan.send(&ServerMessage::FilterRequest).await?;
+        let Some(ClientMessage::Filter { data }) = chan.recv::<ClientMessage>().await? else {
+            bail!("expected Filter after FilterRequest");
+        };
+        partition(&BloomFilter::from_bytes(&data)?, chunks)
+    } else {
+        (Vec::new(), chunks)
+    };
+    chan.send(&ServerMessage::Offer {
+        trees: trees.clone(),
+        chunks: offered.clone(),
+    })
+    .await?;
+
+    let Some(ClientMessage::Have {
+        trees: have_trees,
+        chunks: have_chunks,
+    }) = chan.recv::<ClientMessage>().await?
+    else {
+        bail!("expected Have after Offer");
+    };
+    let have_trees = unpack_bits(&have_trees, trees.len())?;
+    let have_chunks = unpack_bits(&have_chunks, offered.len())?;
+    send.extend(missing(offered, &have_chunks));
+
+    for id in missing(trees, &have_trees) {
+        let data = fs::read(repo.tree_object_path(&id))
+            .with_context(|| format!("read tree {}", hex::encode(id)))?;
+        chan.send(&ServerMessage::TreeData { id, data }).await?;
+    }
+    // One frame per chunk, read just before it is sent.
+    for hash in send {
+        let data = wire_chunk(&state.store, &hash, state.compression_level)?;
+        chan.send(&ServerMessage::ChunkData { hash, data }).await?;
+    }
+    chan.send(&ServerMessage::Done).await
+}
+
+async fn serve_chunks<S: AsyncRead + AsyncWrite>(
+    state: &ServerState,
+    chan: &mut Channel<S>,
+    hashes: &[[u8; 32]],
+) -> Result<()> {
+    for hash in hashes {
+        if !state.store.contains(&blake3::Hash::from(*hash)) {
+            bail!("unknown chunk {}", hex::encode(hash));
+        }
+    }
+    for hash in hashes {
+        let data = wire_chunk(&state.store, hash, state.compression_level)?;
+        chan.send(&ServerMessage::ChunkData { hash: *hash, data }).await?;
+    }
+    chan.send(&ServerMessage::Done).await
+}
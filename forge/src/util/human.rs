@@ -25,3 +25,22 @@ pub fn short_hex(hash: &[u8; 32]) -> String {
     let h = hex::encode(hash);
     h.chars().take(12).collect()
 }
+
+/// Parse a size such as `512M`, `20GB` or `1048576` (bytes, 1024-based units).
+pub fn parse_bytes(text: &str) -> anyhow::Result<u64> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => anyhow::bail!("unknown size unit in '{text}' (use K, M, G or T)"),
+    };
+    let value: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid size '{text}'"))?;
+    Ok((value * (1u64 << shift) as f64) as u64)
+}
@@ -55,6 +55,12 @@ impl ForgeIgnore {
         }
     }
 
+    /// Whether a whole directory can be skipped. Only safe when no `!` rule
+    /// could re-include something beneath it.
+    pub fn excludes_dir(&self, dir: &Path) -> bool {
+        !self.rules.iter().any(|rule| rule.negated) && self.is_ignored(dir)
+    }
+
     pub fn is_ignored(&self, path: &Path) -> bool {
         let rel = path
             .strip_prefix(&self.root)
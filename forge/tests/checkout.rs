@@ -0,0 +1,132 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use common::{commit, forge, forge_fails};
+use forge::core::manifest::deserialize_file_entry;
+use forge::core::repository::Repository;
+use forge::core::worktree::{StatRecord, RACY_WINDOW_NS};
+use forge::db::metadata::MetadataDb;
+
+fn head(root: &Path) -> String {
+    hex::encode(Repository::discover(root).unwrap().read_head().unwrap().unwrap())
+}
+
+fn metadata_db(root: &Path) -> MetadataDb {
+    MetadataDb::open(&Repository::discover(root).unwrap().metadata_db_path()).unwrap()
+}
+
+fn is_clean(root: &Path) -> bool {
+    let out = forge(root, &["status"]);
+    !out.contains("Staged files") && !out.contains("Working tree changes")
+}
+
+#[test]
+fn checkout_refuses_to_clobber_local_changes() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a one")], "one");
+    let one = head(root);
+    commit(root, &[("a.txt", b"a two"), ("b.txt", b"b two")], "two");
+    let two = head(root);
+
+    fs::write(root.join("a.txt"), b"a local").unwrap();
+    let err = forge_fails(root, &["checkout", &one]);
+    assert!(err.contains("would overwrite local changes in:\n  a.txt"), "{err}");
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a local");
+
+    forge(root, &["checkout", "-f", &one]);
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a one");
+
+    // An untracked file where the target has one is not silently replaced.
+    fs::write(root.join("b.txt"), b"b untracked").unwrap();
+    let err = forge_fails(root, &["checkout", &two]);
+    assert!(err.contains("would overwrite local changes in:\n  b.txt"), "{err}");
+    assert_eq!(fs::read(root.join("b.txt")).unwrap(), b"b untracked");
+
+    fs::write(root.join("c.txt"), b"c").unwrap();
+    forge(root, &["add", "c.txt"]);
+    assert!(forge_fails(root, &["checkout", &two]).contains("you have staged changes"));
+}
+
+#[test]
+fn checkout_removes_stale_tracked_files_but_not_untracked_ones() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a")], "one");
+    let one = head(root);
+    commit(root, &[("dir/b.txt", b"b"), ("dir/sub/c.txt", b"c")], "two");
+    fs::write(root.join("untracked.txt"), b"mine").unwrap();
+    fs::write(root.join("dir/untracked.txt"), b"mine too").unwrap();
+
+    assert!(forge(root, &["checkout", &one]).contains("2 removed"));
+    assert!(!root.join("dir/b.txt").exists());
+    assert!(!root.join("dir/sub").exists());
+    assert_eq!(fs::read(root.join("untracked.txt")).unwrap(), b"mine");
+    assert_eq!(fs::read(root.join("dir/untracked.txt")).unwrap(), b"mine too");
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a");
+}
+
+#[test]
+fn checkout_refreshes_tracked_files_and_the_stat_cache() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a one"), ("b.txt", b"b")], "one");
+    let one = head(root);
+    commit(root, &[("a.txt", b"a two"), ("c.txt", b"c")], "two");
+    // Once the files leave the racy window, status caches a record for each.
+    sleep(Duration::from_nanos(RACY_WINDOW_NS as u64 + 100_000_000));
+    assert!(is_clean(root));
+    assert_eq!(metadata_db(root).get_stat_cache().unwrap().len(), 3);
+
+    forge(root, &["checkout", &one]);
+    assert!(is_clean(root));
+
+    let db = metadata_db(root);
+    let tracked: Vec<(String, [u8; 32])> = db
+        .get_all_tracked_files()
+        .unwrap()
+        .into_iter()
+        .map(|(path, bytes)| (path, deserialize_file_entry(&bytes).unwrap().file_hash))
+        .collect();
+    let expected = [("a.txt", &b"a one"[..]), ("b.txt", b"b")]
+        .map(|(path, contents)| (path.to_string(), *blake3::hash(contents).as_bytes()));
+    assert_eq!(tracked, expected);
+
+    // The rewritten a.txt and removed c.txt lose their records; b.txt keeps its own.
+    let cached: Vec<(String, [u8; 32])> = db
+        .get_stat_cache()
+        .unwrap()
+        .into_iter()
+        .map(|(path, bytes)| (path, StatRecord::from_bytes(&bytes).unwrap().file_hash))
+        .collect();
+    assert_eq!(cached, [("b.txt".to_string(), *blake3::hash(b"b").as_bytes())]);
+}
+
+#[test]
+fn checkout_paths_restores_files_without_moving_head() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a one"), ("dir/b.txt", b"b one")], "one");
+    let one = head(root);
+    commit(root, &[("a.txt", b"a two"), ("dir/b.txt", b"b two"), ("dir/c.txt", b"c")], "two");
+    let two = head(root);
+
+    assert!(forge(root, &["checkout", &one, "--", "dir"]).contains("Restored 1 files"));
+    assert_eq!(head(root), two);
+    assert_eq!(fs::read(root.join("dir/b.txt")).unwrap(), b"b one");
+    assert_eq!(fs::read(root.join("dir/c.txt")).unwrap(), b"c");
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a two");
+    assert!(forge(root, &["status"]).contains("M modified dir/b.txt"));
+
+    assert!(forge_fails(root, &["checkout", &one, "--", "missing"]).contains("did not match any file"));
+}
@@ -0,0 +1,577 @@
+mod common;
+
+use std::collections::HashSet;
+
+use forge::chunking::cdc::{ChunkConfig, ChunkResult};
+use forge::chunking::chunk_file;
+use forge::chunking::structure_aware::png::pixel_hash;
+use forge::core::manifest::FileType;
+
+use common::{noise, png, png_chunk};
+
+/// Small enough that every structural region in the fixtures is one chunk.
+fn config() -> ChunkConfig {
+    ChunkConfig {
+        min_size: 64,
+        avg_size: 256,
+        max_size: 1024,
+    }
+}
+
+/// Chunk `data`, checking the chunks cover it back to back and hash what
+/// they cover, so they reassemble to the input byte for byte.
+fn chunk(file_type: FileType, data: &[u8]) -> Vec<ChunkResult> {
+    let chunks = chunk_file(data, file_type, &config());
+    let mut rebuilt = Vec::with_capacity(data.len());
+    for chunk in &chunks {
+        assert_eq!(chunk.offset, rebuilt.len(), "{file_type:?} chunks are not contiguous");
+        let bytes = &data[chunk.offset..chunk.offset + chunk.length];
+        assert_eq!(chunk.hash, blake3::hash(bytes));
+        rebuilt.extend_from_slice(bytes);
+    }
+    assert_eq!(rebuilt, data, "{file_type:?} chunks do not reassemble the input");
+    chunks
+}
+
+fn offsets(chunks: &[ChunkResult]) -> Vec<usize> {
+    chunks.iter().map(|chunk| chunk.offset).collect()
+}
+
+/// Offsets of the chunks in `new` whose hashes `old` does not have.
+fn changed(old: &[ChunkResult], new: &[ChunkResult]) -> Vec<usize> {
+    let old: HashSet<blake3::Hash> = old.iter().map(|chunk| chunk.hash).collect();
+    new.iter().filter(|chunk| !old.contains(&chunk.hash)).map(|chunk| chunk.offset).collect()
+}
+
+/// Every truncation of `data`, and its first `magic` bytes followed by
+/// garbage, still chunks without panicking and reassembles.
+fn assert_tolerates_damage(file_type: FileType, data: &[u8], magic: usize) {
+    for len in 0..data.len() {
+        chunk(file_type, &data[..len]);
+    }
+    for fill in [noise(4096, 99), vec![0; 4096], vec![0xFF; 4096]] {
+        let mut garbage = data[..magic].to_vec();
+        garbage.extend(fill);
+        chunk(file_type, &garbage);
+    }
+}
+
+/// A PSD with one record and the given channels per layer, then `merged`
+/// as the merged image. Returns the file and the offset each structural
+/// region after the header starts at, through the merged image.
+fn psd(layers: &[Vec<Vec<u8>>], merged: &[u8]) -> (Vec<u8>, Vec<usize>) {
+    let mut out = b"8BPS".to_vec();
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&[0; 6]);
+    out.extend_from_slice(&3u16.to_be_bytes());
+    out.extend_from_slice(&16u32.to_be_bytes());
+    out.extend_from_slice(&16u32.to_be_bytes());
+    out.extend_from_slice(&8u16.to_be_bytes());
+    out.extend_from_slice(&3u16.to_be_bytes());
+    // No color mode data.
+    out.extend_from_slice(&0u32.to_be_bytes());
+    let mut cuts = vec![out.len()];
+    let resources = noise(40, 1);
+    out.extend_from_slice(&(resources.len() as u32).to_be_bytes());
+    out.extend(resources);
+    cuts.push(out.len());
+
+    let mut info = (layers.len() as u16).to_be_bytes().to_vec();
+    let mut info_cuts = Vec::new();
+    for (i, channels) in layers.iter().enumerate() {
+        info.extend_from_slice(&[0; 16]);
+        info.extend_from_slice(&(channels.len() as u16).to_be_bytes());
+        for (id, channel) in channels.iter().enumerate() {
+            info.extend_from_slice(&(id as i16).to_be_bytes());
+            info.extend_from_slice(&(2 + channel.len() as u32).to_be_bytes());
+        }
+        info.extend_from_slice(b"8BIMnorm");
+        info.extend_from_slice(&[255, 0, 0, 0]);
+        // Empty mask and blending ranges, then the name padded to four bytes.
+        let mut extra = vec![0; 8];
+        extra.extend_from_slice(&[7, b'l', b'a', b'y', b'e', b'r', b' ', b'0' + i as u8]);
+        info.extend_from_slice(&(extra.len() as u32).to_be_bytes());
+        info.extend(extra);
+        info_cuts.push(info.len());
+    }
+    for channel in layers.iter().flatten() {
+        info.extend_from_slice(&0u16.to_be_bytes());
+        info.extend_from_slice(channel);
+        info_cuts.push(info.len());
+    }
+    info_cuts.pop();
+
+    // Section length, layer info length, the layer info, an empty global mask.
+    out.extend_from_slice(&(info.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(&(info.len() as u32).to_be_bytes());
+    let info_start = out.len();
+    out.extend(info);
+    cuts.extend(info_cuts.iter().map(|cut| info_start + cut));
+    let mask = out.len();
+    out.extend_from_slice(&0u32.to_be_bytes());
+    cuts.extend([mask, out.len()]);
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(merged);
+    (out, cuts)
+}
+
+fn psd_layers() -> Vec<Vec<Vec<u8>>> {
+    vec![
+        vec![noise(300, 2), noise(300, 3)],
+        vec![noise(300, 4), noise(300, 5)],
+    ]
+}
+
+#[test]
+fn psd_cuts_at_each_layer_record_and_channel() {
+    let (data, cuts) = psd(&psd_layers(), &noise(3000, 6));
+    let chunks = chunk(FileType::Psd, &data);
+    let starts = offsets(&chunks);
+    assert_eq!(starts[0], 0);
+    assert_eq!(&starts[1..=cuts.len()], cuts.as_slice());
+}
+
+#[test]
+fn psd_layer_edits_leave_other_layers_alone() {
+    let merged = noise(3000, 6);
+    let (data, cuts) = psd(&psd_layers(), &merged);
+    let before = chunk(FileType::Psd, &data);
+
+    // Repainting a channel in place only touches that channel.
+    let mut layers = psd_layers();
+    layers[1][1][10] ^= 0xFF;
+    let after = chunk(FileType::Psd, &psd(&layers, &merged).0);
+    // Resources, two records, three channels, then the last one.
+    assert_eq!(changed(&before, &after), vec![cuts[6]]);
+
+    // Growing one also rewrites its record and the first one, which carries
+    // the section lengths; the merged image's chunks only move.
+    let mut layers = psd_layers();
+    layers[1][0].extend(noise(50, 7));
+    let (data, cuts) = psd(&layers, &merged);
+    let after = chunk(FileType::Psd, &data);
+    assert_eq!(changed(&before, &after), vec![cuts[1], cuts[2], cuts[5]]);
+}
+
+#[test]
+fn psd_damage_falls_back_without_losing_bytes() {
+    let (data, _) = psd(&psd_layers(), &noise(600, 6));
+    assert_tolerates_damage(FileType::Psd, &data, 6);
+}
+
+/// A little-endian `.blend` holding `blocks` (code and data) then `ENDB`,
+/// in the layout with 8-byte pointers or, with `v5`, the Blender 5.0 one.
+/// Returns the file and the offset of each block after the first that is
+/// not `DATA`.
+fn blend(v5: bool, blocks: &[(&[u8; 4], Vec<u8>)]) -> (Vec<u8>, Vec<usize>) {
+    let mut out = if v5 { b"BLENDER17-01v0500".to_vec() } else { b"BLENDER-v300".to_vec() };
+    let mut cuts = Vec::new();
+    let end = (b"ENDB", Vec::new());
+    for (i, (code, body)) in blocks.iter().chain(std::iter::once(&end)).enumerate() {
+        if i > 0 && *code != b"DATA" && *code != b"ENDB" {
+            cuts.push(out.len());
+        }
+        let pointer = 0x1000 * (i as u64 + 1);
+        out.extend_from_slice(*code);
+        if v5 {
+            // SDNA index, old pointer, length, count.
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&pointer.to_le_bytes());
+            out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+            out.extend_from_slice(&1u64.to_le_bytes());
+        } else {
+            // Length, old pointer, SDNA index, count.
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            out.extend_from_slice(&pointer.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&1u32.to_le_bytes());
+        }
+        out.extend_from_slice(body);
+    }
+    (out, cuts)
+}
+
+fn blend_blocks() -> Vec<(&'static [u8; 4], Vec<u8>)> {
+    vec![
+        (b"OB\0\0", noise(200, 10)),
+        (b"DATA", noise(100, 11)),
+        (b"ME\0\0", noise(300, 12)),
+        (b"DATA", noise(300, 13)),
+        (b"DATA", noise(200, 14)),
+        (b"MA\0\0", noise(150, 15)),
+        (b"DNA1", noise(500, 16)),
+    ]
+}
+
+#[test]
+fn blend_cuts_before_each_datablock() {
+    for v5 in [false, true] {
+        let (data, cuts) = blend(v5, &blend_blocks());
+        let chunks = chunk(FileType::Blend, &data);
+        let mut expected = vec![0];
+        expected.extend(cuts);
+        assert_eq!(offsets(&chunks), expected, "v5: {v5}");
+    }
+}
+
+#[test]
+fn blend_datablock_edits_leave_other_datablocks_alone() {
+    for v5 in [false, true] {
+        let (data, _) = blend(v5, &blend_blocks());
+        let before = chunk(FileType::Blend, &data);
+
+        // The mesh's vertex data changes in place.
+        let mut blocks = blend_blocks();
+        blocks[4].1[0] ^= 0xFF;
+        let (data, cuts) = blend(v5, &blocks);
+        assert_eq!(changed(&before, &chunk(FileType::Blend, &data)), vec![cuts[0]]);
+
+        // The material grows, moving the blocks after it.
+        let mut blocks = blend_blocks();
+        blocks[5].1.extend(noise(40, 17));
+        let (data, cuts) = blend(v5, &blocks);
+        assert_eq!(changed(&before, &chunk(FileType::Blend, &data)), vec![cuts[1]]);
+    }
+}
+
+#[test]
+fn blend_damage_falls_back_without_losing_bytes() {
+    for v5 in [false, true] {
+        let (data, _) = blend(v5, &blend_blocks());
+        assert_tolerates_damage(FileType::Blend, &data, 7);
+    }
+    // Header lengths past the end of the file or short of the version.
+    for header in [&b"BLENDER99-01v0500"[..], b"BLENDER02-01v0500", b"BLENDER17-01v"] {
+        let mut data = header.to_vec();
+        data.extend(noise(40, 18));
+        chunk(FileType::Blend, &data);
+        chunk(FileType::Blend, header);
+    }
+}
+
+/// Offsets of the PNG chunks of `kind` in `data`.
+fn png_chunks_of(data: &[u8], kind: &[u8; 4]) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        if &data[pos + 4..pos + 8] == kind {
+            found.push(pos);
+        }
+        pos += 12 + len;
+    }
+    found
+}
+
+fn png_fixture(text: &[u8]) -> Vec<u8> {
+    let ancillary = [png_chunk(b"tEXt", text), png_chunk(b"pHYs", &[0, 0, 11, 19, 0, 0, 11, 19, 1])];
+    png(32, &noise(32 * 32 * 3, 20), 0, 6, &ancillary, 500)
+}
+
+#[test]
+fn png_cuts_around_each_chunk_and_image_data_run() {
+    let data = png_fixture(b"Comment\0first");
+    let starts = offsets(&chunk(FileType::Png, &data));
+    let idat = png_chunks_of(&data, b"IDAT");
+    let mut expected = vec![0];
+    expected.extend(png_chunks_of(&data, b"tEXt"));
+    expected.extend(png_chunks_of(&data, b"pHYs"));
+    expected.push(idat[0]);
+    let before_idat: Vec<usize> = starts.iter().copied().filter(|&at| at <= idat[0]).collect();
+    assert_eq!(before_idat, expected);
+    // The image data is split by content, and IEND stands alone.
+    assert!(starts.len() > expected.len() + 2);
+    assert_eq!(starts.last(), png_chunks_of(&data, b"IEND").first());
+}
+
+#[test]
+fn png_metadata_edits_leave_image_data_alone() {
+    let before = chunk(FileType::Png, &png_fixture(b"Comment\0first"));
+    let data = png_fixture(b"Comment\0a longer second comment");
+    let after = chunk(FileType::Png, &data);
+    assert_eq!(changed(&before, &after), png_chunks_of(&data, b"tEXt"));
+    assert_eq!(pixel_hash(&data), pixel_hash(&png_fixture(b"Comment\0first")));
+}
+
+#[test]
+fn png_damage_falls_back_without_losing_bytes() {
+    let data = png_fixture(b"Comment\0first");
+    assert_tolerates_damage(FileType::Png, &data, 8);
+    for len in 0..data.len() {
+        pixel_hash(&data[..len]);
+    }
+
+    // A header asking for far more pixels than the image data holds.
+    let mut huge = data.clone();
+    huge[16..24].copy_from_slice(&[0x7F, 0xFF, 0xFF, 0xFF, 0x7F, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(pixel_hash(&huge), None);
+    assert!(pixel_hash(&data).is_some());
+}
+
+fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = (8 + body.len() as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+/// A box with a version and flags word ahead of `fields`.
+fn mp4_full_box(kind: &[u8; 4], flags: u32, fields: &[u32]) -> Vec<u8> {
+    let body: Vec<u8> = std::iter::once(flags).chain(fields.iter().copied()).flat_map(u32::to_be_bytes).collect();
+    mp4_box(kind, &body)
+}
+
+/// Offsets where each sample of `samples` starts and the last one ends, for
+/// samples stored back to back from `start`.
+fn sample_bounds(start: usize, samples: &[Vec<u8>]) -> Vec<usize> {
+    let mut bounds = vec![start];
+    for sample in samples {
+        bounds.push(bounds.last().unwrap() + sample.len());
+    }
+    bounds
+}
+
+fn mp4_samples(seed: u64, count: usize) -> Vec<Vec<u8>> {
+    (0..count).map(|i| noise(40 + (i * 37) % 160, seed + i as u64)).collect()
+}
+
+/// A progressive MP4: `ftyp`, a `moov` with one track and `udta` holding
+/// `metadata`, then one `mdat`. Returns the file and the sample bounds.
+fn mp4(samples: &[Vec<u8>], metadata: &[u8]) -> (Vec<u8>, Vec<usize>) {
+    let sizes: Vec<u32> = samples.iter().map(|sample| sample.len() as u32).collect();
+    let ftyp = mp4_box(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+    let moov = |offset: u32| {
+        let mut stsz = vec![0, sizes.len() as u32];
+        stsz.extend(&sizes);
+        let stbl = [
+            mp4_full_box(b"stsz", 0, &stsz),
+            mp4_full_box(b"stsc", 0, &[1, 1, sizes.len() as u32, 1]),
+            mp4_full_box(b"stco", 0, &[1, offset]),
+        ]
+        .concat();
+        let trak = mp4_box(b"trak", &mp4_box(b"mdia", &mp4_box(b"minf", &mp4_box(b"stbl", &stbl))));
+        mp4_box(b"moov", &[trak, mp4_box(b"udta", metadata)].concat())
+    };
+    let mdat_body = ftyp.len() + moov(0).len() + 8;
+    let mut out = [ftyp, moov(mdat_body as u32)].concat();
+    out.extend(mp4_box(b"mdat", &samples.concat()));
+    (out, sample_bounds(mdat_body, samples))
+}
+
+/// A fragmented MP4: `ftyp`, a `moov` whose `trex` has no default sample
+/// size, then a `moof` and `mdat` per fragment. Returns the file, the
+/// sample bounds and where each `moof` starts.
+fn fragmented_mp4(fragments: &[Vec<Vec<u8>>]) -> (Vec<u8>, Vec<usize>, Vec<usize>) {
+    let trex = mp4_full_box(b"trex", 0, &[1, 1, 0, 0, 0]);
+    let mut out = [mp4_box(b"ftyp", b"iso6\0\0\0\0iso6"), mp4_box(b"moov", &mp4_box(b"mvex", &trex))].concat();
+    let (mut bounds, mut moofs) = (Vec::new(), Vec::new());
+    for (i, samples) in fragments.iter().enumerate() {
+        let moof = |data_offset: u32| {
+            let mut trun = vec![samples.len() as u32, data_offset];
+            trun.extend(samples.iter().map(|sample| sample.len() as u32));
+            let traf = [mp4_full_box(b"tfhd", 0x02_0000, &[1]), mp4_full_box(b"trun", 0x201, &trun)].concat();
+            mp4_box(b"moof", &[mp4_full_box(b"mfhd", 0, &[i as u32 + 1]), mp4_box(b"traf", &traf)].concat())
+        };
+        let data_offset = moof(0).len() + 8;
+        moofs.push(out.len());
+        bounds.extend(sample_bounds(out.len() + data_offset, samples));
+        out.extend(moof(data_offset as u32));
+        out.extend(mp4_box(b"mdat", &samples.concat()));
+    }
+    (out, bounds, moofs)
+}
+
+/// Chunk starts other than `0`, the `boxes` given and sample bounds.
+fn mp4_stray_cuts(chunks: &[ChunkResult], boxes: &[usize], bounds: &[usize]) -> Vec<usize> {
+    offsets(chunks)
+        .into_iter()
+        .filter(|at| *at != 0 && !boxes.contains(at) && !bounds.contains(at))
+        .collect()
+}
+
+#[test]
+fn mp4_cuts_between_samples_and_around_metadata() {
+    let samples = mp4_samples(30, 80);
+    let (data, bounds) = mp4(&samples, b"first");
+    let chunks = chunk(FileType::Mp4, &data);
+    // `ftyp`, `moov` and the `mdat` header make one chunk.
+    assert_eq!(offsets(&chunks)[..2], [0, bounds[0]]);
+    assert!(chunks.len() > 3);
+    assert_eq!(mp4_stray_cuts(&chunks, &[], &bounds), Vec::<usize>::new());
+
+    let fragments = vec![mp4_samples(200, 40), mp4_samples(300, 40)];
+    let (data, bounds, moofs) = fragmented_mp4(&fragments);
+    let chunks = chunk(FileType::Mp4, &data);
+    for at in &moofs[1..] {
+        assert!(offsets(&chunks).contains(at), "no cut before the moof at {at}");
+    }
+    assert_eq!(mp4_stray_cuts(&chunks, &moofs, &bounds), Vec::<usize>::new());
+}
+
+#[test]
+fn mp4_box_edits_leave_other_chunks_alone() {
+    let samples = mp4_samples(30, 80);
+    let before = chunk(FileType::Mp4, &mp4(&samples, b"first").0);
+
+    // Longer metadata moves the whole `mdat`; only the `moov` chunk changes.
+    let after = chunk(FileType::Mp4, &mp4(&samples, b"a longer second title").0);
+    assert_eq!(changed(&before, &after), vec![0]);
+
+    // A sample edited in place only changes the chunk holding it.
+    let mut edited = samples.clone();
+    edited[40][5] ^= 0xFF;
+    let (data, bounds) = mp4(&edited, b"first");
+    let after = chunk(FileType::Mp4, &data);
+    let holding = after.iter().rfind(|chunk| chunk.offset <= bounds[40]).unwrap().offset;
+    assert_eq!(changed(&before, &after), vec![holding]);
+
+    // Editing the second fragment leaves the first fragment's chunks alone.
+    let fragments = vec![mp4_samples(200, 40), mp4_samples(300, 40)];
+    let (data, _, moofs) = fragmented_mp4(&fragments);
+    let before = chunk(FileType::Mp4, &data);
+    let mut edited = fragments.clone();
+    edited[1][3][0] ^= 0xFF;
+    let after = chunk(FileType::Mp4, &fragmented_mp4(&edited).0);
+    let changed = changed(&before, &after);
+    assert!(!changed.is_empty() && changed.iter().all(|&at| at >= moofs[1]), "{changed:?}");
+}
+
+#[test]
+fn mp4_damage_falls_back_without_losing_bytes() {
+    let (data, _) = mp4(&mp4_samples(30, 20), b"first");
+    assert_tolerates_damage(FileType::Mp4, &data, 8);
+    let (data, _, _) = fragmented_mp4(&[mp4_samples(200, 10), mp4_samples(300, 10)]);
+    assert_tolerates_damage(FileType::Mp4, &data, 8);
+}
+
+/// A binary glTF whose BIN chunk holds `views` back to back, each described
+/// by a bufferView, with a material named `material`. Returns the file and
+/// where the BIN data and each view start.
+fn glb(views: &[Vec<u8>], material: &str) -> (Vec<u8>, Vec<usize>) {
+    let mut offsets = vec![0];
+    for view in views {
+        offsets.push(offsets.last().unwrap() + view.len());
+    }
+    let buffer_views: Vec<String> = views
+        .iter()
+        .zip(&offsets)
+        .map(|(view, offset)| format!(r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{}}}"#, view.len()))
+        .collect();
+    let mut json = format!(
+        r#"{{"asset":{{"version":"2.0"}},"buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"materials":[{{"name":"{material}"}}]}}"#,
+        offsets.last().unwrap(),
+        buffer_views.join(",")
+    )
+    .into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let bin = views.concat();
+
+    let mut out = b"glTF".to_vec();
+    out.extend_from_slice(&2u32.to_le_bytes());
+    out.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"JSON");
+    out.extend(json);
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"BIN\0");
+    let bin_start = out.len();
+    out.extend(bin);
+    let starts = offsets[..views.len()].iter().map(|offset| bin_start + offset).collect();
+    (out, starts)
+}
+
+fn glb_views() -> Vec<Vec<u8>> {
+    vec![noise(600, 40), noise(300, 41), noise(800, 42)]
+}
+
+#[test]
+fn glb_cuts_at_each_buffer_view() {
+    let (data, views) = glb(&glb_views(), "red");
+    let mut expected = vec![0];
+    expected.extend(views);
+    assert_eq!(offsets(&chunk(FileType::Gltf, &data)), expected);
+}
+
+#[test]
+fn glb_edits_leave_other_buffer_views_alone() {
+    let before = chunk(FileType::Gltf, &glb(&glb_views(), "red").0);
+
+    // A material edit only rewrites the JSON chunk.
+    let after = chunk(FileType::Gltf, &glb(&glb_views(), "a much darker red").0);
+    assert_eq!(changed(&before, &after), vec![0]);
+
+    // Resizing one view rewrites the JSON describing it and the view itself.
+    let mut views = glb_views();
+    views[1].extend(noise(20, 43));
+    let (data, starts) = glb(&views, "red");
+    assert_eq!(changed(&before, &chunk(FileType::Gltf, &data)), vec![0, starts[1]]);
+}
+
+#[test]
+fn glb_damage_falls_back_without_losing_bytes() {
+    let (data, _) = glb(&glb_views(), "red");
+    assert_tolerates_damage(FileType::Gltf, &data, 4);
+}
+
+/// A USD crate file: the bootstrap, `values`, then the named sections and
+/// the table of contents. Returns the file and where the values, each
+/// section and the table of contents start.
+fn usdc(values: &[u8], sections: &[(&str, Vec<u8>)]) -> (Vec<u8>, Vec<usize>) {
+    let mut out = b"PXR-USDC".to_vec();
+    out.extend_from_slice(&[0, 10, 0, 0, 0, 0, 0, 0]);
+    out.resize(88, 0);
+    let mut starts = vec![out.len()];
+    out.extend_from_slice(values);
+
+    let mut toc = (sections.len() as u64).to_le_bytes().to_vec();
+    for (name, body) in sections {
+        starts.push(out.len());
+        let mut field = name.as_bytes().to_vec();
+        field.resize(16, 0);
+        toc.extend(field);
+        toc.extend_from_slice(&(out.len() as u64).to_le_bytes());
+        toc.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        out.extend_from_slice(body);
+    }
+    starts.push(out.len());
+    let toc_offset = (out.len() as u64).to_le_bytes();
+    out[16..24].copy_from_slice(&toc_offset);
+    out.extend(toc);
+    (out, starts)
+}
+
+fn usd_sections() -> Vec<(&'static str, Vec<u8>)> {
+    ["TOKENS", "STRINGS", "FIELDS", "FIELDSETS", "PATHS", "SPECS"]
+        .into_iter()
+        .zip(50..)
+        .map(|(name, seed)| (name, noise(100 + seed as usize, seed)))
+        .collect()
+}
+
+#[test]
+fn usdc_cuts_at_each_section() {
+    let (data, starts) = usdc(&noise(900, 49), &usd_sections());
+    let mut expected = vec![0];
+    expected.extend(starts);
+    assert_eq!(offsets(&chunk(FileType::Usd, &data)), expected);
+}
+
+#[test]
+fn usdc_section_edits_leave_value_data_alone() {
+    let values = noise(900, 49);
+    let before = chunk(FileType::Usd, &usdc(&values, &usd_sections()).0);
+
+    // New tokens move every later section, so the table of contents and its
+    // offset in the bootstrap change with them.
+    let mut sections = usd_sections();
+    sections[0].1.extend_from_slice(b"\0displayColor");
+    let (data, starts) = usdc(&values, &sections);
+    let toc = *starts.last().unwrap();
+    assert_eq!(changed(&before, &chunk(FileType::Usd, &data)), vec![0, starts[1], toc]);
+}
+
+#[test]
+fn usdc_damage_falls_back_without_losing_bytes() {
+    let (data, _) = usdc(&noise(300, 49), &usd_sections());
+    assert_tolerates_damage(FileType::Usd, &data, 8);
+}
@@ -0,0 +1,94 @@
+//! Hand-built file fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::Path;
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Run the `forge` binary in `dir`, failing the test if it fails; returns stdout.
+pub fn forge(dir: &Path, args: &[&str]) -> String {
+    let output = cargo_bin_cmd!("forge").current_dir(dir).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "forge {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Run the `forge` binary in `dir`, expecting it to fail; returns stdout
+/// followed by stderr.
+pub fn forge_fails(dir: &Path, args: &[&str]) -> String {
+    let output = cargo_bin_cmd!("forge").current_dir(dir).args(args).output().unwrap();
+    assert!(!output.status.success(), "forge {args:?} unexpectedly succeeded");
+    let mut out = String::from_utf8_lossy(&output.stdout).into_owned();
+    out.push_str(&String::from_utf8_lossy(&output.stderr));
+    out
+}
+
+/// Write `files` under `dir`, stage them and commit; returns the commit output.
+pub fn commit(dir: &Path, files: &[(&str, &[u8])], message: &str) -> String {
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+    }
+    let mut args = vec!["add"];
+    args.extend(files.iter().map(|(path, _)| *path));
+    forge(dir, &args);
+    forge(dir, &["commit", "-m", message])
+}
+
+/// One PNG chunk. The CRC is left zero; forge never reads it.
+pub fn png_chunk(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = (body.len() as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out.extend_from_slice(&[0; 4]);
+    out
+}
+
+/// An 8-bit RGB PNG `width` pixels wide holding `rgb`, with every scanline
+/// using `filter` (0 none or 1 sub) and deflated at `level`. `ancillary`
+/// chunks go before the image data, which is split into `IDAT` chunks of at
+/// most `idat_len` bytes.
+pub fn png(width: u32, rgb: &[u8], filter: u8, level: u32, ancillary: &[Vec<u8>], idat_len: usize) -> Vec<u8> {
+    let stride = width as usize * 3;
+    let height = (rgb.len() / stride) as u32;
+    let mut raw = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks(stride) {
+        raw.push(filter);
+        for (i, &byte) in row.iter().enumerate() {
+            let left = if filter == 1 && i >= 3 { row[i - 3] } else { 0 };
+            raw.push(byte.wrapping_sub(left));
+        }
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+    encoder.write_all(&raw).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut header = width.to_be_bytes().to_vec();
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    out.extend(png_chunk(b"IHDR", &header));
+    for chunk in ancillary {
+        out.extend_from_slice(chunk);
+    }
+    for part in compressed.chunks(idat_len) {
+        out.extend(png_chunk(b"IDAT", part));
+    }
+    out.extend(png_chunk(b"IEND", &[]));
+    out
+}
+
+/// Bytes that do not compress or repeat, from a fixed seed.
+pub fn noise(len: usize, seed: u64) -> Vec<u8> {
+    use rand::{RngCore, SeedableRng};
+    let mut buf = vec![0u8; len];
+    rand::rngs::StdRng::seed_from_u64(seed).fill_bytes(&mut buf);
+    buf
+}
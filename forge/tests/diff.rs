@@ -0,0 +1,39 @@
+mod common;
+
+use std::fs;
+
+use tempfile::tempdir;
+
+use common::{forge, noise, png, png_chunk};
+
+#[test]
+fn pixels_flag_notes_reencoded_pngs_in_the_working_tree() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    let rgb = noise(32 * 32 * 3, 1);
+    let mut edited = rgb.clone();
+    edited[100] ^= 0xFF;
+
+    forge(root, &["init"]);
+    fs::write(root.join("same.png"), png(32, &rgb, 0, 1, &[], 8192)).unwrap();
+    fs::write(root.join("edited.png"), png(32, &rgb, 0, 1, &[], 8192)).unwrap();
+    forge(root, &["add", "same.png", "edited.png"]);
+    forge(root, &["commit", "-m", "images"]);
+
+    // Same pixels with another filter, zlib level and a text chunk.
+    let text = png_chunk(b"tEXt", b"Software\0another editor");
+    fs::write(root.join("same.png"), png(32, &rgb, 1, 9, &[text], 8192)).unwrap();
+    fs::write(root.join("edited.png"), png(32, &edited, 0, 1, &[], 8192)).unwrap();
+
+    let out = forge(root, &["diff", "--pixels"]);
+    let lines: Vec<&str> = out.lines().collect();
+    let after = |name: &str| {
+        let at = lines.iter().position(|line| line.starts_with(&format!("M {name} "))).unwrap();
+        lines.get(at + 1).copied().unwrap_or("")
+    };
+    assert!(after("same.png").contains("pixels unchanged"), "{out}");
+    assert!(!after("edited.png").contains("pixels unchanged"), "{out}");
+
+    let out = forge(root, &["diff"]);
+    assert!(out.contains("M same.png") && !out.contains("pixels unchanged"), "{out}");
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::fs;
+
+use tempfile::tempdir;
+
+use common::{commit, forge, forge_fails};
+use forge::core::repository::Repository;
+use forge::store::compression;
+use forge::util::human::short_hex;
+
+#[test]
+fn fsck_passes_on_a_sound_repository() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a"), ("dir/b.txt", b"b")], "one");
+    commit(root, &[("a.txt", b"a two")], "two");
+    let out = forge(root, &["fsck"]);
+    assert!(out.contains("2 reachable commits, 3 files") && out.contains("No problems found."), "{out}");
+}
+
+#[test]
+fn fsck_reports_and_quarantines_corrupt_chunks() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a"), ("b.txt", b"b")], "one");
+    let repo = Repository::discover(root).unwrap();
+    let head = short_hex(&repo.read_head().unwrap().unwrap());
+    let store = repo.chunk_store().unwrap();
+    let hash = blake3::hash(b"a");
+    let path = store.chunk_path(&hash);
+
+    // Valid zstd, wrong content.
+    fs::write(&path, compression::compress(b"not a", 3).unwrap()).unwrap();
+    let out = forge_fails(root, &["fsck"]);
+    assert!(out.contains(&format!("corrupt chunk {} (content hash mismatch)", hash.to_hex())), "{out}");
+    assert!(out.contains(&format!("a.txt @ {head}: uses corrupt chunk {}", hash.to_hex())), "{out}");
+    assert!(!out.contains("b.txt"), "{out}");
+
+    fs::write(&path, b"garbage").unwrap();
+    assert!(forge_fails(root, &["fsck"]).contains("(undecodable"));
+
+    forge_fails(root, &["fsck", "--quarantine"]);
+    assert!(!path.exists());
+    assert!(root.join(".forge/quarantine/chunks").join(hash.to_hex().as_str()).exists());
+    let out = forge_fails(root, &["fsck"]);
+    assert!(out.contains(&format!("a.txt @ {head}: missing chunk {}", hash.to_hex())), "{out}");
+    assert!(out.contains("1 problem(s) found"), "{out}");
+}
+
+#[test]
+fn fsck_reports_and_quarantines_corrupt_manifests_and_trees() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("dir/a.txt", b"a")], "one");
+    let repo = Repository::discover(root).unwrap();
+    let id = repo.read_head().unwrap().unwrap();
+    let id_hex = hex::encode(id);
+    let tree = repo.read_commit(&id).unwrap().tree;
+
+    let tree_path = repo.tree_object_path(&tree);
+    let mut bytes = fs::read(&tree_path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&tree_path, bytes).unwrap();
+    let out = forge_fails(root, &["fsck"]);
+    assert!(out.contains(&format!("corrupt tree {}", hex::encode(tree))), "{out}");
+
+    let manifest = repo.manifest_path(&id);
+    let mut bytes = fs::read(&manifest).unwrap();
+    bytes[0] ^= 0xff;
+    fs::write(&manifest, bytes).unwrap();
+    let out = forge_fails(root, &["fsck"]);
+    assert!(out.contains(&format!("corrupt manifest {id_hex}")), "{out}");
+    assert!(out.contains(&format!("commit {id_hex} differs between metadata.redb and manifests/")), "{out}");
+
+    forge_fails(root, &["fsck", "--quarantine"]);
+    assert!(root.join(".forge/quarantine/manifests").join(&id_hex).exists());
+    assert!(root.join(".forge/quarantine/trees").join(hex::encode(tree)).exists());
+    let out = forge_fails(root, &["fsck"]);
+    assert!(out.contains(&format!("missing commit {id_hex} (reachable from HEAD)")), "{out}");
+}
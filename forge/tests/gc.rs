@@ -0,0 +1,85 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use common::{commit, forge};
+use forge::core::repository::Repository;
+
+/// Whether the chunk holding `contents` (small enough to be one chunk) is stored.
+fn has_chunk(root: &Path, contents: &[u8]) -> bool {
+    let repo = Repository::discover(root).unwrap();
+    repo.chunk_store().unwrap().contains(&blake3::hash(contents))
+}
+
+/// Commit `path` on a new branch `name` and switch back to main.
+fn commit_on_branch(root: &Path, name: &str, path: &str, contents: &[u8]) {
+    forge(root, &["switch", "-c", name]);
+    commit(root, &[(path, contents)], name);
+    forge(root, &["switch", "main"]);
+}
+
+/// Stage `contents` at `path`, then restage it with other contents so the
+/// first chunk is referenced by nothing.
+fn orphan_chunk(root: &Path, path: &str, contents: &[u8]) {
+    fs::write(root.join(path), contents).unwrap();
+    forge(root, &["add", path]);
+    fs::write(root.join(path), b"replacement").unwrap();
+    forge(root, &["add", path]);
+}
+
+#[test]
+fn gc_keeps_chunks_reachable_from_refs_merge_head_and_the_index() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("main.txt", b"on main")], "main");
+
+    commit_on_branch(root, "feature", "feature.txt", b"on a branch");
+
+    commit_on_branch(root, "tagged", "tagged.txt", b"only tagged");
+    forge(root, &["tag", "v1", "tagged"]);
+    forge(root, &["branch", "-d", "tagged"]);
+
+    commit_on_branch(root, "merging", "merging.txt", b"being merged");
+    let repo = Repository::discover(root).unwrap();
+    let merging = repo.read_ref("refs/heads/merging").unwrap().unwrap();
+    repo.write_merge_head(&merging).unwrap();
+    forge(root, &["branch", "-d", "merging"]);
+
+    fs::write(root.join("staged.txt"), b"only staged").unwrap();
+    forge(root, &["add", "staged.txt"]);
+    orphan_chunk(root, "orphan.txt", b"referenced by nothing");
+
+    forge(root, &["gc", "--grace-hours", "0"]);
+
+    for contents in [&b"on main"[..], b"on a branch", b"only tagged", b"being merged", b"only staged"] {
+        assert!(has_chunk(root, contents), "gc removed {:?}", String::from_utf8_lossy(contents));
+    }
+    assert!(!has_chunk(root, b"referenced by nothing"));
+
+    repo.clear_merge_head().unwrap();
+    forge(root, &["commit", "-m", "staged"]);
+    forge(root, &["switch", "feature"]);
+    assert_eq!(fs::read(root.join("feature.txt")).unwrap(), b"on a branch");
+}
+
+#[test]
+fn gc_keeps_recent_chunks_and_dry_run_deletes_nothing() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    orphan_chunk(root, "orphan.txt", b"referenced by nothing");
+
+    assert!(forge(root, &["gc"]).contains("Removed 0 unreachable chunks"));
+    assert!(has_chunk(root, b"referenced by nothing"));
+
+    let out = forge(root, &["gc", "--grace-hours", "0", "--dry-run"]);
+    assert!(out.contains("Would remove 1 unreachable chunks"), "{out}");
+    assert!(has_chunk(root, b"referenced by nothing"));
+
+    forge(root, &["gc", "--grace-hours", "0"]);
+    assert!(!has_chunk(root, b"referenced by nothing"));
+}
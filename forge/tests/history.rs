@@ -0,0 +1,261 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use tempfile::{tempdir, TempDir};
+
+use common::{commit, forge, forge_fails};
+use forge::core::graph::merge_base;
+use forge::core::repository::Repository;
+use forge::core::revision::resolve_revision;
+
+fn head(root: &Path) -> [u8; 32] {
+    Repository::discover(root).unwrap().read_head().unwrap().unwrap()
+}
+
+fn parents(root: &Path, id: &[u8; 32]) -> Vec<[u8; 32]> {
+    Repository::discover(root).unwrap().read_commit(id).unwrap().parents
+}
+
+/// Path to file hash for every file in commit `id`.
+fn files(root: &Path, id: &[u8; 32]) -> BTreeMap<String, [u8; 32]> {
+    let repo = Repository::discover(root).unwrap();
+    repo.read_commit_files(id).unwrap().into_iter().map(|(path, e)| (path, e.file_hash)).collect()
+}
+
+/// A repository with `a.txt` and `b.txt` committed on main and a `feature`
+/// branch at the same commit.
+fn forked() -> TempDir {
+    let dir = tempdir().unwrap();
+    forge(dir.path(), &["init"]);
+    commit(dir.path(), &[("a.txt", b"a base"), ("b.txt", b"b base")], "base");
+    forge(dir.path(), &["branch", "feature"]);
+    dir
+}
+
+#[test]
+fn commits_snapshot_the_whole_tree() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a one"), ("dir/b.txt", b"b one")], "one");
+    let one = head(root);
+    commit(root, &[("a.txt", b"a two")], "two");
+    let two = head(root);
+
+    assert_eq!(parents(root, &two), [one]);
+    let snapshot = files(root, &two);
+    assert_eq!(snapshot.keys().collect::<Vec<_>>(), ["a.txt", "dir/b.txt"]);
+    assert_eq!(snapshot["a.txt"], *blake3::hash(b"a two").as_bytes());
+    assert_eq!(snapshot["dir/b.txt"], files(root, &one)["dir/b.txt"]);
+
+    forge(root, &["rm", "dir/b.txt"]);
+    forge(root, &["commit", "-m", "three"]);
+    assert_eq!(files(root, &head(root)).into_keys().collect::<Vec<_>>(), ["a.txt"]);
+
+    forge(root, &["checkout", &hex::encode(one)]);
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a one");
+    assert_eq!(fs::read(root.join("dir/b.txt")).unwrap(), b"b one");
+}
+
+#[test]
+fn status_names_branches_with_slashes() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    fs::write(root.join("a.txt"), "one").unwrap();
+    forge(root, &["add", "a.txt"]);
+    forge(root, &["commit", "-m", "one"]);
+    forge(root, &["switch", "-c", "feature/foo"]);
+    assert!(forge(root, &["status"]).contains("On branch: feature/foo"));
+}
+
+#[test]
+fn merge_fast_forwards_when_head_is_behind() {
+    let dir = forked();
+    let root = dir.path();
+    forge(root, &["switch", "feature"]);
+    commit(root, &[("a.txt", b"a feature")], "feature");
+    let feature = head(root);
+    forge(root, &["switch", "main"]);
+
+    assert!(forge(root, &["merge", "feature"]).contains("Fast-forward"));
+    assert_eq!(head(root), feature);
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a feature");
+}
+
+#[test]
+fn merge_combines_changes_to_different_files() {
+    let dir = forked();
+    let root = dir.path();
+    forge(root, &["switch", "feature"]);
+    commit(root, &[("a.txt", b"a feature")], "feature");
+    let feature = head(root);
+    forge(root, &["switch", "main"]);
+    commit(root, &[("b.txt", b"b main")], "main");
+    let main = head(root);
+
+    assert!(forge(root, &["merge", "feature"]).contains("Merge made"));
+    assert_eq!(parents(root, &head(root)), [main, feature]);
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a feature");
+    assert_eq!(fs::read(root.join("b.txt")).unwrap(), b"b main");
+}
+
+#[test]
+fn both_modified_conflicts_wait_for_resolve() {
+    let dir = forked();
+    let root = dir.path();
+    forge(root, &["switch", "feature"]);
+    commit(root, &[("a.txt", b"a feature"), ("b.txt", b"b feature")], "feature");
+    let feature = head(root);
+    forge(root, &["switch", "main"]);
+    commit(root, &[("a.txt", b"a main")], "main");
+    let main = head(root);
+
+    assert!(forge_fails(root, &["merge", "feature"]).contains("automatic merge failed"));
+    assert_eq!(head(root), main);
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a main");
+    assert_eq!(fs::read(root.join("b.txt")).unwrap(), b"b feature");
+    assert!(forge_fails(root, &["merge", "feature"]).contains("already in progress"));
+
+    assert!(forge(root, &["resolve", "a.txt", "--theirs"]).contains("All conflicts resolved"));
+    assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a feature");
+    forge(root, &["commit", "-m", "merged"]);
+    let merged = head(root);
+    assert_eq!(parents(root, &merged), [main, feature]);
+    assert_eq!(files(root, &merged), files(root, &feature));
+}
+
+#[test]
+fn delete_modify_conflicts_can_keep_the_deletion() {
+    let dir = forked();
+    let root = dir.path();
+    forge(root, &["switch", "feature"]);
+    commit(root, &[("a.txt", b"a feature")], "feature");
+    forge(root, &["switch", "main"]);
+    forge(root, &["rm", "a.txt"]);
+    forge(root, &["commit", "-m", "drop a"]);
+
+    forge_fails(root, &["merge", "feature"]);
+    assert!(!root.join("a.txt").exists());
+    assert!(forge(root, &["resolve", "a.txt", "--ours"]).contains("(deleted)"));
+    forge(root, &["commit", "-m", "merged"]);
+    assert!(!files(root, &head(root)).contains_key("a.txt"));
+    assert!(!root.join("a.txt").exists());
+}
+
+#[test]
+fn criss_cross_merges_pick_the_newest_base() {
+    let dir = forked();
+    let root = dir.path();
+    commit(root, &[("a.txt", b"a main")], "main");
+    let main = head(root);
+    forge(root, &["switch", "feature"]);
+    commit(root, &[("b.txt", b"b feature")], "feature");
+    let feature = head(root);
+
+    // Each side merges the other's first commit, so both are best common ancestors.
+    forge(root, &["merge", &hex::encode(main)]);
+    let feature_merge = head(root);
+    forge(root, &["switch", "main"]);
+    forge(root, &["merge", &hex::encode(feature)]);
+    let main_merge = head(root);
+
+    let repo = Repository::discover(root).unwrap();
+    assert_eq!(merge_base(&repo, &main_merge, &feature_merge).unwrap(), Some(feature));
+    assert_eq!(merge_base(&repo, &feature_merge, &main_merge).unwrap(), Some(feature));
+
+    assert!(forge(root, &["merge", "feature"]).contains("Merge made"));
+    assert_eq!(files(root, &head(root)), files(root, &feature_merge));
+}
+
+fn resolve(root: &Path, spec: &str) -> Result<[u8; 32], String> {
+    resolve_revision(&Repository::discover(root).unwrap(), spec).map_err(|err| format!("{err:#}"))
+}
+
+#[test]
+fn revisions_follow_ancestry_suffixes() {
+    let dir = forked();
+    let root = dir.path();
+    let base = head(root);
+    commit(root, &[("a.txt", b"a two")], "two");
+    let two = head(root);
+    forge(root, &["tag", "v2"]);
+    commit(root, &[("a.txt", b"a three")], "three");
+    let three = head(root);
+    forge(root, &["switch", "feature"]);
+    commit(root, &[("b.txt", b"b feature")], "feature");
+    let feature = head(root);
+    forge(root, &["switch", "main"]);
+    forge(root, &["merge", "feature"]);
+    let merge = head(root);
+
+    for (spec, id) in [
+        ("HEAD", merge),
+        ("@", merge),
+        ("main", merge),
+        ("heads/main", merge),
+        ("HEAD^0", merge),
+        ("HEAD~", three),
+        ("HEAD^", three),
+        ("@~2", two),
+        ("main~3", base),
+        ("HEAD^2", feature),
+        ("HEAD^2~1", base),
+        ("HEAD~1^1~", base),
+        ("v2", two),
+        ("tags/v2~1", base),
+        (" feature ", feature),
+    ] {
+        assert_eq!(resolve(root, spec), Ok(id), "{spec}");
+    }
+    assert_eq!(resolve(root, &hex::encode(two)), Ok(two));
+    assert_eq!(resolve(root, &format!("{}~1", &hex::encode(three)[..8])), Ok(two));
+    assert_eq!(resolve(root, &hex::encode(feature)[..6].to_uppercase()), Ok(feature));
+
+    for (spec, error) in [
+        ("main~4", "goes past the root commit"),
+        ("HEAD^3", "has no parent #3"),
+        ("nope", "unknown revision 'nope'"),
+        ("~1", "invalid revision '~1'"),
+        ("HEAD~x", "invalid revision 'HEAD~x'"),
+        ("HEAD~é", "invalid revision 'HEAD~é'"),
+        ("HEAD~1é2", "invalid revision"),
+        ("HEAD^²", "invalid revision"),
+        ("HEAD~99999999999999999999", "invalid number"),
+    ] {
+        let err = resolve(root, spec).unwrap_err();
+        assert!(err.contains(error), "{spec}: {err}");
+    }
+}
+
+#[test]
+fn ambiguous_revisions_are_errors() {
+    let dir = forked();
+    let root = dir.path();
+    let base = head(root);
+    commit(root, &[("a.txt", b"a two")], "two");
+    let two = head(root);
+    let base_hex = hex::encode(base);
+
+    // A second manifest sharing all but the last digit of `base`.
+    let repo = Repository::discover(root).unwrap();
+    let mut twin = base;
+    twin[31] ^= 1;
+    fs::copy(repo.manifest_path(&base), repo.manifest_path(&twin)).unwrap();
+    let err = resolve(root, &base_hex[..8]).unwrap_err();
+    assert!(err.contains("is ambiguous") && err.contains(&base_hex) && err.contains(&hex::encode(twin)), "{err}");
+    assert_eq!(resolve(root, &base_hex), Ok(base));
+
+    // A branch whose name is also a prefix of another commit.
+    let prefix = &hex::encode(two)[..4];
+    forge(root, &["branch", prefix, &base_hex]);
+    let err = resolve(root, prefix).unwrap_err();
+    assert!(err.contains(&format!("refs/heads/{prefix}")) && err.contains("commit "), "{err}");
+    // Unless both name the same commit.
+    forge(root, &["branch", "-d", prefix]);
+    forge(root, &["branch", prefix, "HEAD"]);
+    assert_eq!(resolve(root, prefix), Ok(two));
+}
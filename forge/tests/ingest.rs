@@ -0,0 +1,149 @@
+mod common;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use common::{forge, noise};
+use forge::cli::add::stage_paths;
+use forge::core::manifest::deserialize_file_entry;
+use forge::core::repository::Repository;
+use forge::db::metadata::MetadataDb;
+
+/// Files of assorted sizes, with whole-file duplicates and a pair sharing
+/// most of their chunks. Returns the relative paths.
+fn write_dataset(root: &Path) -> Vec<String> {
+    let sizes = [0, 1, 100, 70_000, 300_000, 1_200_000];
+    let mut files: BTreeMap<String, Vec<u8>> = (0..24)
+        .map(|i| (format!("assets/{}/f{i}.bin", i % 4), noise(sizes[i % sizes.len()], i as u64)))
+        .collect();
+    let copy = files["assets/1/f5.bin"].clone();
+    files.insert("copies/a.bin".to_string(), copy.clone());
+    files.insert("copies/b.bin".to_string(), copy);
+    let mut base = noise(900_000, 99);
+    files.insert("edits/base.bin".to_string(), base.clone());
+    base[850_000..].fill(7);
+    files.insert("edits/edited.bin".to_string(), base);
+
+    for (path, contents) in &files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    files.into_keys().collect()
+}
+
+type Staged = BTreeMap<String, (u64, [u8; 32], Vec<([u8; 32], u64, u32)>)>;
+
+/// Staged entries by path, and every loose chunk.
+fn staged(repo: &Repository) -> (Staged, BTreeSet<[u8; 32]>) {
+    let db = MetadataDb::open(&repo.metadata_db_path()).unwrap();
+    let entries = db
+        .get_staged_files()
+        .unwrap()
+        .into_iter()
+        .map(|(path, bytes)| {
+            let entry = deserialize_file_entry(&bytes).unwrap();
+            let chunks = entry.chunks.iter().map(|c| (c.hash, c.offset, c.length)).collect();
+            (path, (entry.size, entry.file_hash, chunks))
+        })
+        .collect();
+    let chunks = repo.chunk_store().unwrap().list_loose().unwrap().iter().map(|h| *h.as_bytes()).collect();
+    (entries, chunks)
+}
+
+/// Largest anonymous (heap) resident size seen while `forge args` runs in
+/// `dir`, in bytes. File-backed pages of an mmapped input are not counted.
+#[cfg(target_os = "linux")]
+fn peak_anon_rss(dir: &std::path::Path, args: &[&str]) -> u64 {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin_cmd!("forge").get_program())
+        .current_dir(dir)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let status = format!("/proc/{}/status", child.id());
+    let mut peak = 0;
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            assert!(status.success(), "forge {args:?} failed");
+            return peak;
+        }
+        let kb = fs::read_to_string(&status)
+            .ok()
+            .and_then(|s| s.lines().find_map(|l| l.strip_prefix("RssAnon:").map(str::to_string)))
+            .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        peak = peak.max(kb * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn adding_a_large_file_keeps_memory_bounded() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    let size = 512 * 1024 * 1024;
+    fs::File::create(root.join("master.mov")).unwrap().set_len(size).unwrap();
+
+    // Each compressor holds at most a chunk and its zstd context.
+    let workers = std::thread::available_parallelism().unwrap().get() as u64;
+    let bound = 48 * 1024 * 1024 + workers * 4 * 1024 * 1024;
+    let peak = peak_anon_rss(root, &["add", "master.mov"]);
+    assert!(peak < bound.min(size / 4), "peak anonymous memory {peak} bytes");
+
+    forge(root, &["commit", "-m", "master"]);
+    let repo = Repository::discover(root).unwrap();
+    let entry = &repo.read_commit_files(&repo.read_head().unwrap().unwrap()).unwrap()["master.mov"];
+    assert_eq!(entry.size, size);
+    assert_eq!(entry.chunks.iter().map(|c| c.length as u64).sum::<u64>(), size);
+}
+
+#[test]
+fn pipelined_add_matches_adding_files_one_at_a_time() {
+    let all_at_once = tempdir().unwrap();
+    let one_by_one = tempdir().unwrap();
+    let mut repos = Vec::new();
+    for dir in [&all_at_once, &one_by_one] {
+        let repo = Repository::init(dir.path()).unwrap();
+        let paths = write_dataset(dir.path());
+        repos.push((repo, paths));
+    }
+
+    let (batched, paths) = &repos[0];
+    let dirs: Vec<String> = ["assets", "copies", "edits"].map(String::from).to_vec();
+    let stats = stage_paths(batched, &dirs, false).unwrap();
+    assert_eq!(stats.staged_files, paths.len());
+
+    let (sequential, paths) = &repos[1];
+    let mut new_chunks = 0;
+    for path in paths {
+        new_chunks += stage_paths(sequential, std::slice::from_ref(path), false).unwrap().new_chunks;
+    }
+    assert_eq!(stats.new_chunks, new_chunks);
+
+    let (entries, chunks) = staged(batched);
+    let (sequential_entries, sequential_chunks) = staged(sequential);
+    assert_eq!(entries, sequential_entries);
+    assert_eq!(chunks, sequential_chunks);
+    assert_eq!(chunks.len(), new_chunks);
+    assert!(stats.deduped_chunks > 0);
+
+    // Every entry reassembles to the file on disk.
+    let store = batched.chunk_store().unwrap();
+    for (path, (size, file_hash, refs)) in &entries {
+        let contents = fs::read(all_at_once.path().join(path)).unwrap();
+        assert_eq!((contents.len() as u64, blake3::hash(&contents).as_bytes()), (*size, file_hash));
+        for (hash, offset, length) in refs {
+            let raw = store.read_decompressed(&blake3::Hash::from(*hash)).unwrap();
+            assert_eq!(raw, contents[*offset as usize..][..*length as usize], "{path} @ {offset}");
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use tempfile::tempdir;
+
+use forge::store::cas::ChunkStore;
+use forge::transport::negotiate::{pack_bits, unpack_bits, BloomFilter};
+
+/// Distinct, well-spread ids, like the chunk hashes the filter sees.
+fn ids(range: std::ops::Range<u64>) -> Vec<[u8; 32]> {
+    range.map(|i| *blake3::hash(&i.to_le_bytes()).as_bytes()).collect()
+}
+
+fn false_positive_rate(filter: &BloomFilter, absent: &[[u8; 32]]) -> f64 {
+    absent.iter().filter(|id| filter.may_contain(id)).count() as f64 / absent.len() as f64
+}
+
+#[test]
+fn bloom_filters_have_no_false_negatives() {
+    for count in [0, 1, 63, 5_000] {
+        let inserted = ids(0..count);
+        let mut filter = BloomFilter::with_capacity(inserted.len(), 0.01);
+        for id in &inserted {
+            filter.insert(id);
+        }
+        assert!(inserted.iter().all(|id| filter.may_contain(id)), "{count} ids");
+
+        let decoded = BloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!(inserted.iter().all(|id| decoded.may_contain(id)), "{count} ids after decoding");
+    }
+}
+
+#[test]
+fn bloom_false_positives_stay_near_the_target_rate() {
+    let inserted = ids(0..20_000);
+    let absent = ids(1_000_000..1_100_000);
+    for target in [0.01, 0.001] {
+        let mut filter = BloomFilter::with_capacity(inserted.len(), target);
+        for id in &inserted {
+            filter.insert(id);
+        }
+        let rate = false_positive_rate(&filter, &absent);
+        assert!(rate > target / 3.0 && rate < target * 2.0, "target {target}, got {rate}");
+    }
+}
+
+#[test]
+fn store_filters_cover_every_chunk_at_one_percent() {
+    let dir = tempdir().unwrap();
+    let store = ChunkStore::new(dir.path().join("chunks"));
+    let mut stored = Vec::new();
+    for i in 0..2_000u64 {
+        let raw = i.to_le_bytes();
+        let hash = blake3::hash(&raw);
+        store.store(&hash, &raw).unwrap();
+        stored.push(*hash.as_bytes());
+    }
+
+    let filter = BloomFilter::for_store(&store).unwrap();
+    assert!(stored.iter().all(|id| filter.may_contain(id)));
+    let rate = false_positive_rate(&filter, &ids(1_000_000..1_100_000));
+    assert!(rate > 0.003 && rate < 0.02, "got {rate}");
+}
+
+#[test]
+fn malformed_bloom_filters_are_rejected() {
+    for bytes in [&[][..], &[3], &[0, 0xff], &[17, 0xff]] {
+        assert!(BloomFilter::from_bytes(bytes).is_err(), "{bytes:?}");
+    }
+    assert!(BloomFilter::from_bytes(&[16, 0xff]).is_ok());
+}
+
+#[test]
+fn bitmaps_round_trip_at_any_length() {
+    for len in [0usize, 1, 7, 8, 9, 13, 63, 64, 65, 100] {
+        let bits: Vec<bool> = (0..len).map(|i| i % 3 == 0 || i == len - 1).collect();
+        let packed = pack_bits(&bits);
+        assert_eq!(packed.len(), len.div_ceil(8));
+        assert_eq!(unpack_bits(&packed, len).unwrap(), bits, "{len} entries");
+    }
+    // Trailing padding bits never leak into the decoded list.
+    assert_eq!(unpack_bits(&[0xff], 3).unwrap(), [true; 3]);
+    assert!(unpack_bits(&[0, 0], 8).is_err());
+    assert!(unpack_bits(&[0], 9).is_err());
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use forge::store::cas::ChunkStore;
+use forge::store::compression;
+use forge::core::manifest::FileType;
+use forge::core::repository::Repository;
+use forge::store::compression::{dictionary_file_name, dictionary_id, frame_dict_id, train_dictionary, Dictionaries};
+use forge::store::pack::{create_pack, list_packs, pack_idx_path, pack_name, read_pack_index, PackFile};
+use tempfile::tempdir;
+
+use common::{commit, forge, noise};
+
+/// Store `count` random chunks loose and return their hashes.
+fn store_chunks(store: &ChunkStore, count: u64) -> Vec<[u8; 32]> {
+    (0..count)
+        .map(|seed| {
+            let raw = noise(4096, seed);
+            let hash = blake3::hash(&raw);
+            store.store(&hash, &compression::compress(&raw, 3).unwrap()).unwrap();
+            *hash.as_bytes()
+        })
+        .collect()
+}
+
+/// Small JSON-like records sharing `kind`'s vocabulary, the kind of data
+/// dictionaries help with.
+fn records(kind: &str, count: u64) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let mut out = String::new();
+            for j in 0..8 {
+                out.push_str(&format!(
+                    "{{\"{kind}_id\": {}, \"{kind}_name\": \"{kind}_{i}_{j}\", \"visible\": {}, \"scale\": [{}, 1.0, 1.0]}}\n",
+                    i * 8 + j,
+                    (i + j) % 2 == 0,
+                    j
+                ));
+            }
+            out.into_bytes()
+        })
+        .collect()
+}
+
+/// Train a dictionary on `kind` records and archive it under `base/dictionaries`.
+fn archive_dictionary(base: &Path, kind: &str) -> (u32, String) {
+    let dict = train_dictionary(&records(kind, 500), 4096).unwrap();
+    let id = dictionary_id(&dict).unwrap();
+    let rel = format!("dictionaries/{}", dictionary_file_name(id));
+    fs::create_dir_all(base.join("dictionaries")).unwrap();
+    fs::write(base.join(&rel), dict).unwrap();
+    (id, rel)
+}
+
+#[test]
+fn chunks_decode_with_retired_dictionaries() {
+    let dir = tempdir().unwrap();
+    let base = dir.path();
+    let (old_id, old_rel) = archive_dictionary(base, "mesh");
+    let (new_id, new_rel) = archive_dictionary(base, "light");
+    assert_ne!(old_id, new_id);
+    let sample = records("mesh", 501).pop().unwrap();
+
+    let registry = BTreeMap::from([(".JSON".to_string(), old_rel)]);
+    let dicts = Dictionaries::load(base, &registry, 3).unwrap();
+    assert_eq!(dicts.for_file("scene/a.json", FileType::Unknown), Some(old_id));
+    assert_eq!(dicts.for_file("scene/a.bin", FileType::Unknown), None);
+    let packed = dicts.compress(&sample, 3, Some(old_id)).unwrap();
+    assert_eq!(frame_dict_id(&packed), Some(old_id));
+    assert!(packed.len() < compression::compress(&sample, 3).unwrap().len());
+    assert_eq!(dicts.decompress(&packed).unwrap(), sample);
+
+    // Registering a newer dictionary retires the old one for new chunks only.
+    let registry = BTreeMap::from([("json".to_string(), new_rel)]);
+    let dicts = Dictionaries::load(base, &registry, 3).unwrap();
+    assert_eq!(dicts.for_file("scene/a.json", FileType::Unknown), Some(new_id));
+    assert_eq!(dicts.decompress(&packed).unwrap(), sample);
+
+    fs::remove_file(base.join("dictionaries").join(dictionary_file_name(old_id))).unwrap();
+    let dicts = Dictionaries::load(base, &registry, 3).unwrap();
+    let err = format!("{:#}", dicts.decompress(&packed).unwrap_err());
+    assert!(err.contains(&format!("dictionary {old_id:#010x}")), "{err}");
+}
+
+#[test]
+fn history_survives_retraining_dictionaries() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    for kind in ["mesh", "light"] {
+        let samples = root.join("samples").join(kind);
+        fs::create_dir_all(&samples).unwrap();
+        for (i, record) in records(kind, 300).into_iter().enumerate() {
+            fs::write(samples.join(format!("{i}.json")), record).unwrap();
+        }
+    }
+    let samples = |kind: &str| format!("samples/{kind}");
+
+    assert!(forge(root, &["train-dict", "--file-type", "json", "--samples", &samples("mesh")]).contains("Registered"));
+    let mesh = records("mesh", 301).pop().unwrap();
+    commit(root, &[("scene.json", &mesh)], "mesh");
+    let first = hex::encode(Repository::discover(root).unwrap().read_head().unwrap().unwrap());
+    let stored = |contents: &[u8]| {
+        let store = Repository::discover(root).unwrap().chunk_store().unwrap();
+        frame_dict_id(&store.read(&blake3::hash(contents)).unwrap())
+    };
+    let mesh_dict = stored(&mesh).expect("chunk compressed with the dictionary");
+
+    forge(root, &["train-dict", "--file-type", "json", "--samples", &samples("light")]);
+    let light = records("light", 301).pop().unwrap();
+    commit(root, &[("scene.json", &light)], "light");
+    let light_dict = stored(&light).expect("chunk compressed with the dictionary");
+    assert_ne!(mesh_dict, light_dict);
+
+    forge(root, &["checkout", &first]);
+    assert_eq!(fs::read(root.join("scene.json")).unwrap(), mesh);
+    assert!(forge(root, &["fsck"]).contains("No problems found."));
+}
+
+#[test]
+fn packed_chunks_are_read_through_the_index() {
+    let dir = tempdir().unwrap();
+    let store = ChunkStore::new(dir.path().join("chunks"));
+    let hashes = store_chunks(&store, 50);
+    fs::create_dir_all(&store.pack_dir).unwrap();
+    let pack = store.pack_dir.join(pack_name(&hashes));
+    let index = create_pack(&store, &hashes, &pack).unwrap();
+    for hash in &hashes {
+        store.remove(&blake3::Hash::from(*hash)).unwrap();
+    }
+    store.reload_packs();
+
+    for (seed, hash) in hashes.iter().enumerate() {
+        let hash = blake3::Hash::from(*hash);
+        assert!(!store.is_loose(&hash) && store.is_packed(&hash).unwrap());
+        assert_eq!(store.read_decompressed(&hash).unwrap(), noise(4096, seed as u64));
+    }
+    assert!(!store.contains(&blake3::hash(b"never stored")));
+
+    // The mapped index agrees with the pack trailer, and is rebuilt from it when lost.
+    for _ in 0..2 {
+        let lookup = PackFile::open(&pack).unwrap();
+        assert!(pack_idx_path(&pack).exists());
+        assert_eq!(lookup.len(), hashes.len());
+        for entry in &index.entries {
+            assert_eq!(lookup.find(&entry.hash).as_ref(), Some(entry));
+            assert_eq!(lookup.read(entry).unwrap(), store.read(&blake3::Hash::from(entry.hash)).unwrap());
+        }
+        assert!(lookup.find(&[0xab; 32]).is_none());
+        fs::remove_file(pack_idx_path(&pack)).unwrap();
+    }
+}
+
+#[test]
+fn repack_round_trips_history() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    let one = noise(200_000, 1);
+    commit(root, &[("one.bin", &one), ("small.txt", b"small")], "one");
+    let first = hex::encode(Repository::discover(root).unwrap().read_head().unwrap().unwrap());
+    let store = Repository::discover(root).unwrap().chunk_store().unwrap();
+    let chunks = store.list_loose().unwrap().len();
+
+    let out = forge(root, &["repack"]);
+    assert!(out.contains(&format!("Packed {chunks} chunks")), "{out}");
+    assert!(store.list_loose().unwrap().is_empty());
+    assert_eq!(list_packs(&store.pack_dir).unwrap().len(), 1);
+
+    let two = noise(200_000, 2);
+    commit(root, &[("two.bin", &two)], "two");
+    forge(root, &["repack"]);
+    assert_eq!(list_packs(&store.pack_dir).unwrap().len(), 2);
+    assert!(forge(root, &["repack", "--all"]).contains("replaced 2 pack(s)"));
+    assert_eq!(list_packs(&store.pack_dir).unwrap().len(), 1);
+    assert!(forge(root, &["repack", "--all"]).contains("Nothing to repack."));
+
+    assert!(forge(root, &["fsck"]).contains("No problems found."));
+    for path in ["one.bin", "two.bin", "small.txt"] {
+        fs::remove_file(root.join(path)).unwrap();
+    }
+    forge(root, &["checkout", "-f", &first]);
+    assert_eq!(fs::read(root.join("one.bin")).unwrap(), one);
+    assert_eq!(fs::read(root.join("small.txt")).unwrap(), b"small");
+    assert!(!root.join("two.bin").exists());
+}
+
+#[test]
+fn corrupt_pack_trailers_are_errors() {
+    let dir = tempdir().unwrap();
+    let store = ChunkStore::new(dir.path().join("chunks"));
+    let hashes = store_chunks(&store, 3);
+    let pack = dir.path().join("test.pack");
+    create_pack(&store, &hashes, &pack).unwrap();
+    let good = fs::read(&pack).unwrap();
+    let size_at = good.len() - 8;
+    let trailer_len = u64::from_le_bytes(good[size_at..].try_into().unwrap()) as usize;
+
+    for len in [0, 3, good.len() as u64 - 7, u64::MAX - 7, u64::MAX] {
+        let mut data = good.clone();
+        data[size_at..].copy_from_slice(&len.to_le_bytes());
+        fs::write(&pack, data).unwrap();
+        let err = format!("{:#}", read_pack_index(&pack).unwrap_err());
+        assert!(err.contains("corrupt index trailer"), "{len}: {err}");
+    }
+
+    // The first entry's offset, after the count and its hash.
+    let offset_at = size_at - trailer_len + 4 + 32;
+    for offset in [u64::MAX, u64::MAX - 100, size_at as u64] {
+        let mut data = good.clone();
+        data[offset_at..offset_at + 8].copy_from_slice(&offset.to_le_bytes());
+        fs::write(&pack, data).unwrap();
+        let err = format!("{:#}", read_pack_index(&pack).unwrap_err());
+        assert!(err.contains("points past the data section"), "{offset}: {err}");
+    }
+}
@@ -0,0 +1,496 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use forge::core::manifest::{
+    compute_commit_id, serialize_commit, serialize_tree, Commit, FileEntry, FileType, Tree, TreeEntry, TreeNode,
+};
+use forge::core::repository::Repository;
+use forge::db::metadata::MetadataDb;
+use forge::transport::objects::{accept_tree, check_complete};
+use forge::transport::protocol::{Channel, ClientMessage, ServerMessage};
+use forge::transport::server::{self, ServerState};
+use forge::transport::{client, quic};
+use rand::{RngCore, SeedableRng};
+use tempfile::tempdir;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// Serve a fresh bare repository on an ephemeral loopback port from a
+/// background runtime; returns the URL and the certificate to trust.
+fn start_server(dir: &Path) -> (String, PathBuf) {
+    let repo = Repository::init_bare(dir).unwrap();
+    let (cert, key) = quic::ensure_certificate(&repo).unwrap();
+    let (tx, rx) = std::sync::mpsc::channel::<SocketAddr>();
+    let (server_cert, server_key) = (cert.clone(), key);
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let server = quic::Server::bind(repo, "127.0.0.1:0", &server_cert, &server_key).unwrap();
+            tx.send(server.local_addr().unwrap()).unwrap();
+            server.run().await.unwrap();
+        });
+    });
+    let addr = rx.recv().unwrap();
+    (format!("quic://{addr}"), cert)
+}
+
+fn forge(dir: &Path, ca: &Path, args: &[&str]) -> String {
+    let output = cargo_bin_cmd!("forge")
+        .current_dir(dir)
+        .env("FORGE_QUIC_CA", ca)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "forge {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn write_random_file(path: &Path, size: usize, seed: u64) {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut buf = vec![0u8; size];
+    rng.fill_bytes(&mut buf);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, buf).unwrap();
+}
+
+#[test]
+fn push_and_pull_over_loopback() {
+    let dir = tempdir().unwrap();
+    let (url, ca) = start_server(&dir.path().join("server"));
+
+    let alice = dir.path().join("alice");
+    forge(dir.path(), &ca, &["init", "alice"]);
+    write_random_file(&alice.join("assets/big.bin"), 4 * 1024 * 1024, 1);
+    fs::write(alice.join("notes.txt"), "first\n").unwrap();
+    forge(&alice, &ca, &["add", "."]);
+    forge(&alice, &ca, &["commit", "-m", "first"]);
+    let out = forge(&alice, &ca, &["push", &url]);
+    assert!(out.contains("Pushed main"), "{out}");
+
+    let bob = dir.path().join("bob");
+    forge(dir.path(), &ca, &["init", "bob"]);
+    forge(&bob, &ca, &["pull", &url]);
+    assert_eq!(
+        fs::read(bob.join("assets/big.bin")).unwrap(),
+        fs::read(alice.join("assets/big.bin")).unwrap()
+    );
+    assert_eq!(fs::read_to_string(bob.join("notes.txt")).unwrap(), "first\n");
+
+    // A one-file change only ships that file's chunk and the trees above it.
+    fs::write(alice.join("notes.txt"), "second\n").unwrap();
+    forge(&alice, &ca, &["add", "notes.txt"]);
+    forge(&alice, &ca, &["commit", "-m", "second"]);
+    let out = forge(&alice, &ca, &["push", &url]);
+    assert!(out.contains("(1 commits, 1 trees, 1 chunks"), "{out}");
+    assert!(forge(&alice, &ca, &["push", &url]).contains("Everything up-to-date"));
+
+    let out = forge(&bob, &ca, &["pull", &url]);
+    assert!(out.contains("Fetched 1 commits, 1 trees, 1 chunks"), "{out}");
+    assert_eq!(fs::read_to_string(bob.join("notes.txt")).unwrap(), "second\n");
+
+    let server = Repository::open_bare(&dir.path().join("server")).unwrap();
+    let alice_head = Repository::discover(&alice).unwrap().read_head().unwrap();
+    assert_eq!(server.read_ref("refs/heads/main").unwrap(), alice_head);
+}
+
+#[test]
+fn diverged_push_is_rejected() {
+    let dir = tempdir().unwrap();
+    let (url, ca) = start_server(&dir.path().join("server"));
+
+    for name in ["alice", "bob"] {
+        forge(dir.path(), &ca, &["init", name]);
+    }
+    let (alice, bob) = (dir.path().join("alice"), dir.path().join("bob"));
+    fs::write(alice.join("a.txt"), "a\n").unwrap();
+    forge(&alice, &ca, &["add", "."]);
+    forge(&alice, &ca, &["commit", "-m", "alice"]);
+    forge(&alice, &ca, &["push", &url]);
+
+    fs::write(bob.join("b.txt"), "b\n").unwrap();
+    forge(&bob, &ca, &["add", "."]);
+    forge(&bob, &ca, &["commit", "-m", "bob"]);
+    let output = cargo_bin_cmd!("forge")
+        .current_dir(&bob)
+        .env("FORGE_QUIC_CA", &ca)
+        .args(["push", &url])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pull before pushing"));
+
+    forge(&bob, &ca, &["pull", &url]);
+    forge(&bob, &ca, &["push", &url]);
+    assert!(bob.join("a.txt").exists());
+}
+
+#[test]
+fn push_skips_objects_the_server_already_has() {
+    let dir = tempdir().unwrap();
+    let (url, ca) = start_server(&dir.path().join("server"));
+
+    for name in ["alice", "bob"] {
+        forge(dir.path(), &ca, &["init", name]);
+    }
+    let (alice, bob) = (dir.path().join("alice"), dir.path().join("bob"));
+    write_random_file(&alice.join("assets/big.bin"), 4 * 1024 * 1024, 7);
+    forge(&alice, &ca, &["add", "."]);
+    forge(&alice, &ca, &["commit", "-m", "alice"]);
+    forge(&alice, &ca, &["push", &url]);
+
+    // A new branch on an advertised commit needs nothing but the ref.
+    forge(&alice, &ca, &["switch", "-c", "topic"]);
+    let out = forge(&alice, &ca, &["push", &url]);
+    assert!(out.contains("(0 commits, 0 trees, 0 chunks"), "{out}");
+
+    // Unrelated history holding the same file only ships what is new.
+    fs::write(bob.join("b.txt"), "b\n").unwrap();
+    forge(&bob, &ca, &["add", "."]);
+    forge(&bob, &ca, &["commit", "-m", "bob"]);
+    forge(&bob, &ca, &["switch", "-c", "feature"]);
+    write_random_file(&bob.join("assets/big.bin"), 4 * 1024 * 1024, 7);
+    forge(&bob, &ca, &["add", "."]);
+    forge(&bob, &ca, &["commit", "-m", "same asset"]);
+    let out = forge(&bob, &ca, &["push", &url]);
+    assert!(out.contains("(2 commits,") && out.contains(" 1 chunks,"), "{out}");
+}
+
+#[test]
+fn protocol_1_peers_get_a_readable_error() {
+    let dir = tempdir().unwrap();
+    let state = ServerState::open(Repository::init_bare(&dir.path().join("server")).unwrap()).unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // A JSON-lines client is answered in JSON.
+        let (mut old_client, stream) = tokio::io::duplex(64 * 1024);
+        let serve = server::handle(&state, stream);
+        let talk = async {
+            old_client
+                .write_all(b"{\"Hello\":{\"client\":\"forge\",\"version\":\"0.1.0\"}}\n")
+                .await
+                .unwrap();
+            let mut line = String::new();
+            BufReader::new(&mut old_client).read_line(&mut line).await.unwrap();
+            line
+        };
+        let (served, line) = tokio::join!(serve, talk);
+        assert!(served.is_err());
+        assert!(line.starts_with("{\"Error\"") && line.contains("upgrade forge"), "{line}");
+
+        // A JSON-lines server's error line is reported by a new client.
+        let (stream, mut old_server) = tokio::io::duplex(64 * 1024);
+        let mut chan = Channel::open(stream).await.unwrap();
+        let talk = async {
+            let mut line = String::new();
+            BufReader::new(&mut old_server).read_line(&mut line).await.unwrap();
+            old_server
+                .write_all(b"{\"Error\":{\"message\":\"decode message\"}}\n")
+                .await
+                .unwrap();
+        };
+        let (handshake, ()) = tokio::join!(client::handshake(&mut chan), talk);
+        let err = format!("{:#}", handshake.unwrap_err());
+        assert!(err.contains("older forge protocol: decode message"), "{err}");
+    });
+}
+
+#[test]
+fn clone_and_fetch_track_remote_branches() {
+    let dir = tempdir().unwrap();
+    let (url, ca) = start_server(&dir.path().join("server"));
+
+    let alice = dir.path().join("alice");
+    forge(dir.path(), &ca, &["init", "alice"]);
+    write_random_file(&alice.join("assets/big.bin"), 1024 * 1024, 3);
+    fs::write(alice.join("notes.txt"), "main\n").unwrap();
+    forge(&alice, &ca, &["add", "."]);
+    forge(&alice, &ca, &["commit", "-m", "main"]);
+    forge(&alice, &ca, &["push", &url]);
+    forge(&alice, &ca, &["switch", "-c", "topic"]);
+    fs::write(alice.join("topic.txt"), "topic\n").unwrap();
+    forge(&alice, &ca, &["add", "topic.txt"]);
+    forge(&alice, &ca, &["commit", "-m", "topic"]);
+    forge(&alice, &ca, &["push", &url]);
+
+    forge(dir.path(), &ca, &["clone", &url, "carol"]);
+    let carol = dir.path().join("carol");
+    assert_eq!(
+        fs::read(carol.join("assets/big.bin")).unwrap(),
+        fs::read(alice.join("assets/big.bin")).unwrap()
+    );
+    assert!(!carol.join("topic.txt").exists());
+    let carol_repo = Repository::discover(&carol).unwrap();
+    let alice_repo = Repository::discover(&alice).unwrap();
+    assert_eq!(carol_repo.current_branch().unwrap().as_deref(), Some("main"));
+    for branch in ["main", "topic"] {
+        assert_eq!(
+            carol_repo.read_ref(&format!("refs/remotes/origin/{branch}")).unwrap(),
+            alice_repo.read_ref(&format!("refs/heads/{branch}")).unwrap()
+        );
+    }
+
+    // Fetch moves the tracking ref and leaves the working tree alone.
+    fs::write(alice.join("topic.txt"), "topic, again\n").unwrap();
+    forge(&alice, &ca, &["add", "topic.txt"]);
+    forge(&alice, &ca, &["commit", "-m", "more topic"]);
+    forge(&alice, &ca, &["push", &url]);
+    let out = forge(&carol, &ca, &["fetch"]);
+    assert!(out.contains("topic -> origin/topic"), "{out}");
+    assert_eq!(
+        carol_repo.read_ref("refs/remotes/origin/topic").unwrap(),
+        alice_repo.read_head().unwrap()
+    );
+    assert!(!carol.join("topic.txt").exists());
+    assert!(!forge(&carol, &ca, &["status"]).contains("Working tree changes"));
+}
+
+#[test]
+fn named_remotes_each_trust_their_own_certificate() {
+    let dir = tempdir().unwrap();
+    let (studio_url, studio_ca) = start_server(&dir.path().join("studio"));
+    let (backup_url, backup_ca) = start_server(&dir.path().join("backup"));
+
+    // No FORGE_QUIC_CA here: each remote's `ca` setting has to be used.
+    let alice = dir.path().join("alice");
+    let run = |args: &[&str]| {
+        let output = cargo_bin_cmd!("forge")
+            .current_dir(&alice)
+            .env_remove("FORGE_QUIC_CA")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "forge {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    forge(dir.path(), &studio_ca, &["init", "alice"]);
+    run(&["remote", "add", "studio", &studio_url, "--ca", studio_ca.to_str().unwrap()]);
+    run(&["remote", "add", "backup", &backup_url, "--ca", backup_ca.to_str().unwrap()]);
+    let out = run(&["remote", "-v"]);
+    assert!(out.contains(&studio_url) && out.contains(&backup_url), "{out}");
+
+    fs::write(alice.join("scene.txt"), "v1\n").unwrap();
+    run(&["add", "."]);
+    run(&["commit", "-m", "v1"]);
+    assert!(run(&["push", "studio"]).contains("Pushed main to studio"));
+    assert!(run(&["push", "backup"]).contains("Pushed main to backup"));
+
+    let head = Repository::discover(&alice).unwrap().read_head().unwrap();
+    for name in ["studio", "backup"] {
+        let server = Repository::open_bare(&dir.path().join(name)).unwrap();
+        assert_eq!(server.read_ref("refs/heads/main").unwrap(), head);
+    }
+
+    run(&["fetch", "backup"]);
+    let repo = Repository::discover(&alice).unwrap();
+    assert_eq!(repo.read_ref("refs/remotes/backup/main").unwrap(), head);
+    run(&["remote", "remove", "backup"]);
+    assert_eq!(repo.read_ref("refs/remotes/backup/main").unwrap(), None);
+    assert_eq!(run(&["remote"]), "studio\n");
+}
+
+#[test]
+fn partial_clone_fetches_chunks_on_demand() {
+    let dir = tempdir().unwrap();
+    let (url, ca) = start_server(&dir.path().join("server"));
+
+    let alice = dir.path().join("alice");
+    forge(dir.path(), &ca, &["init", "alice"]);
+    write_random_file(&alice.join("assets/big.bin"), 1024 * 1024, 4);
+    forge(&alice, &ca, &["add", "."]);
+    forge(&alice, &ca, &["commit", "-m", "v1"]);
+    let v1 = fs::read(alice.join("assets/big.bin")).unwrap();
+    write_random_file(&alice.join("assets/big.bin"), 1024 * 1024, 5);
+    forge(&alice, &ca, &["add", "."]);
+    forge(&alice, &ca, &["commit", "-m", "v2"]);
+    forge(&alice, &ca, &["push", &url]);
+    let v2 = fs::read(alice.join("assets/big.bin")).unwrap();
+
+    let out = forge(dir.path(), &ca, &["clone", "--partial", "--cache-size", "1536K", &url, "carol"]);
+    assert!(out.contains("Fetched 2 commits, 4 trees, 0 chunks"), "{out}");
+    let carol = dir.path().join("carol");
+    assert_eq!(fs::read(carol.join("assets/big.bin")).unwrap(), v2);
+
+    // History stays on the server until something reads it.
+    let repo = Repository::discover(&carol).unwrap();
+    let old = repo
+        .read_commit_files(&repo.read_commit(&repo.read_head().unwrap().unwrap()).unwrap().parents[0])
+        .unwrap();
+    let old_chunks: Vec<[u8; 32]> = old["assets/big.bin"].chunks.iter().map(|c| c.hash).collect();
+    let store = repo.chunk_store().unwrap();
+    assert!(old_chunks.iter().all(|h| !store.contains(&blake3::Hash::from(*h))));
+
+    let cat = |rev: &str| {
+        let output = cargo_bin_cmd!("forge")
+            .current_dir(&carol)
+            .env("FORGE_QUIC_CA", &ca)
+            .args(["cat", "assets/big.bin", "--rev", rev])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output.stdout
+    };
+    assert_eq!(cat("HEAD~1"), v1);
+    assert!(old_chunks.iter().all(|h| store.contains(&blake3::Hash::from(*h))));
+    // Reading v1 pushed the cache over its limit, so v2's chunks went.
+    assert!(store.total_size().unwrap() <= 1536 * 1024);
+    assert_eq!(cat("HEAD"), v2);
+
+    forge(&carol, &ca, &["checkout", "HEAD~1", "--", "assets"]);
+    assert_eq!(fs::read(carol.join("assets/big.bin")).unwrap(), v1);
+    forge(&carol, &ca, &["fsck"]);
+}
+
+#[test]
+fn fetch_rejects_commits_whose_objects_never_arrived() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("source");
+    fs::create_dir_all(&source).unwrap();
+    let ca = dir.path().join("unused.pem");
+    forge(&source, &ca, &["init"]);
+    write_random_file(&source.join("a.bin"), 4096, 7);
+    forge(&source, &ca, &["add", "a.bin"]);
+    forge(&source, &ca, &["commit", "-m", "one"]);
+    let source = Repository::discover(&source).unwrap();
+    let tip = source.read_head().unwrap().unwrap();
+    let manifest = fs::read(source.manifest_path(&tip)).unwrap();
+
+    let local = Repository::init(&dir.path().join("local")).unwrap();
+    let db = MetadataDb::open(&local.metadata_db_path()).unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // A server that sends the commit, then stops without its tree or chunks.
+        let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+        let serve = async {
+            let mut chan = Channel::accept(server_end).await.unwrap();
+            let Some(ClientMessage::PullRequest { .. }) = chan.recv().await.unwrap() else {
+                panic!("expected a pull request");
+            };
+            chan.send(&ServerMessage::Manifest {
+                commit_id: tip,
+                data: manifest.clone(),
+            })
+            .await
+            .unwrap();
+            chan.send(&ServerMessage::Offer {
+                trees: Vec::new(),
+                chunks: Vec::new(),
+            })
+            .await
+            .unwrap();
+            let Some(ClientMessage::Have { .. }) = chan.recv().await.unwrap() else {
+                panic!("expected have bitmaps");
+            };
+            chan.send(&ServerMessage::Done).await.unwrap();
+            chan.finish().await.unwrap();
+        };
+        let fetch = async {
+            let mut chan = Channel::open(client_end).await.unwrap();
+            client::fetch(&local, &db, &mut chan, &[tip], true).await
+        };
+        let ((), fetched) = tokio::join!(serve, fetch);
+        let err = format!("{:#}", fetched.unwrap_err());
+        assert!(err.contains("missing tree"), "{err}");
+    });
+    assert!(!local.manifest_path(&tip).exists());
+}
+
+#[test]
+fn received_trees_only_name_paths_inside_the_working_tree() {
+    let dir = tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let store = repo.chunk_store().unwrap();
+    let tree = |name: &str, path: &str| Tree {
+        entries: vec![TreeEntry {
+            name: name.to_string(),
+            node: TreeNode::File(FileEntry {
+                path: path.to_string(),
+                size: 0,
+                file_hash: *blake3::hash(b"").as_bytes(),
+                chunks: Vec::new(),
+                mode: 0,
+                mtime_ns: 0,
+                file_type: FileType::Unknown,
+            }),
+        }],
+        file_count: 1,
+    };
+
+    for name in ["", ".", "..", ".forge", "a/b", "a\\b"] {
+        let bytes = serialize_tree(&tree(name, "x")).unwrap();
+        let id = *blake3::hash(&bytes).as_bytes();
+        let err = format!("{:#}", accept_tree(&repo, &id, &bytes).unwrap_err());
+        assert!(err.contains("not a valid tree entry name"), "{name:?}: {err}");
+    }
+
+    // A plain name whose entry carries a path elsewhere.
+    let bytes = serialize_tree(&tree("a.txt", "../a.txt")).unwrap();
+    let root = *blake3::hash(&bytes).as_bytes();
+    accept_tree(&repo, &root, &bytes).unwrap();
+    let mut commit = Commit {
+        id: [0; 32],
+        parents: Vec::new(),
+        tree: root,
+        message: "escape".to_string(),
+        author: "test".to_string(),
+        timestamp_ns: 0,
+    };
+    commit.id = compute_commit_id(&commit).unwrap();
+    let manifest = serialize_commit(&commit).unwrap();
+    let err = check_complete(&repo, &store, &commit.id, &manifest, &mut Default::default(), true).unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("lists ../a.txt under a.txt"), "{err}");
+}
+
+#[test]
+fn long_pushes_do_not_stall_on_unread_acks() {
+    let dir = tempdir().unwrap();
+    let source = Repository::init(&dir.path().join("source")).unwrap();
+    let tree = source.write_tree(&Tree::default()).unwrap();
+    let mut tip = None;
+    for i in 0..3000 {
+        let mut commit = Commit {
+            id: [0; 32],
+            parents: tip.into_iter().collect(),
+            tree,
+            message: format!("commit {i}"),
+            author: "test".to_string(),
+            timestamp_ns: i,
+        };
+        commit.id = compute_commit_id(&commit).unwrap();
+        fs::write(source.manifest_path(&commit.id), serialize_commit(&commit).unwrap()).unwrap();
+        tip = Some(commit.id);
+    }
+    let tip = tip.unwrap();
+
+    let state = ServerState::open(Repository::init_bare(&dir.path().join("server")).unwrap()).unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // A pipe far smaller than the manifests and acks, standing in for
+        // the stream window a long history overruns.
+        let (client_end, server_end) = tokio::io::duplex(16 * 1024);
+        let serve = server::handle(&state, server_end);
+        let push = async {
+            let mut chan = Channel::open(client_end).await?;
+            let refs = client::handshake(&mut chan).await?;
+            let stats = client::push(&source, &mut chan, "refs/heads/main", &tip, None, &refs.refs).await?;
+            chan.finish().await?;
+            anyhow::Ok(stats)
+        };
+        let (served, pushed) = tokio::time::timeout(Duration::from_secs(60), async { tokio::join!(serve, push) })
+            .await
+            .expect("push stalled");
+        served.unwrap();
+        assert_eq!(pushed.unwrap().commits, 3000);
+    });
+    assert_eq!(state.repo.read_ref("refs/heads/main").unwrap(), Some(tip));
+}
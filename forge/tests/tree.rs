@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use tempfile::tempdir;
+
+use forge::core::manifest::{serialize_legacy_commit, FileEntry, FileType, LegacyCommit, TreeNode};
+use forge::core::repository::Repository;
+use forge::core::tree;
+
+fn entry(path: &str, seed: u8) -> FileEntry {
+    FileEntry {
+        path: path.to_string(),
+        size: seed as u64,
+        file_hash: [seed; 32],
+        chunks: Vec::new(),
+        mode: 0o644,
+        mtime_ns: 0,
+        file_type: FileType::Unknown,
+    }
+}
+
+fn snapshot(paths: &[(&str, u8)]) -> BTreeMap<String, FileEntry> {
+    paths.iter().map(|&(path, seed)| (path.to_string(), entry(path, seed))).collect()
+}
+
+/// Id of the subtree `name` directly under `root`.
+fn subtree(repo: &Repository, root: &[u8; 32], name: &str) -> Option<[u8; 32]> {
+    repo.read_tree(root).unwrap().entries.into_iter().find(|e| e.name == name).and_then(|e| match e.node {
+        TreeNode::Dir(id) => Some(id),
+        TreeNode::File(_) => None,
+    })
+}
+
+#[test]
+fn update_tree_matches_rebuilding_the_snapshot() {
+    let dir = tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let mut files = snapshot(&[
+        ("a.txt", 1),
+        ("dir/b.txt", 2),
+        ("dir/sub/c.txt", 3),
+        ("dir/sub/d.txt", 4),
+        ("gone/e.txt", 5),
+        ("keep/deep/f.txt", 6),
+        ("x", 7),
+        ("y/z.txt", 8),
+    ]);
+    let base = tree::write_tree(&repo, &files).unwrap();
+    assert_eq!(repo.read_tree(&base).unwrap().file_count, 8);
+
+    let changes: BTreeMap<String, Option<FileEntry>> = [
+        ("dir/sub/c.txt", Some(entry("dir/sub/c.txt", 30))),
+        ("gone/e.txt", None),
+        ("new/deep/g.txt", Some(entry("new/deep/g.txt", 9))),
+        ("never/there.txt", None),
+        // A file becomes a directory and a directory becomes a file.
+        ("x", None),
+        ("x/w.txt", Some(entry("x/w.txt", 10))),
+        ("y/z.txt", None),
+        ("y", Some(entry("y", 11))),
+    ]
+    .into_iter()
+    .map(|(path, entry)| (path.to_string(), entry))
+    .collect();
+    let updated = tree::update_tree(&repo, Some(&base), &changes).unwrap();
+
+    for (path, change) in &changes {
+        match change {
+            Some(entry) => files.insert(path.clone(), entry.clone()),
+            None => files.remove(path),
+        };
+    }
+    assert_eq!(updated, tree::write_tree(&repo, &files).unwrap());
+    assert_eq!(tree::flatten(&repo, &updated).unwrap().keys().collect::<Vec<_>>(), files.keys().collect::<Vec<_>>());
+    assert_eq!(repo.read_tree(&updated).unwrap().file_count, files.len() as u64);
+    assert_eq!(subtree(&repo, &updated, "dir").map(|d| repo.read_tree(&d).unwrap().file_count), Some(3));
+    assert_eq!(subtree(&repo, &updated, "gone"), None);
+    assert_eq!(subtree(&repo, &updated, "keep"), subtree(&repo, &base, "keep"));
+    assert_ne!(subtree(&repo, &updated, "dir"), subtree(&repo, &base, "dir"));
+
+    let removals = files.keys().map(|path| (path.clone(), None)).collect();
+    let empty = tree::update_tree(&repo, Some(&updated), &removals).unwrap();
+    assert_eq!(empty, tree::write_tree(&repo, &BTreeMap::new()).unwrap());
+    assert_eq!(repo.read_tree(&empty).unwrap().file_count, 0);
+}
+
+#[test]
+fn tree_id_matches_write_tree_without_writing() {
+    let dir = tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let files = snapshot(&[("a.txt", 1), ("dir/b.txt", 2), ("dir/sub/c.txt", 3)]);
+    let trees = repo.forge_dir.join("objects/trees");
+
+    let id = tree::tree_id(&repo, &files).unwrap();
+    assert_eq!(fs::read_dir(&trees).unwrap().count(), 0);
+    assert_eq!(tree::write_tree(&repo, &files).unwrap(), id);
+    assert_eq!(fs::read_dir(&trees).unwrap().count(), 3);
+}
+
+#[test]
+fn diff_trees_reports_changed_files_and_skips_shared_subtrees() {
+    let dir = tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let old_files = snapshot(&[("a.txt", 1), ("dir/b.txt", 2), ("dir/c.txt", 3), ("shared/d.txt", 4), ("x", 5)]);
+    let new_files = snapshot(&[("a.txt", 1), ("dir/b.txt", 20), ("dir/e.txt", 6), ("shared/d.txt", 4), ("x/f.txt", 7)]);
+    let old = tree::write_tree(&repo, &old_files).unwrap();
+    let new = tree::write_tree(&repo, &new_files).unwrap();
+
+    // Identical subtrees are compared by id alone.
+    let shared = subtree(&repo, &old, "shared").unwrap();
+    assert_eq!(subtree(&repo, &new, "shared"), Some(shared));
+    fs::remove_file(repo.tree_object_path(&shared)).unwrap();
+
+    let (removed, added) = tree::diff_trees(&repo, Some(&old), Some(&new)).unwrap();
+    assert_eq!(removed.keys().collect::<Vec<_>>(), ["dir/b.txt", "dir/c.txt", "x"]);
+    assert_eq!(added.keys().collect::<Vec<_>>(), ["dir/b.txt", "dir/e.txt", "x/f.txt"]);
+    assert_eq!(added["dir/b.txt"].file_hash, [20; 32]);
+
+    let (removed, added) = tree::diff_trees(&repo, Some(&old), Some(&old)).unwrap();
+    assert!(removed.is_empty() && added.is_empty());
+    let (removed, added) = tree::diff_trees(&repo, None, Some(&tree::write_tree(&repo, &snapshot(&[("q/r.txt", 1)])).unwrap())).unwrap();
+    assert!(removed.is_empty());
+    assert_eq!(added.keys().collect::<Vec<_>>(), ["q/r.txt"]);
+}
+
+#[test]
+fn legacy_manifests_are_migrated_on_open_not_on_parse() {
+    let dir = tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let trees = repo.forge_dir.join("objects/trees");
+    fs::remove_dir_all(&trees).unwrap();
+
+    let legacy = LegacyCommit {
+        id: [7; 32],
+        parents: Vec::new(),
+        files: vec![entry("a/b.txt", 1), entry("c.txt", 2)],
+        message: "old".to_string(),
+        author: "someone".to_string(),
+        timestamp_ns: 0,
+    };
+    let bytes = serialize_legacy_commit(&legacy).unwrap();
+    fs::write(repo.manifest_path(&legacy.id), &bytes).unwrap();
+
+    let commit = repo.parse_manifest(&bytes).unwrap();
+    assert!(!trees.exists(), "parse_manifest wrote tree objects");
+
+    let repo = Repository::discover(dir.path()).unwrap();
+    assert!(trees.is_dir());
+    assert!(!repo.forge_dir.join("objects/trees.migrating").exists());
+    assert_eq!(repo.parse_manifest(&bytes).unwrap().tree, commit.tree);
+    let files = tree::flatten(&repo, &commit.tree).unwrap();
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["a/b.txt", "c.txt"]);
+    assert_eq!(files["a/b.txt"].file_hash, [1; 32]);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use common::{commit, forge, forge_fails, noise};
+use forge::core::manifest::FileEntry;
+use forge::core::repository::Repository;
+use forge::core::worktree::{StatRecord, RACY_WINDOW_NS};
+use forge::db::metadata::MetadataDb;
+
+fn head_files(root: &Path) -> BTreeMap<String, FileEntry> {
+    let repo = Repository::discover(root).unwrap();
+    repo.read_commit_files(&repo.read_head().unwrap().unwrap()).unwrap()
+}
+
+fn chunk_count(root: &Path) -> usize {
+    Repository::discover(root).unwrap().chunk_store().unwrap().chunk_count().unwrap()
+}
+
+#[test]
+fn rm_stages_deletions() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"a"), ("b.txt", b"b"), ("dir/c.txt", b"c")], "one");
+
+    fs::write(root.join("a.txt"), b"a local").unwrap();
+    assert!(forge_fails(root, &["rm", "a.txt"]).contains("has local modifications"));
+    assert!(forge_fails(root, &["rm", "missing.txt"]).contains("did not match any tracked files"));
+
+    forge(root, &["rm", "-f", "a.txt"]);
+    forge(root, &["rm", "--cached", "b.txt"]);
+    forge(root, &["rm", "dir"]);
+    assert!(!root.join("a.txt").exists());
+    assert!(!root.join("dir").exists());
+    assert_eq!(fs::read(root.join("b.txt")).unwrap(), b"b");
+    let status = forge(root, &["status"]);
+    for line in ["- a.txt", "- b.txt", "- dir/c.txt"] {
+        assert!(status.contains(line), "{status}");
+    }
+
+    forge(root, &["commit", "-m", "two"]);
+    assert!(head_files(root).is_empty());
+    assert!(forge(root, &["status"]).contains("? untracked b.txt"));
+}
+
+#[test]
+fn mv_stages_renames_without_new_chunks() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    let texture = noise(256 * 1024, 1);
+    commit(root, &[("big.bin", &texture), ("dir/a.txt", b"a"), ("dir/b.txt", b"b"), ("c.txt", b"c")], "one");
+    let before = head_files(root);
+    let chunks = chunk_count(root);
+
+    assert!(forge_fails(root, &["mv", "big.bin", "c.txt"]).contains("already exists"));
+    assert!(forge_fails(root, &["mv", "untracked.bin", "x.bin"]).contains("not under version control"));
+    forge(root, &["mv", "big.bin", "assets/big.bin"]);
+    forge(root, &["mv", "dir", "moved"]);
+    assert!(forge(root, &["status"]).contains("R big.bin -> assets/big.bin"));
+    assert_eq!(fs::read(root.join("assets/big.bin")).unwrap(), texture);
+    assert!(!root.join("dir").exists());
+
+    forge(root, &["commit", "-m", "two"]);
+    let after = head_files(root);
+    assert_eq!(after.keys().collect::<Vec<_>>(), ["assets/big.bin", "c.txt", "moved/a.txt", "moved/b.txt"]);
+    let chunk_hashes = |entry: &FileEntry| entry.chunks.iter().map(|c| c.hash).collect::<Vec<_>>();
+    assert_eq!(chunk_hashes(&after["assets/big.bin"]), chunk_hashes(&before["big.bin"]));
+    assert_eq!(after["moved/a.txt"].file_hash, before["dir/a.txt"].file_hash);
+    assert_eq!(chunk_count(root), chunks);
+
+    forge(root, &["mv", "-f", "c.txt", "moved/a.txt"]);
+    forge(root, &["commit", "-m", "three"]);
+    let after = head_files(root);
+    assert!(!after.contains_key("c.txt"));
+    assert_eq!(after["moved/a.txt"].file_hash, before["c.txt"].file_hash);
+}
+
+fn stat_cache(root: &Path) -> BTreeMap<String, StatRecord> {
+    let db = MetadataDb::open(&Repository::discover(root).unwrap().metadata_db_path()).unwrap();
+    db.get_stat_cache()
+        .unwrap()
+        .into_iter()
+        .map(|(path, bytes)| (path, StatRecord::from_bytes(&bytes).unwrap()))
+        .collect()
+}
+
+/// Replace `path`'s contents with `contents` of the same length, keeping its mtime.
+fn rewrite_keeping_mtime(path: &Path, contents: &[u8]) {
+    let mtime = fs::metadata(path).unwrap().modified().unwrap();
+    fs::write(path, contents).unwrap();
+    fs::File::options().write(true).open(path).unwrap().set_modified(mtime).unwrap();
+}
+
+#[test]
+fn stat_records_round_trip_and_flag_the_racy_window() {
+    let record = StatRecord {
+        size: 4,
+        mtime_ns: 10_000_000_000,
+        ctime_ns: 12_000_000_000,
+        ino: 7,
+        dev: 9,
+        file_hash: [3; 32],
+    };
+    let bytes = record.to_bytes();
+    assert_eq!(StatRecord::from_bytes(&bytes), Some(record));
+    assert_eq!(StatRecord::from_bytes(&bytes[1..]), None);
+
+    // The later of mtime and ctime must be a full window before the record is taken.
+    let settled = record.ctime_ns + RACY_WINDOW_NS + 1;
+    assert!(!record.is_racy(settled));
+    assert!(record.is_racy(settled - 1));
+    assert!(StatRecord { ctime_ns: 0, ..record }.is_racy(record.mtime_ns + RACY_WINDOW_NS));
+    assert!(!StatRecord { ctime_ns: 0, ..record }.is_racy(record.mtime_ns + RACY_WINDOW_NS + 1));
+}
+
+#[test]
+fn status_trusts_stat_records_only_outside_the_racy_window() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    forge(root, &["init"]);
+    commit(root, &[("a.txt", b"aaaa"), ("b.txt", b"bbbb")], "one");
+    let a = root.join("a.txt");
+
+    // Freshly written files are never cached, so a same-size edit that keeps
+    // the mtime is still caught.
+    assert!(!forge(root, &["status"]).contains("modified"));
+    assert!(stat_cache(root).is_empty());
+    rewrite_keeping_mtime(&a, b"AAAA");
+    assert!(forge(root, &["status"]).contains("M modified a.txt"));
+    rewrite_keeping_mtime(&a, b"aaaa");
+
+    sleep(Duration::from_nanos(RACY_WINDOW_NS as u64 + 100_000_000));
+    assert!(!forge(root, &["status"]).contains("modified"));
+    let cache = stat_cache(root);
+    assert_eq!(cache.keys().collect::<Vec<_>>(), ["a.txt", "b.txt"]);
+    assert_eq!(cache["a.txt"].file_hash, *blake3::hash(b"aaaa").as_bytes());
+
+    // A matching record is trusted without rehashing the file...
+    let db = MetadataDb::open(&Repository::discover(root).unwrap().metadata_db_path()).unwrap();
+    let forged = StatRecord::new(&fs::metadata(&a).unwrap(), [0; 32]);
+    db.update_stat_cache(&[("a.txt".to_string(), forged.to_bytes())], &[]).unwrap();
+    drop(db);
+    assert!(forge(root, &["status"]).contains("M modified a.txt"));
+
+    // ...but any rewrite changes the ctime, so the record no longer matches.
+    rewrite_keeping_mtime(&a, b"aaaa");
+    assert!(!forge(root, &["status"]).contains("modified"));
+    rewrite_keeping_mtime(&a, b"AAAA");
+    assert!(forge(root, &["status"]).contains("M modified a.txt"));
+}